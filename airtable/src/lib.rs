@@ -7,45 +7,67 @@
  *
  * ```
  * use airtable_api::Airtable;
+ * use serde::Deserialize;
+ *
+ * #[derive(Debug, Deserialize)]
+ * struct Fields {
+ *     #[serde(rename = "Name")]
+ *     name: String,
+ * }
  *
  * async fn get_records() {
  *     // Initialize the Airtable client.
  *     let airtable = Airtable::new_from_env();
  *
  *     // Get the current records from a table.
- *     let mut records = airtable
- *         .list_records(
- *             "Table Name",
- *             "Grid view",
- *             vec!["the", "fields", "you", "want", "to", "return"],
- *         )
+ *     let records = airtable
+ *         .list_records::<Fields>("Table Name", "Grid view", vec!["Name"])
  *         .await
  *         .unwrap();
  *
- *     // Iterate over the records.
- *     for (i, record) in records.clone().iter().enumerate() {
- *         println!("{} - {:?}", i, record);
+ *     // Iterate over the records, skipping the ones that don't fit.
+ *     for (i, record) in records.iter().enumerate() {
+ *         match record {
+ *             Ok(r) => println!("{} - {}", i, r.fields.name),
+ *             Err(e) => println!("{} - {}", i, e),
+ *         }
  *     }
  * }
  * ```
  */
+use std::cmp;
+use std::collections::HashMap;
 use std::env;
 use std::error;
 use std::fmt;
-use std::sync::Arc;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::{self, Instant};
 
-use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use lazy_static::lazy_static;
+use reqwest::{header, Client, Method, Request, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
 /// Endpoint for the Airtable API.
 const ENDPOINT: &str = "https://api.airtable.com/v0/";
 
+/// The Airtable API only takes this many records in a single create or
+/// update request.
+pub const MAX_RECORDS_PER_REQUEST: usize = 10;
+
+/// The Airtable API allows this many requests per second to each base.
+pub const MAX_REQUESTS_PER_SECOND: u32 = 5;
+
 /// Entrypoint for interacting with the Airtable API.
 pub struct Airtable {
     key: String,
     base_id: String,
+    endpoint: String,
 
     client: Arc<Client>,
+    rate_limiter: Arc<RateLimiter>,
+    retry_policy: RetryPolicy,
 }
 
 impl Airtable {
@@ -62,8 +84,11 @@ impl Airtable {
             Ok(c) => Self {
                 key: key.to_string(),
                 base_id: base_id.to_string(),
+                endpoint: ENDPOINT.to_string(),
 
                 client: Arc::new(c),
+                rate_limiter: rate_limiter(&base_id.to_string()),
+                retry_policy: RetryPolicy::default(),
             },
             Err(e) => panic!("creating client failed: {:?}", e),
         }
@@ -80,28 +105,64 @@ impl Airtable {
         Airtable::new(key, base_id)
     }
 
+    /// Set how requests that fail with a 429 or a 5xx are retried.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Send requests to a different endpoint than the Airtable API, for
+    /// example a proxy. The endpoint must end with a `/`.
+    pub fn with_endpoint<E>(mut self, endpoint: E) -> Self
+    where
+        E: ToString,
+    {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
     /// Get the currently set API key.
     pub fn get_key(&self) -> &str {
         &self.key
     }
 
+    /// Build a request for a path in our base.
     fn request<B>(
         &self,
         method: Method,
         path: String,
         body: B,
-        query: Option<Vec<(&str, String)>>,
-    ) -> Request
+        query: Option<Vec<(String, String)>>,
+    ) -> Result<Request, Error>
     where
         B: Serialize,
     {
-        let base = Url::parse(ENDPOINT).unwrap();
-        let url = base
-            .join(&(self.base_id.to_string() + "/" + &path))
-            .unwrap();
+        self.request_url(
+            method,
+            self.base_id.to_string() + "/" + &path,
+            body,
+            query,
+        )
+    }
+
+    /// Build a request for a path relative to the endpoint.
+    fn request_url<B>(
+        &self,
+        method: Method,
+        path: String,
+        body: B,
+        query: Option<Vec<(String, String)>>,
+    ) -> Result<Request, Error>
+    where
+        B: Serialize,
+    {
+        let url = Url::parse(&self.endpoint)
+            .and_then(|base| base.join(&path))
+            .map_err(|e| Error::Request(format!("invalid url: {}", e)))?;
 
         let bt = format!("Bearer {}", self.key);
-        let bearer = header::HeaderValue::from_str(&bt).unwrap();
+        let bearer = header::HeaderValue::from_str(&bt)
+            .map_err(|e| Error::Request(format!("invalid api key: {}", e)))?;
 
         // Set the default headers.
         let mut headers = header::HeaderMap::new();
@@ -126,100 +187,433 @@ impl Airtable {
         }
 
         // Build the request.
-        rb.build().unwrap()
+        Ok(rb.build()?)
+    }
+
+    /// Execute a request, waiting first if we would go over the rate limit.
+    ///
+    /// Requests that fail with a 429 or a 5xx, or that time out or fail to
+    /// connect, are retried with exponential backoff according to our retry
+    /// policy. If Airtable tells us how long to wait with `Retry-After`, we
+    /// wait that long instead. Any other status is returned as an error.
+    ///
+    /// A POST creates records, so if it timed out or got a 5xx it may have
+    /// gone through and retrying it could create them twice. Those are only
+    /// retried on a 429 or when we could not connect, since then Airtable
+    /// never took the request.
+    async fn execute(&self, request: Request) -> Result<Response, Error> {
+        let idempotent = request.method() != Method::POST;
+        let mut retries = 0;
+
+        loop {
+            // Our bodies are always in memory, so this always works.
+            let req = request.try_clone().ok_or_else(|| {
+                Error::Request("request cannot be retried".to_string())
+            })?;
+
+            self.rate_limiter.wait().await;
+
+            let can_retry = retries < self.retry_policy.max_retries;
+            let mut wait = self.retry_policy.backoff(retries);
+            match self.client.execute(req).await {
+                Ok(resp) if resp.status().is_success() => return Ok(resp),
+                Ok(resp) => {
+                    let status_code = resp.status();
+                    let retry_after = retry_after(&resp);
+                    let retryable = status_code
+                        == StatusCode::TOO_MANY_REQUESTS
+                        || (idempotent && status_code.is_server_error());
+
+                    if !(retryable && can_retry) {
+                        let body = resp.text().await?;
+                        if status_code == StatusCode::TOO_MANY_REQUESTS {
+                            return Err(Error::RateLimited {
+                                retry_after,
+                                body,
+                            });
+                        }
+                        return Err(Error::Status { status_code, body });
+                    }
+
+                    if let Some(retry_after) = retry_after {
+                        wait = cmp::min(
+                            retry_after,
+                            self.retry_policy.max_backoff,
+                        );
+                    }
+                }
+                Err(e) => {
+                    let retryable =
+                        e.is_connect() || (idempotent && e.is_timeout());
+                    if !(retryable && can_retry) {
+                        return Err(Error::Transport(e));
+                    }
+                }
+            }
+
+            tokio::time::delay_for(wait).await;
+            retries += 1;
+        }
     }
 
     /// List records in a table for a particular view.
-    pub async fn list_records(
+    ///
+    /// The fields of each record are deserialized into `T`. A record that
+    /// does not fit `T` does not fail the whole list, it comes back as an
+    /// error for that record.
+    pub async fn list_records<T: DeserializeOwned>(
         &self,
         table: &str,
         view: &str,
         fields: Vec<&str>,
-    ) -> Result<Vec<Record>, APIError> {
-        let mut params =
-            vec![("pageSize", "100".to_string()), ("view", view.to_string())];
-        for field in fields {
-            params.push(("fields", field.to_string()));
+    ) -> Result<Vec<Result<Record<T>, RecordError>>, Error> {
+        self.list_records_with_options(
+            table,
+            &ListRecordsOptions {
+                view: Some(view.to_string()),
+                fields: fields.iter().map(|f| f.to_string()).collect(),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// List the records in a table that match the options, following the
+    /// pages of results until we have all of them.
+    ///
+    /// The fields of each record are deserialized into `T`. A record that
+    /// does not fit `T` does not fail the whole list, it comes back as an
+    /// error for that record.
+    pub async fn list_records_with_options<T: DeserializeOwned>(
+        &self,
+        table: &str,
+        options: &ListRecordsOptions,
+    ) -> Result<Vec<Result<Record<T>, RecordError>>, Error> {
+        Ok(self
+            .list_raw_records(table, options)
+            .await?
+            .into_iter()
+            .map(Record::decode)
+            .collect())
+    }
+
+    async fn list_raw_records(
+        &self,
+        table: &str,
+        options: &ListRecordsOptions,
+    ) -> Result<Vec<Record>, Error> {
+        let mut records: Vec<Record> = Default::default();
+        let mut offset: Option<String> = None;
+
+        loop {
+            let mut params = options.to_query();
+            if let Some(o) = offset {
+                params.push(("offset".to_string(), o));
+            }
+
+            // Build the request.
+            let request =
+                self.request(Method::GET, table.to_string(), (), Some(params))?;
+
+            let resp = self.execute(request).await?;
+
+            // Try to deserialize the response.
+            let mut r: APICall<serde_json::Value> = decode(resp).await?;
+            records.append(&mut r.records);
+
+            // Paginate if we should.
+            offset = r.offset;
+            if offset.is_none() {
+                break;
+            }
         }
 
+        Ok(records)
+    }
+
+    /// Get a single record from a table by its id.
+    pub async fn get_record<T: DeserializeOwned>(
+        &self,
+        table: &str,
+        id: &str,
+    ) -> Result<Record<T>, Error> {
         // Build the request.
-        let mut request =
-            self.request(Method::GET, table.to_string(), (), Some(params));
-
-        let mut resp = self.client.execute(request).await.unwrap();
-        match resp.status() {
-            StatusCode::OK => (),
-            s => {
-                return Err(APIError {
-                    status_code: s,
-                    body: resp.text().await.unwrap(),
-                })
-            }
-        };
+        let request =
+            self.request(Method::GET, format!("{}/{}", table, id), (), None)?;
+
+        let resp = self.execute(request).await?;
 
         // Try to deserialize the response.
-        let mut r: APICall = resp.json().await.unwrap();
+        decode(resp).await
+    }
 
-        let mut records = r.records;
+    /// List the bases the API key has access to.
+    pub async fn list_bases(&self) -> Result<Vec<Base>, Error> {
+        let mut bases: Vec<Base> = Default::default();
+        let mut offset: Option<String> = None;
 
-        let mut offset = if let Some(o) = r.offset {
-            o
-        } else {
-            "".to_string()
-        };
+        loop {
+            let params = offset
+                .map(|o| vec![("offset".to_string(), o)])
+                .unwrap_or_default();
 
-        // Paginate if we should.
-        // TODO: make this more DRY
-        while !offset.is_empty() {
-            request = self.request(
+            // Build the request.
+            let request = self.request_url(
                 Method::GET,
-                table.to_string(),
+                "meta/bases".to_string(),
                 (),
-                Some(vec![
-                    ("pageSize", "100".to_string()),
-                    ("view", view.to_string()),
-                    ("offset", offset),
-                ]),
-            );
+                Some(params),
+            )?;
 
-            resp = self.client.execute(request).await.unwrap();
-            match resp.status() {
-                StatusCode::OK => (),
-                s => {
-                    return Err(APIError {
-                        status_code: s,
-                        body: resp.text().await.unwrap(),
-                    })
-                }
-            };
+            let resp = self.execute(request).await?;
 
             // Try to deserialize the response.
-            r = resp.json().await.unwrap();
+            let mut r: BasesResponse = decode(resp).await?;
+            bases.append(&mut r.bases);
 
-            records.append(&mut r.records);
+            // Paginate if we should.
+            offset = r.offset;
+            if offset.is_none() {
+                break;
+            }
+        }
 
-            offset = if let Some(o) = r.offset {
-                o
-            } else {
-                "".to_string()
-            };
+        Ok(bases)
+    }
+
+    /// Get the tables in our base, with their fields and views.
+    pub async fn get_tables(&self) -> Result<Vec<Table>, Error> {
+        // Build the request.
+        let request = self.request_url(
+            Method::GET,
+            format!("meta/bases/{}/tables", self.base_id),
+            (),
+            None,
+        )?;
+
+        let resp = self.execute(request).await?;
+
+        // Try to deserialize the response.
+        let r: TablesResponse = decode(resp).await?;
+
+        Ok(r.tables)
+    }
+
+    /// Get a table in our base by its name or id.
+    pub async fn get_table(&self, table: &str) -> Result<Option<Table>, Error> {
+        Ok(self
+            .get_tables()
+            .await?
+            .into_iter()
+            .find(|t| t.name == table || t.id == table))
+    }
+
+    /// Get the records a linked record field points to, in the same order
+    /// as the links. Records that no longer exist are left out.
+    ///
+    /// Like `list_records`, a record that does not fit `T` comes back as an
+    /// error for that record.
+    pub async fn get_linked_records<T: DeserializeOwned>(
+        &self,
+        table: &str,
+        links: &LinkedRecords,
+    ) -> Result<Vec<Result<Record<T>, RecordError>>, Error> {
+        let mut found: HashMap<String, Record> = Default::default();
+        // Look the records up in chunks so the formula, which ends up in the
+        // url, does not get too long.
+        for chunk in links.ids().chunks(MAX_LINKED_RECORDS_PER_REQUEST) {
+            let formula = format!(
+                "OR({})",
+                chunk
+                    .iter()
+                    .filter_map(|id| formula_value(&serde_json::json!(id)))
+                    .map(|id| format!("RECORD_ID()={}", id))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            );
+            let records = self
+                .list_raw_records(
+                    table,
+                    &ListRecordsOptions {
+                        filter_by_formula: Some(formula),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            for record in records {
+                if let Some(id) = record.id.clone() {
+                    found.insert(id, record);
+                }
+            }
         }
 
-        Ok(records)
+        Ok(links
+            .ids()
+            .iter()
+            .filter_map(|id| found.remove(id))
+            .map(Record::decode)
+            .collect())
     }
 
     /// Bulk create records in a table.
     ///
-    /// Due to limitations on the Airtable API, you can only bulk create 10
-    /// records at a time.
-    pub async fn create_records(
+    /// The Airtable API only takes 10 records per request, so the records
+    /// are sent in chunks of 10 and the created records are returned in the
+    /// same order. If a chunk fails, the records in the chunks before it have
+    /// already been created and nothing after it is sent.
+    pub async fn create_records<T: Serialize + DeserializeOwned>(
+        &self,
+        table: &str,
+        records: Vec<Record<T>>,
+    ) -> Result<Vec<Record<T>>, BatchError<Record<T>>> {
+        self.send_batches(Method::POST, table, records).await
+    }
+
+    /// Bulk update records in a table.
+    ///
+    /// The Airtable API only takes 10 records per request, so the records
+    /// are sent in chunks of 10 and the updated records are returned in the
+    /// same order. If a chunk fails, the records in the chunks before it have
+    /// already been updated and nothing after it is sent.
+    pub async fn update_records<T: Serialize + DeserializeOwned>(
+        &self,
+        table: &str,
+        records: Vec<Record<T>>,
+    ) -> Result<Vec<Record<T>>, BatchError<Record<T>>> {
+        self.send_batches(Method::PATCH, table, records).await
+    }
+
+    /// Bulk delete records in a table by their ids.
+    ///
+    /// The Airtable API only takes 10 records per request, so the records
+    /// are deleted in chunks of 10 and the ids of the deleted records are
+    /// returned. If a chunk fails, the records in the chunks before it have
+    /// already been deleted and nothing after it is sent.
+    pub async fn delete_records(
+        &self,
+        table: &str,
+        ids: Vec<String>,
+    ) -> Result<Vec<String>, BatchError<String>> {
+        let mut completed: Vec<String> = Vec::with_capacity(ids.len());
+
+        for (chunk, batch) in ids.chunks(MAX_RECORDS_PER_REQUEST).enumerate() {
+            let start = chunk * MAX_RECORDS_PER_REQUEST;
+
+            match self.delete_batch(table, batch).await {
+                Ok(mut r) => completed.append(&mut r),
+                Err(error) => {
+                    return Err(BatchError {
+                        chunk,
+                        records: start..start + batch.len(),
+                        completed,
+                        error,
+                    })
+                }
+            }
+        }
+
+        Ok(completed)
+    }
+
+    /// Create or update records in a table, matching the records we already
+    /// have on the value of `field`. Records with a value for `field` that
+    /// is not in the table yet are created, the rest are updated. Records
+    /// without a value for `field` are always created, and records with the
+    /// same new value are created once, with the fields of the last of them.
+    ///
+    /// This looks up the existing records 10 at a time with a formula, so it
+    /// does not need to list the whole table. The returned records are in the
+    /// same order as the ones passed in. If a chunk fails, the records in the
+    /// chunks before it have been written and nothing after it is sent. The
+    /// updates in the failed chunk may have been written before the creates
+    /// failed, those are in `completed` too.
+    pub async fn upsert_records<T: Serialize + DeserializeOwned + Clone>(
+        &self,
+        table: &str,
+        field: &str,
+        records: Vec<Record<T>>,
+    ) -> Result<Vec<Record<T>>, BatchError<Record<T>>> {
+        let mut completed: Vec<Record<T>> = Vec::with_capacity(records.len());
+
+        let mut records = records.into_iter().peekable();
+        let mut chunk = 0;
+        while records.peek().is_some() {
+            let batch: Vec<Record<T>> =
+                records.by_ref().take(MAX_RECORDS_PER_REQUEST).collect();
+            let start = chunk * MAX_RECORDS_PER_REQUEST;
+            let end = start + batch.len();
+
+            match self.upsert_batch(table, field, batch).await {
+                Ok(mut r) => completed.append(&mut r),
+                Err((mut r, error)) => {
+                    completed.append(&mut r);
+                    return Err(BatchError {
+                        chunk,
+                        records: start..end,
+                        completed,
+                        error,
+                    });
+                }
+            }
+
+            chunk += 1;
+        }
+
+        Ok(completed)
+    }
+
+    async fn send_batches<T: Serialize + DeserializeOwned>(
         &self,
+        method: Method,
+        table: &str,
+        records: Vec<Record<T>>,
+    ) -> Result<Vec<Record<T>>, BatchError<Record<T>>> {
+        let mut completed: Vec<Record<T>> = Vec::with_capacity(records.len());
+
+        let mut records = records.into_iter().peekable();
+        let mut chunk = 0;
+        while records.peek().is_some() {
+            let batch: Vec<Record<T>> =
+                records.by_ref().take(MAX_RECORDS_PER_REQUEST).collect();
+            let start = chunk * MAX_RECORDS_PER_REQUEST;
+            let end = start + batch.len();
+
+            match self.send_batch(method.clone(), table, batch).await {
+                Ok(mut r) => completed.append(&mut r),
+                Err(error) => {
+                    return Err(BatchError {
+                        chunk,
+                        records: start..end,
+                        completed,
+                        error,
+                    })
+                }
+            }
+
+            chunk += 1;
+        }
+
+        Ok(completed)
+    }
+
+    async fn send_batch<T: Serialize + DeserializeOwned>(
+        &self,
+        method: Method,
         table: &str,
-        records: Vec<Record>,
-    ) -> Result<Vec<Record>, APIError> {
+        records: Vec<Record<T>>,
+    ) -> Result<Vec<Record<T>>, Error> {
+        // Airtable only takes the id and the fields when writing records.
+        let records: Vec<Record<T>> = records
+            .into_iter()
+            .map(|mut r| {
+                r.created_time = None;
+                r
+            })
+            .collect();
+
         // Build the request.
         let request = self.request(
-            Method::POST,
+            method,
             table.to_string(),
             APICall {
                 records,
@@ -227,109 +621,554 @@ impl Airtable {
                 typecast: Some(true),
             },
             None,
-        );
+        )?;
 
-        let resp = self.client.execute(request).await.unwrap();
-        match resp.status() {
-            StatusCode::OK => (),
-            s => {
-                return Err(APIError {
-                    status_code: s,
-                    body: resp.text().await.unwrap(),
-                })
-            }
-        };
+        let resp = self.execute(request).await?;
 
         // Try to deserialize the response.
-        let r: APICall = resp.json().await.unwrap();
+        let r: APICall<T> = decode(resp).await?;
 
         Ok(r.records)
     }
 
-    /// Bulk update records in a table.
-    ///
-    /// Due to limitations on the Airtable API, you can only bulk update 10
-    /// records at a time.
-    pub async fn update_records(
+    async fn delete_batch(
         &self,
         table: &str,
-        records: Vec<Record>,
-    ) -> Result<Vec<Record>, APIError> {
+        ids: &[String],
+    ) -> Result<Vec<String>, Error> {
+        let params = ids
+            .iter()
+            .map(|id| ("records[]".to_string(), id.to_string()))
+            .collect();
+
         // Build the request.
-        let request = self.request(
-            Method::PATCH,
-            table.to_string(),
-            APICall {
-                records,
-                offset: None,
-                typecast: Some(true),
+        let request =
+            self.request(Method::DELETE, table.to_string(), (), Some(params))?;
+
+        let resp = self.execute(request).await?;
+
+        // Try to deserialize the response.
+        let r: DeletedRecords = decode(resp).await?;
+
+        Ok(r.records
+            .into_iter()
+            .filter(|r| r.deleted)
+            .map(|r| r.id)
+            .collect())
+    }
+
+    /// Send a batch of records, keeping track of where each record was in
+    /// the records passed in.
+    async fn send_indexed_batch<T: Serialize + DeserializeOwned>(
+        &self,
+        method: Method,
+        table: &str,
+        records: Vec<(usize, Record<T>)>,
+    ) -> Result<Vec<(usize, Record<T>)>, Error> {
+        if records.is_empty() {
+            return Ok(Default::default());
+        }
+
+        let (indexes, records): (Vec<usize>, Vec<Record<T>>) =
+            records.into_iter().unzip();
+        let r = self.send_batch(method, table, records).await?;
+
+        Ok(indexes.into_iter().zip(r).collect())
+    }
+
+    /// Upsert a batch of records. If writing fails, the records that were
+    /// written before the error are returned with it.
+    async fn upsert_batch<T: Serialize + DeserializeOwned + Clone>(
+        &self,
+        table: &str,
+        field: &str,
+        records: Vec<Record<T>>,
+    ) -> Result<Vec<Record<T>>, (Vec<Record<T>>, Error)> {
+        // The values of the field we match on, for each record.
+        let keys: Vec<serde_json::Value> = records
+            .iter()
+            .map(|r| match serde_json::to_value(&r.fields) {
+                Ok(fields) => fields[field].clone(),
+                Err(_) => serde_json::Value::Null,
+            })
+            .collect();
+
+        // Find the records we already have with the same values.
+        let values: Vec<String> =
+            keys.iter().filter_map(formula_value).collect();
+        let mut existing: Vec<Record> = Default::default();
+        if !values.is_empty() {
+            let formula = format!(
+                "OR({})",
+                values
+                    .iter()
+                    .map(|v| format!("{}={}", formula_field(field), v))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            );
+            existing = self
+                .list_raw_records(
+                    table,
+                    &ListRecordsOptions {
+                        fields: vec![field.to_string()],
+                        filter_by_formula: Some(formula),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .map_err(|e| (Default::default(), e))?;
+        }
+
+        let mut creates: Vec<(usize, Record<T>)> = Default::default();
+        let mut create_keys: Vec<serde_json::Value> = Default::default();
+        let mut updates: Vec<(usize, Record<T>)> = Default::default();
+        // The records with the same new key as one we already create, with
+        // the index of that one.
+        let mut repeats: Vec<(usize, usize)> = Default::default();
+        for (i, (mut record, key)) in records.into_iter().zip(keys).enumerate()
+        {
+            if key.is_null() {
+                creates.push((i, record));
+                create_keys.push(key);
+                continue;
+            }
+
+            if let Some(e) = existing.iter().find(|e| e.fields[field] == key) {
+                record.id = e.id.clone();
+                updates.push((i, record));
+                continue;
+            }
+
+            match create_keys.iter().position(|k| *k == key) {
+                Some(c) => {
+                    // The last one wins, like it would if the records were
+                    // upserted one after the other.
+                    creates[c].1 = record;
+                    repeats.push((i, creates[c].0));
+                }
+                None => {
+                    creates.push((i, record));
+                    create_keys.push(key);
+                }
+            }
+        }
+
+        let mut upserted = self
+            .send_indexed_batch(Method::PATCH, table, updates)
+            .await
+            .map_err(|e| (Default::default(), e))?;
+        let created =
+            match self.send_indexed_batch(Method::POST, table, creates).await {
+                Ok(created) => created,
+                Err(e) => return Err((in_order(upserted), e)),
+            };
+        for (i, first) in repeats {
+            if let Some((_, r)) = created.iter().find(|(c, _)| *c == first) {
+                upserted.push((i, r.clone()));
+            }
+        }
+        upserted.extend(created);
+
+        Ok(in_order(upserted))
+    }
+
+    /// Create a webhook on our base. Airtable pings the notification url
+    /// when the data the webhook watches changes, the changes themselves are
+    /// fetched with `list_webhook_payloads`.
+    ///
+    /// The secret in the response is only returned here, it is needed to
+    /// verify the pings are from Airtable.
+    pub async fn create_webhook(
+        &self,
+        notification_url: &str,
+        specification: WebhookSpecification,
+    ) -> Result<NewWebhook, Error> {
+        // Build the request.
+        let request = self.request_url(
+            Method::POST,
+            format!("bases/{}/webhooks", self.base_id),
+            CreateWebhookRequest {
+                notification_url: notification_url.to_string(),
+                specification,
             },
             None,
-        );
+        )?;
 
-        let resp = self.client.execute(request).await.unwrap();
-        match resp.status() {
-            StatusCode::OK => (),
-            s => {
-                return Err(APIError {
-                    status_code: s,
-                    body: resp.text().await.unwrap(),
-                })
-            }
-        };
+        let resp = self.execute(request).await?;
 
         // Try to deserialize the response.
-        let r: APICall = resp.json().await.unwrap();
+        decode(resp).await
+    }
 
-        Ok(r.records)
+    /// List the webhooks on our base.
+    pub async fn list_webhooks(&self) -> Result<Vec<Webhook>, Error> {
+        // Build the request.
+        let request = self.request_url(
+            Method::GET,
+            format!("bases/{}/webhooks", self.base_id),
+            (),
+            None,
+        )?;
+
+        let resp = self.execute(request).await?;
+
+        // Try to deserialize the response.
+        let r: WebhooksResponse = decode(resp).await?;
+
+        Ok(r.webhooks)
+    }
+
+    /// Extend the life of a webhook, they expire after 7 days otherwise.
+    /// Returns the new expiration time.
+    pub async fn refresh_webhook(
+        &self,
+        id: &str,
+    ) -> Result<Option<String>, Error> {
+        // Build the request.
+        let request = self.request_url(
+            Method::POST,
+            format!("bases/{}/webhooks/{}/refresh", self.base_id, id),
+            (),
+            None,
+        )?;
+
+        let resp = self.execute(request).await?;
+
+        // Try to deserialize the response.
+        let r: RefreshWebhookResponse = decode(resp).await?;
+
+        Ok(r.expiration_time)
+    }
+
+    /// Delete a webhook from our base.
+    pub async fn delete_webhook(&self, id: &str) -> Result<(), Error> {
+        // Build the request.
+        let request = self.request_url(
+            Method::DELETE,
+            format!("bases/{}/webhooks/{}", self.base_id, id),
+            (),
+            None,
+        )?;
+
+        self.execute(request).await?;
+
+        Ok(())
+    }
+
+    /// Get the changes a webhook has seen, starting at `cursor`. The first
+    /// payload of a webhook is at cursor 1.
+    ///
+    /// This pages through all the payloads there are. The returned cursor is
+    /// where to start the next time.
+    pub async fn list_webhook_payloads(
+        &self,
+        id: &str,
+        cursor: u64,
+    ) -> Result<WebhookPayloads, Error> {
+        let mut payloads = WebhookPayloads {
+            payloads: Default::default(),
+            cursor,
+        };
+
+        loop {
+            // Build the request.
+            let request = self.request_url(
+                Method::GET,
+                format!("bases/{}/webhooks/{}/payloads", self.base_id, id),
+                (),
+                Some(vec![("cursor".to_string(), payloads.cursor.to_string())]),
+            )?;
+
+            let resp = self.execute(request).await?;
+
+            // Try to deserialize the response.
+            let mut r: WebhookPayloadsResponse = decode(resp).await?;
+            payloads.payloads.append(&mut r.payloads);
+            payloads.cursor = r.cursor;
+
+            // Paginate if we should.
+            if !r.might_have_more {
+                break;
+            }
+        }
+
+        Ok(payloads)
     }
 }
 
-/// Error type returned by our library.
-pub struct APIError {
-    pub status_code: StatusCode,
-    pub body: String,
+/// Deserialize the body of a response.
+async fn decode<T: DeserializeOwned>(resp: Response) -> Result<T, Error> {
+    let body = resp.bytes().await?;
+
+    Ok(serde_json::from_slice(&body)?)
 }
 
-impl fmt::Display for APIError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "APIError: status code -> {}, body -> {}",
-            self.status_code.to_string(),
-            self.body
-        )
-    }
+/// How long Airtable asked us to wait before retrying, from the
+/// `Retry-After` header. We only understand the number of seconds.
+fn retry_after(resp: &Response) -> Option<time::Duration> {
+    resp.headers()
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(time::Duration::from_secs)
 }
 
-impl fmt::Debug for APIError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "APIError: status code -> {}, body -> {}",
-            self.status_code.to_string(),
-            self.body
-        )
-    }
+/// How we retry requests that fail with a 429 or a 5xx, or that time out or
+/// fail to connect.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// How many times to retry a request before giving up. Zero turns off
+    /// retries.
+    pub max_retries: u32,
+    /// How long to wait before the first retry. This doubles with every
+    /// retry after that.
+    pub initial_backoff: time::Duration,
+    /// The longest we wait before a retry, even if Airtable asks us to wait
+    /// longer.
+    pub max_backoff: time::Duration,
 }
 
-// This is important for other errors to wrap this one.
-impl error::Error for APIError {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        // Generic error, underlying cause isn't tracked.
-        None
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        // Airtable asks us to wait 30 seconds after we hit the rate limit,
+        // so we keep retrying for a bit longer than that.
+        RetryPolicy {
+            max_retries: 5,
+            initial_backoff: time::Duration::from_secs(1),
+            max_backoff: time::Duration::from_secs(30),
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct APICall {
-    /// If there are more records, the response will contain an
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// How long to wait before the given retry, counting from zero.
+    pub fn backoff(&self, retry: u32) -> time::Duration {
+        self.initial_backoff
+            .checked_mul(1 << cmp::min(retry, 16))
+            .map_or(self.max_backoff, |b| cmp::min(b, self.max_backoff))
+    }
+}
+
+/// The most linked records we look up in a single request.
+const MAX_LINKED_RECORDS_PER_REQUEST: usize = 50;
+
+/// Format a field name as a reference in an Airtable formula.
+fn formula_field(field: &str) -> String {
+    format!("{{{}}}", field.replace('\\', "\\\\").replace('}', "\\}"))
+}
+
+/// Put records that were sent keyed by their index back in order.
+fn in_order<T>(mut records: Vec<(usize, T)>) -> Vec<T> {
+    records.sort_by_key(|(i, _)| *i);
+    records.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Format a field value as a literal in an Airtable formula. We can only
+/// match on strings, numbers and booleans.
+fn formula_value(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(format!(
+            "'{}'",
+            s.replace('\\', "\\\\").replace('\'', "\\'")
+        )),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(true) => Some("TRUE()".to_string()),
+        serde_json::Value::Bool(false) => Some("FALSE()".to_string()),
+        _ => None,
+    }
+}
+
+lazy_static! {
+    /// The rate limiter for each base. Airtable limits requests per base, so
+    /// every client for a base shares the same limiter.
+    static ref RATE_LIMITERS: Mutex<HashMap<String, Arc<RateLimiter>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Get the rate limiter for a base.
+fn rate_limiter(base_id: &str) -> Arc<RateLimiter> {
+    let mut limiters = RATE_LIMITERS.lock().unwrap();
+    let limiter = limiters
+        .entry(base_id.to_string())
+        .or_insert_with(|| Arc::new(RateLimiter::new(MAX_REQUESTS_PER_SECOND)));
+
+    Arc::clone(limiter)
+}
+
+/// Spaces out requests so we stay under Airtable's rate limit. It is shared
+/// by all the clients for the same base.
+struct RateLimiter {
+    interval: time::Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_second: u32) -> Self {
+        RateLimiter {
+            interval: time::Duration::from_secs(1) / requests_per_second,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait until we are allowed to send the next request.
+    async fn wait(&self) {
+        let wait = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let start = cmp::max(*next, now);
+            *next = start + self.interval;
+            start - now
+        };
+
+        if wait > time::Duration::from_secs(0) {
+            tokio::time::delay_for(wait).await;
+        }
+    }
+}
+
+/// Error type returned by our library.
+pub enum Error {
+    /// The request could not be built, for example because the table name
+    /// does not make a valid URL.
+    Request(String),
+    /// The request could not be sent or the response could not be read.
+    Transport(reqwest::Error),
+    /// Airtable responded with an error.
+    Status {
+        status_code: StatusCode,
+        body: String,
+    },
+    /// Airtable was still rate limiting us after all our retries.
+    RateLimited {
+        retry_after: Option<time::Duration>,
+        body: String,
+    },
+    /// The response could not be deserialized.
+    Decode(serde_json::Error),
+}
+
+impl Error {
+    /// The status code Airtable responded with, if it responded.
+    pub fn status_code(&self) -> Option<StatusCode> {
+        match self {
+            Error::Status { status_code, .. } => Some(*status_code),
+            Error::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Request(e) => write!(f, "Error: request -> {}", e),
+            Error::Transport(e) => write!(f, "Error: transport -> {}", e),
+            Error::Status { status_code, body } => write!(
+                f,
+                "Error: status code -> {}, body -> {}",
+                status_code, body
+            ),
+            Error::RateLimited { retry_after, body } => write!(
+                f,
+                "Error: rate limited -> retry after {}s, body -> {}",
+                retry_after.map(|d| d.as_secs()).unwrap_or_default(),
+                body
+            ),
+            Error::Decode(e) => write!(f, "Error: decode -> {}", e),
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// This is important for other errors to wrap this one.
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Transport(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Transport(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Decode(e)
+    }
+}
+
+/// Error type returned when sending records in chunks fails part of the
+/// way through.
+pub struct BatchError<T = Record> {
+    /// The index of the chunk that failed.
+    pub chunk: usize,
+    /// The indexes of the records in the chunk that failed. The records
+    /// before these succeeded and the records after them were not sent.
+    pub records: Range<usize>,
+    /// The results for the chunks that succeeded. For upserts, this also has
+    /// the records of the failed chunk that were updated before it failed.
+    pub completed: Vec<T>,
+    /// The error for the chunk that failed.
+    pub error: Error,
+}
+
+impl<T> fmt::Display for BatchError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "BatchError: chunk -> {}, records -> {}..{}, completed -> {}, error -> {}",
+            self.chunk,
+            self.records.start,
+            self.records.end,
+            self.completed.len(),
+            self.error
+        )
+    }
+}
+
+impl<T> fmt::Debug for BatchError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl<T> error::Error for BatchError<T> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct APICall<T> {
+    /// If there are more records, the response will contain an
     /// offset. To fetch the next page of records, include offset
     /// in the next request's parameters.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub offset: Option<String>,
     /// The current page number of returned records.
-    pub records: Vec<Record>,
+    pub records: Vec<Record<T>>,
     /// The Airtable API will perform best-effort automatic data conversion
     /// from string values if the typecast parameter is passed in. Automatic
     /// conversion is disabled by default to ensure data integrity, but it may
@@ -338,20 +1177,1490 @@ struct APICall {
     pub typecast: Option<bool>,
 }
 
-/// An Airtable record.
+/// The options for listing records in a table.
+///
+/// Docs: https://airtable.com/api, under "List records".
+#[derive(Debug, Default, Clone)]
+pub struct ListRecordsOptions {
+    /// Only return the records in this view, sorted the way the view is.
+    pub view: Option<String>,
+    /// Only return these fields. If empty, all the fields are returned.
+    pub fields: Vec<String>,
+    /// Only return the records this formula evaluates to true for, for
+    /// example `{Name}='Jess'`.
+    pub filter_by_formula: Option<String>,
+    /// How to sort the records. This takes precedence over the view.
+    pub sort: Vec<Sort>,
+    /// The maximum number of records to return in total.
+    pub max_records: Option<usize>,
+    /// How cell values are formatted, `json` by default.
+    pub cell_format: Option<CellFormat>,
+    /// The time zone to format dates in. Required with `CellFormat::String`.
+    pub time_zone: Option<String>,
+    /// The locale to format dates in. Required with `CellFormat::String`.
+    pub user_locale: Option<String>,
+}
+
+impl ListRecordsOptions {
+    fn to_query(&self) -> Vec<(String, String)> {
+        let mut params = vec![("pageSize".to_string(), "100".to_string())];
+
+        if let Some(view) = &self.view {
+            params.push(("view".to_string(), view.to_string()));
+        }
+        for field in &self.fields {
+            params.push(("fields[]".to_string(), field.to_string()));
+        }
+        if let Some(formula) = &self.filter_by_formula {
+            params.push(("filterByFormula".to_string(), formula.to_string()));
+        }
+        for (i, sort) in self.sort.iter().enumerate() {
+            params
+                .push((format!("sort[{}][field]", i), sort.field.to_string()));
+            params.push((
+                format!("sort[{}][direction]", i),
+                sort.direction.to_string(),
+            ));
+        }
+        if let Some(max_records) = self.max_records {
+            params.push(("maxRecords".to_string(), max_records.to_string()));
+        }
+        if let Some(cell_format) = &self.cell_format {
+            params.push(("cellFormat".to_string(), cell_format.to_string()));
+        }
+        if let Some(time_zone) = &self.time_zone {
+            params.push(("timeZone".to_string(), time_zone.to_string()));
+        }
+        if let Some(user_locale) = &self.user_locale {
+            params.push(("userLocale".to_string(), user_locale.to_string()));
+        }
+
+        params
+    }
+}
+
+/// How to sort records by a field.
+#[derive(Debug, Clone)]
+pub struct Sort {
+    pub field: String,
+    pub direction: SortDirection,
+}
+
+/// The direction to sort records in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+impl fmt::Display for SortDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SortDirection::Asc => write!(f, "asc"),
+            SortDirection::Desc => write!(f, "desc"),
+        }
+    }
+}
+
+/// How cell values are formatted when listing records.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellFormat {
+    /// Cell values are returned as JSON, linked records are ids.
+    Json,
+    /// Cell values are returned as the strings shown in the UI, linked
+    /// records are their primary field.
+    String,
+}
+
+impl fmt::Display for CellFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CellFormat::Json => write!(f, "json"),
+            CellFormat::String => write!(f, "string"),
+        }
+    }
+}
+
+/// The response from deleting records.
+#[derive(Debug, Clone, Deserialize)]
+struct DeletedRecords {
+    records: Vec<DeletedRecord>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DeletedRecord {
+    id: String,
+    deleted: bool,
+}
+
+/// An Airtable record, with its fields deserialized into `T`.
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
-pub struct Record {
+pub struct Record<T = serde_json::Value> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
-    pub fields: serde_json::Value,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: T,
+    #[serde(
+        default,
+        rename = "createdTime",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub created_time: Option<String>,
 }
 
-/// An airtable user.
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
-pub struct User {
+impl Record {
+    /// Deserialize the fields of the record into `T`.
+    pub fn decode<T: DeserializeOwned>(self) -> Result<Record<T>, RecordError> {
+        match serde_json::from_value(self.fields) {
+            Ok(fields) => Ok(Record {
+                id: self.id,
+                fields,
+                created_time: self.created_time,
+            }),
+            Err(error) => Err(RecordError { id: self.id, error }),
+        }
+    }
+}
+
+/// Error type returned when the fields of a record could not be
+/// deserialized.
+pub struct RecordError {
+    /// The id of the record.
+    pub id: Option<String>,
+    /// The error, this names the field that did not fit.
+    pub error: serde_json::Error,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "RecordError: id -> {}, error -> {}",
+            self.id.as_deref().unwrap_or_default(),
+            self.error
+        )
+    }
+}
+
+impl fmt::Debug for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl error::Error for RecordError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct BasesResponse {
+    bases: Vec<Base>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    offset: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct TablesResponse {
+    tables: Vec<Table>,
+}
+
+/// A base the API key has access to.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Base {
+    pub id: String,
+    pub name: String,
+    #[serde(default, rename = "permissionLevel")]
+    pub permission_level: String,
+}
+
+/// The schema of a table: its fields and views.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Table {
     pub id: String,
+    pub name: String,
+    #[serde(default, rename = "primaryFieldId")]
+    pub primary_field_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub fields: Vec<Field>,
+    #[serde(default)]
+    pub views: Vec<View>,
+}
+
+impl Table {
+    /// Get a field by its name.
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+/// A field, or column, in a table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// The options for the field type, like the choices of a select.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<serde_json::Value>,
+}
+
+/// The type of a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FieldType {
+    AutoNumber,
+    Barcode,
+    Button,
+    Checkbox,
+    Count,
+    CreatedBy,
+    CreatedTime,
+    Currency,
+    Date,
+    DateTime,
+    Duration,
+    Email,
+    ExternalSyncSource,
+    Formula,
+    LastModifiedBy,
+    LastModifiedTime,
+    MultilineText,
+    MultipleAttachments,
+    MultipleCollaborators,
+    MultipleLookupValues,
+    MultipleRecordLinks,
+    MultipleSelects,
+    Number,
+    Percent,
+    PhoneNumber,
+    Rating,
+    RichText,
+    Rollup,
+    SingleCollaborator,
+    SingleLineText,
+    SingleSelect,
+    Url,
+    /// A type this library does not know about yet.
+    #[serde(other)]
+    Other,
+}
+
+/// A view of a table.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct View {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub view_type: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateWebhookRequest {
+    notification_url: String,
+    specification: WebhookSpecification,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct WebhooksResponse {
+    webhooks: Vec<Webhook>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RefreshWebhookResponse {
+    #[serde(default)]
+    expiration_time: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookPayloadsResponse {
+    payloads: Vec<WebhookPayload>,
+    cursor: u64,
+    #[serde(default)]
+    might_have_more: bool,
+}
+
+/// A webhook on a base.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notification_url: Option<String>,
+    /// Where the payloads we have not seen yet start.
+    #[serde(default)]
+    pub cursor_for_next_payload: u64,
+    #[serde(default)]
+    pub are_notifications_enabled: bool,
+    #[serde(default)]
+    pub is_hook_enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration_time: Option<String>,
+    pub specification: WebhookSpecification,
+}
+
+/// A webhook we just created, with the secret Airtable signs its pings
+/// with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewWebhook {
+    pub id: String,
+    pub mac_secret_base64: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration_time: Option<String>,
+}
+
+/// What a webhook watches.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookSpecification {
+    pub options: WebhookOptions,
+}
+
+impl WebhookSpecification {
+    /// Watch for changes to the records in a table, or in the whole base if
+    /// there is no table.
+    pub fn table_data(table_id: Option<&str>) -> Self {
+        WebhookSpecification {
+            options: WebhookOptions {
+                filters: WebhookFilters {
+                    data_types: vec!["tableData".to_string()],
+                    record_change_scope: table_id.map(|t| t.to_string()),
+                },
+            },
+        }
+    }
+}
+
+/// The options for a webhook.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookOptions {
+    pub filters: WebhookFilters,
+}
+
+/// The changes a webhook watches for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookFilters {
+    /// What kind of changes to watch, `tableData`, `tableFields` or
+    /// `tableMetadata`.
+    pub data_types: Vec<String>,
+    /// The id of the table or view to watch, instead of the whole base.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record_change_scope: Option<String>,
+}
+
+/// The ping Airtable sends to the notification url of a webhook. It only
+/// says something changed, the changes are fetched with
+/// `list_webhook_payloads`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookNotification {
+    pub base: ObjectId,
+    pub webhook: ObjectId,
+    pub timestamp: String,
+}
+
+/// A reference to an object by its id.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectId {
+    pub id: String,
+}
+
+/// The payloads of a webhook and the cursor after the last one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookPayloads {
+    pub payloads: Vec<WebhookPayload>,
+    pub cursor: u64,
+}
+
+/// The changes made to a base in a single transaction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookPayload {
+    pub timestamp: String,
+    pub base_transaction_number: u64,
+    /// Who or what made the change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action_metadata: Option<ActionMetadata>,
+    #[serde(default)]
+    pub changed_tables_by_id: HashMap<String, ChangedTable>,
+}
+
+/// Who or what made a change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionMetadata {
+    /// Where the change came from, like `client` for the Airtable UI or
+    /// `publicApi` for the API.
+    pub source: String,
+    #[serde(default)]
+    pub source_metadata: serde_json::Value,
+}
+
+/// The changes to the records in a table.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedTable {
+    #[serde(default)]
+    pub changed_records_by_id: HashMap<String, ChangedRecord>,
+    #[serde(default)]
+    pub created_records_by_id: HashMap<String, CreatedRecord>,
+    #[serde(default)]
+    pub destroyed_record_ids: Vec<String>,
+}
+
+/// A record that changed, with the cells that changed.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangedRecord {
+    pub current: CellValues,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<CellValues>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unchanged: Option<CellValues>,
+}
+
+/// The values of cells, by the id of their field.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellValues {
+    #[serde(default)]
+    pub cell_values_by_field_id: HashMap<String, serde_json::Value>,
+}
+
+/// A record that was created.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedRecord {
+    pub created_time: String,
+    #[serde(default)]
+    pub cell_values_by_field_id: HashMap<String, serde_json::Value>,
+}
+
+/// A file attached to a record.
+///
+/// To attach a new file, create an attachment with `Attachment::new` and
+/// Airtable will download it from the url. To keep the files a record
+/// already has, send back the attachments it came with.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub filename: String,
+    /// The size of the file in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// The MIME type of the file.
+    #[serde(
+        default,
+        skip_serializing_if = "String::is_empty",
+        rename = "type"
+    )]
+    pub mime_type: String,
+    /// Only set for images and documents Airtable can preview.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnails: Option<Thumbnails>,
+}
+
+impl Attachment {
+    /// A new attachment for Airtable to download from the url.
+    pub fn new<U, F>(url: U, filename: F) -> Self
+    where
+        U: ToString,
+        F: ToString,
+    {
+        Attachment {
+            url: url.to_string(),
+            filename: filename.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The thumbnails Airtable generates for an attachment.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Thumbnails {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub small: Option<Thumbnail>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub large: Option<Thumbnail>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full: Option<Thumbnail>,
+}
+
+/// A single thumbnail of an attachment.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Thumbnail {
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The ids of the records a linked record field points to. Use
+/// `Airtable::get_linked_records` to get the records themselves.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LinkedRecords(pub Vec<String>);
+
+impl LinkedRecords {
+    /// The ids of the linked records.
+    pub fn ids(&self) -> &[String] {
+        &self.0
+    }
+
+    /// Returns true if there are no linked records.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns true if the record with the given id is linked.
+    pub fn contains(&self, id: &str) -> bool {
+        self.0.iter().any(|i| i == id)
+    }
+
+    /// Link another record, if it isn't linked already.
+    pub fn push<I>(&mut self, id: I)
+    where
+        I: ToString,
+    {
+        let id = id.to_string();
+        if !self.contains(&id) {
+            self.0.push(id);
+        }
+    }
+}
+
+impl From<Vec<String>> for LinkedRecords {
+    fn from(ids: Vec<String>) -> Self {
+        LinkedRecords(ids)
+    }
+}
+
+/// An Airtable user in a collaborator field.
+///
+/// To set a collaborator, only the id or the email is needed.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Collaborator {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub email: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub name: String,
 }
+
+impl Collaborator {
+    /// A collaborator to set by their email.
+    pub fn from_email<E>(email: E) -> Self
+    where
+        E: ToString,
+    {
+        Collaborator {
+            email: email.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// An airtable user.
+pub type User = Collaborator;
+
+/// The value of a barcode field.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Barcode {
+    pub text: String,
+    /// The symbology, for example `upce` or `code39`.
+    #[serde(
+        default,
+        skip_serializing_if = "String::is_empty",
+        rename = "type"
+    )]
+    pub barcode_type: String,
+}
+
+/// The value of a rating field, from 1 up to the maximum set on the field.
+/// Airtable leaves the field out when there is no rating, which is 0 here.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
+#[serde(transparent)]
+pub struct Rating(pub u8);
+
+/// The value of a duration field. Airtable sends durations as a number of
+/// seconds.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Duration(pub f64);
+
+impl Duration {
+    /// The duration in seconds.
+    pub fn as_secs_f64(&self) -> f64 {
+        self.0
+    }
+}
+
+impl From<time::Duration> for Duration {
+    fn from(d: time::Duration) -> Self {
+        Duration(d.as_secs_f64())
+    }
+}
+
+impl From<Duration> for time::Duration {
+    fn from(d: Duration) -> Self {
+        // Airtable does not stop you from entering a negative duration, or
+        // one too long to fit, so clamp it rather than panic.
+        let secs = d.0.max(0.0);
+        if secs >= u64::MAX as f64 {
+            return time::Duration::from_secs(u64::MAX);
+        }
+        time::Duration::from_secs_f64(secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, VecDeque};
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server, StatusCode};
+    use serde_json::{json, Value};
+
+    use crate::{
+        formula_field, formula_value, Airtable, Attachment, Barcode,
+        Collaborator, Error, FieldType, LinkedRecords, ListRecordsOptions,
+        Rating, Record, RetryPolicy, Sort, SortDirection, WebhookNotification,
+        WebhookSpecification,
+    };
+
+    /// A request the local server got: the method, the path with the query
+    /// and the body.
+    type Sent = (String, String, String);
+
+    /// Serve the canned responses in order from a local server in place of
+    /// Airtable, returning the endpoint and a count of the requests made.
+    fn serve(
+        responses: Vec<(u16, Option<&str>, String)>,
+    ) -> (String, Arc<AtomicUsize>) {
+        let (endpoint, count, _) = serve_logged(responses);
+        (endpoint, count)
+    }
+
+    /// Like `serve`, but also keep the requests that were made.
+    fn serve_logged(
+        responses: Vec<(u16, Option<&str>, String)>,
+    ) -> (String, Arc<AtomicUsize>, Arc<Mutex<Vec<Sent>>>) {
+        let responses: VecDeque<(u16, Option<String>, String)> = responses
+            .into_iter()
+            .map(|(s, r, b)| (s, r.map(|r| r.to_string()), b))
+            .collect();
+        let responses = Arc::new(Mutex::new(responses));
+        let count = Arc::new(AtomicUsize::new(0));
+        let sent: Arc<Mutex<Vec<Sent>>> = Default::default();
+
+        let (r, c, l) = (responses.clone(), count.clone(), sent.clone());
+        let make_svc = make_service_fn(move |_| {
+            let (r, c, l) = (r.clone(), c.clone(), l.clone());
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    c.fetch_add(1, Ordering::SeqCst);
+                    let (status, retry_after, body) =
+                        r.lock().unwrap().pop_front().unwrap();
+                    let l = l.clone();
+                    async move {
+                        let method = req.method().to_string();
+                        let uri = req.uri().to_string();
+                        let req_body = hyper::body::to_bytes(req.into_body())
+                            .await
+                            .unwrap();
+                        l.lock().unwrap().push((
+                            method,
+                            uri,
+                            String::from_utf8(req_body.to_vec()).unwrap(),
+                        ));
+
+                        let mut resp = Response::builder().status(status);
+                        if let Some(retry_after) = retry_after {
+                            resp = resp.header("Retry-After", retry_after);
+                        }
+                        Ok::<_, Infallible>(
+                            resp.body(Body::from(body)).unwrap(),
+                        )
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let endpoint = format!("http://{}/v0/", server.local_addr());
+        tokio::spawn(server);
+
+        (endpoint, count, sent)
+    }
+
+    fn airtable(endpoint: &str) -> Airtable {
+        Airtable::new("key", "base")
+            .with_endpoint(endpoint)
+            .with_retry_policy(RetryPolicy {
+                max_retries: 2,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(10),
+            })
+    }
+
+    fn records(n: usize) -> String {
+        let records: Vec<Record> = (0..n)
+            .map(|i| Record {
+                id: Some(format!("rec{}", i)),
+                fields: json!({"count": i}),
+                created_time: None,
+            })
+            .collect();
+        json!({ "records": records }).to_string()
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_secs(1));
+        assert_eq!(policy.backoff(1), Duration::from_secs(2));
+        assert_eq!(policy.backoff(2), Duration::from_secs(4));
+        assert_eq!(policy.backoff(3), Duration::from_secs(5));
+        assert_eq!(policy.backoff(100), Duration::from_secs(5));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_retry_rate_limited() {
+        let (endpoint, count) = serve(vec![
+            (429, Some("0"), "{}".to_string()),
+            (200, None, records(1)),
+        ]);
+
+        let records = airtable(&endpoint)
+            .list_records::<serde_json::Value>("table", "view", vec![])
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        // Once we run out of retries we get the rate limit back.
+        let (endpoint, count) = serve(vec![
+            (429, Some("0"), "{}".to_string()),
+            (429, Some("0"), "{}".to_string()),
+            (429, Some("0"), "{}".to_string()),
+        ]);
+        let err = airtable(&endpoint)
+            .get_record::<serde_json::Value>("t", "rec")
+            .await
+            .unwrap_err();
+        match err {
+            Error::RateLimited { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_secs(0)))
+            }
+            e => panic!("expected rate limited, got {}", e),
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_retry_errors() {
+        // Server errors are retried and then returned.
+        let (endpoint, count) = serve(vec![
+            (500, None, "oops".to_string()),
+            (502, None, "oops".to_string()),
+            (503, None, "still oops".to_string()),
+        ]);
+        let err = airtable(&endpoint)
+            .get_record::<serde_json::Value>("t", "rec")
+            .await
+            .unwrap_err();
+        assert_eq!(err.status_code(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(err.to_string().contains("still oops"));
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        // Creates are not, they may have gone through.
+        let (endpoint, count) = serve(vec![(500, None, "oops".to_string())]);
+        let new = vec![Record {
+            id: None,
+            fields: json!({ "count": 1 }),
+            created_time: None,
+        }];
+        let err = airtable(&endpoint)
+            .create_records("table", new)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.error.status_code(),
+            Some(StatusCode::INTERNAL_SERVER_ERROR)
+        );
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // Client errors are not.
+        let (endpoint, count) =
+            serve(vec![(404, None, "not found".to_string())]);
+        let err = airtable(&endpoint)
+            .get_record::<serde_json::Value>("t", "rec")
+            .await
+            .unwrap_err();
+        assert_eq!(err.status_code(), Some(StatusCode::NOT_FOUND));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // Neither are responses we cannot decode.
+        let (endpoint, count) =
+            serve(vec![(200, None, "not json".to_string())]);
+        let err = airtable(&endpoint)
+            .get_record::<serde_json::Value>("t", "rec")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Decode(_)));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_rate_limiter_per_base() {
+        let a = Airtable::new("key", "shared_base");
+        let b = Airtable::new("other_key", "shared_base");
+        let c = Airtable::new("key", "other_base");
+        assert!(Arc::ptr_eq(&a.rate_limiter, &b.rate_limiter));
+        assert!(!Arc::ptr_eq(&a.rate_limiter, &c.rate_limiter));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_batches() {
+        let page = |start: usize, n: usize| {
+            let records: Vec<Record> = (start..start + n)
+                .map(|i| Record {
+                    id: Some(format!("rec{}", i)),
+                    fields: json!({ "count": i }),
+                    created_time: None,
+                })
+                .collect();
+            json!({ "records": records }).to_string()
+        };
+        let (endpoint, count) = serve(vec![
+            (200, None, page(0, 10)),
+            (200, None, page(10, 10)),
+            (200, None, page(20, 5)),
+        ]);
+
+        let new: Vec<Record> = (0..25)
+            .map(|i| Record {
+                id: None,
+                fields: json!({ "count": i }),
+                created_time: None,
+            })
+            .collect();
+        let created = airtable(&endpoint)
+            .create_records("table", new)
+            .await
+            .unwrap();
+
+        // 25 records go out in chunks of 10, 10 and 5, and the results are
+        // merged back in order.
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+        let ids: Vec<String> =
+            created.into_iter().map(|r| r.id.unwrap()).collect();
+        let expected: Vec<String> =
+            (0..25).map(|i| format!("rec{}", i)).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_batch_error() {
+        let (endpoint, _) = serve(vec![
+            (200, None, records(10)),
+            (422, None, "invalid".to_string()),
+        ]);
+
+        let new: Vec<Record> = (0..15)
+            .map(|i| Record {
+                id: None,
+                fields: json!({ "count": i }),
+                created_time: None,
+            })
+            .collect();
+        let err = airtable(&endpoint)
+            .create_records("table", new)
+            .await
+            .unwrap_err();
+        assert_eq!(err.chunk, 1);
+        assert_eq!(err.records, 10..15);
+        assert_eq!(err.completed.len(), 10);
+        assert_eq!(
+            err.error.status_code(),
+            Some(StatusCode::UNPROCESSABLE_ENTITY)
+        );
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_upsert_records() {
+        let (endpoint, count, sent) = serve_logged(vec![
+            (
+                200,
+                None,
+                json!({"records": [
+                    {"id": "recJess", "fields": {"Email": "jess@oxide"}}
+                ]})
+                .to_string(),
+            ),
+            (
+                200,
+                None,
+                json!({"records": [
+                    {"id": "recJess", "fields": {"Email": "jess@oxide"}}
+                ]})
+                .to_string(),
+            ),
+            (
+                200,
+                None,
+                json!({"records": [
+                    {"id": "recNew", "fields": {"Email": "new@oxide"}},
+                    {"id": "recNone", "fields": {"Name": "No email"}}
+                ]})
+                .to_string(),
+            ),
+        ]);
+
+        let records: Vec<Record> = vec![
+            json!({"Email": "new@oxide", "Name": "New"}),
+            json!({"Email": "jess@oxide", "Name": "Jess"}),
+            json!({"Name": "No email"}),
+        ]
+        .into_iter()
+        .map(|fields| Record {
+            id: None,
+            fields,
+            created_time: None,
+        })
+        .collect();
+        let upserted = airtable(&endpoint)
+            .upsert_records("table", "Email", records)
+            .await
+            .unwrap();
+
+        // The results come back in the order the records were passed in.
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+        let ids: Vec<String> =
+            upserted.into_iter().map(|r| r.id.unwrap()).collect();
+        assert_eq!(ids, vec!["recNew", "recJess", "recNone"]);
+
+        // The existing records are looked up by the keys we have, then
+        // updated, and the rest are created.
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0].0, "GET");
+        assert!(sent[0].1.contains("filterByFormula="), "{}", sent[0].1);
+        assert_eq!(sent[1].0, "PATCH");
+        let body: Value = serde_json::from_str(&sent[1].2).unwrap();
+        assert_eq!(
+            body["records"],
+            json!([{
+                "id": "recJess",
+                "fields": {"Email": "jess@oxide", "Name": "Jess"}
+            }])
+        );
+        assert_eq!(sent[2].0, "POST");
+        let body: Value = serde_json::from_str(&sent[2].2).unwrap();
+        assert_eq!(
+            body["records"],
+            json!([
+                {"fields": {"Email": "new@oxide", "Name": "New"}},
+                {"fields": {"Name": "No email"}}
+            ])
+        );
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_upsert_records_same_new_key() {
+        let (endpoint, count, sent) = serve_logged(vec![
+            (200, None, json!({ "records": [] }).to_string()),
+            (
+                200,
+                None,
+                json!({"records": [
+                    {"id": "recNew", "fields": {"Email": "new@oxide"}}
+                ]})
+                .to_string(),
+            ),
+        ]);
+
+        let records: Vec<Record> = vec![
+            json!({"Email": "new@oxide", "Name": "First"}),
+            json!({"Email": "new@oxide", "Name": "Second"}),
+        ]
+        .into_iter()
+        .map(|fields| Record {
+            id: None,
+            fields,
+            created_time: None,
+        })
+        .collect();
+        let upserted = airtable(&endpoint)
+            .upsert_records("table", "Email", records)
+            .await
+            .unwrap();
+
+        // The record is created once, from the last one with the key.
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        let ids: Vec<String> =
+            upserted.into_iter().map(|r| r.id.unwrap()).collect();
+        assert_eq!(ids, vec!["recNew", "recNew"]);
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[1].0, "POST");
+        let body: Value = serde_json::from_str(&sent[1].2).unwrap();
+        assert_eq!(
+            body["records"],
+            json!([{"fields": {"Email": "new@oxide", "Name": "Second"}}])
+        );
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_upsert_records_error() {
+        let (endpoint, _) = serve(vec![
+            (
+                200,
+                None,
+                json!({"records": [
+                    {"id": "recJess", "fields": {"Email": "jess@oxide"}}
+                ]})
+                .to_string(),
+            ),
+            (
+                200,
+                None,
+                json!({"records": [
+                    {"id": "recJess", "fields": {"Email": "jess@oxide"}}
+                ]})
+                .to_string(),
+            ),
+            (422, None, "invalid".to_string()),
+        ]);
+
+        let records: Vec<Record> = vec![
+            json!({"Email": "new@oxide"}),
+            json!({"Email": "jess@oxide"}),
+        ]
+        .into_iter()
+        .map(|fields| Record {
+            id: None,
+            fields,
+            created_time: None,
+        })
+        .collect();
+        let err = airtable(&endpoint)
+            .upsert_records("table", "Email", records)
+            .await
+            .unwrap_err();
+
+        // The update went through before the create failed.
+        assert_eq!(err.chunk, 0);
+        assert_eq!(err.records, 0..2);
+        assert_eq!(err.completed.len(), 1);
+        assert_eq!(err.completed[0].id.as_deref(), Some("recJess"));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_delete_records() {
+        let deleted = |start: usize, n: usize| {
+            let records: Vec<Value> = (start..start + n)
+                .map(|i| json!({"id": format!("rec{}", i), "deleted": true}))
+                .collect();
+            json!({ "records": records }).to_string()
+        };
+        let (endpoint, _, sent) = serve_logged(vec![
+            (200, None, deleted(0, 10)),
+            (200, None, deleted(10, 10)),
+            (200, None, deleted(20, 5)),
+        ]);
+
+        let ids: Vec<String> = (0..25).map(|i| format!("rec{}", i)).collect();
+        let deleted = airtable(&endpoint)
+            .delete_records("table", ids.clone())
+            .await
+            .unwrap();
+        assert_eq!(deleted, ids);
+
+        // The ids go out in chunks of 10, 10 and 5.
+        let sent = sent.lock().unwrap();
+        let chunks: Vec<usize> = sent
+            .iter()
+            .map(|(method, uri, _)| {
+                assert_eq!(method, "DELETE");
+                uri.matches("records%5B%5D=").count()
+            })
+            .collect();
+        assert_eq!(chunks, vec![10, 10, 5]);
+    }
+
+    #[test]
+    fn test_formula_field() {
+        assert_eq!(formula_field("Email"), "{Email}");
+        assert_eq!(formula_field("a}b\\c"), "{a\\}b\\\\c}");
+    }
+
+    #[test]
+    fn test_formula_value() {
+        assert_eq!(formula_value(&json!("jess")).unwrap(), "'jess'");
+        assert_eq!(formula_value(&json!("it's")).unwrap(), r"'it\'s'");
+        assert_eq!(formula_value(&json!(42)).unwrap(), "42");
+        assert_eq!(formula_value(&json!(true)).unwrap(), "TRUE()");
+        assert!(formula_value(&json!(null)).is_none());
+        assert!(formula_value(&json!(["a"])).is_none());
+    }
+
+    #[test]
+    fn test_list_records_options_to_query() {
+        let options = ListRecordsOptions {
+            fields: vec!["Name".to_string(), "Email".to_string()],
+            filter_by_formula: Some("{Name}='Jess'".to_string()),
+            sort: vec![Sort {
+                field: "Name".to_string(),
+                direction: SortDirection::Desc,
+            }],
+            max_records: Some(3),
+            ..Default::default()
+        };
+
+        let query = options.to_query();
+        assert_eq!(
+            query
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<(&str, &str)>>(),
+            vec![
+                ("pageSize", "100"),
+                ("fields[]", "Name"),
+                ("fields[]", "Email"),
+                ("filterByFormula", "{Name}='Jess'"),
+                ("sort[0][field]", "Name"),
+                ("sort[0][direction]", "desc"),
+                ("maxRecords", "3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_decode() {
+        #[derive(Debug, serde::Deserialize)]
+        struct Fields {
+            name: String,
+            count: i32,
+        }
+
+        let record = Record {
+            id: Some("rec1".to_string()),
+            fields: json!({"name": "Jess", "count": 3, "extra": true}),
+            created_time: None,
+        };
+        let decoded: Record<Fields> = record.decode().unwrap();
+        assert_eq!(decoded.id.as_deref(), Some("rec1"));
+        assert_eq!(decoded.fields.name, "Jess");
+        assert_eq!(decoded.fields.count, 3);
+
+        let record = Record {
+            id: Some("rec2".to_string()),
+            fields: json!({"name": "Jess", "count": "three"}),
+            created_time: None,
+        };
+        let err = record.decode::<Fields>().unwrap_err();
+        assert_eq!(err.id.as_deref(), Some("rec2"));
+        assert!(err.to_string().contains("rec2"));
+    }
+
+    #[test]
+    fn test_field_types() {
+        #[derive(Debug, serde::Deserialize, serde::Serialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Fields {
+            resume: Vec<Attachment>,
+            interviewers: LinkedRecords,
+            recruiter: Collaborator,
+            badge: Barcode,
+            #[serde(default)]
+            rating: Rating,
+            call: crate::Duration,
+        }
+
+        let fields: Fields = serde_json::from_value(json!({
+            "Resume": [{
+                "id": "att1",
+                "url": "https://dl.airtable.com/resume.pdf",
+                "filename": "resume.pdf",
+                "size": 1024,
+                "type": "application/pdf",
+                "thumbnails": {
+                    "small": {"url": "https://dl.airtable.com/s", "width": 27, "height": 36}
+                }
+            }],
+            "Interviewers": ["rec1", "rec2"],
+            "Recruiter": {"id": "usr1", "email": "jess@oxide.computer", "name": "Jess"},
+            "Badge": {"text": "1234", "type": "code39"},
+            "Call": 5400
+        }))
+        .unwrap();
+
+        assert_eq!(fields.resume[0].size, Some(1024));
+        assert_eq!(fields.resume[0].mime_type, "application/pdf");
+        let small =
+            fields.resume[0].thumbnails.as_ref().unwrap().small.as_ref();
+        assert_eq!(small.unwrap().width, 27);
+        assert!(fields.interviewers.contains("rec2"));
+        assert_eq!(fields.recruiter.name, "Jess");
+        assert_eq!(fields.badge.barcode_type, "code39");
+        assert_eq!(fields.rating, Rating(0));
+        assert_eq!(Duration::from(fields.call), Duration::from_secs(90 * 60));
+        assert_eq!(Duration::from(crate::Duration(-1.0)), Duration::default());
+        assert_eq!(
+            Duration::from(crate::Duration(1e30)),
+            Duration::from_secs(u64::MAX)
+        );
+        assert_eq!(
+            Duration::from(crate::Duration(f64::INFINITY)),
+            Duration::from_secs(u64::MAX)
+        );
+
+        // New attachments and collaborators only send what Airtable needs.
+        assert_eq!(
+            serde_json::to_value(Attachment::new("https://x/cv.pdf", "cv.pdf"))
+                .unwrap(),
+            json!({"url": "https://x/cv.pdf", "filename": "cv.pdf"})
+        );
+        assert_eq!(
+            serde_json::to_value(Collaborator::from_email(
+                "jess@oxide.computer"
+            ))
+            .unwrap(),
+            json!({"email": "jess@oxide.computer"})
+        );
+
+        let mut links = LinkedRecords::default();
+        links.push("rec1");
+        links.push("rec1");
+        assert_eq!(serde_json::to_value(&links).unwrap(), json!(["rec1"]));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_get_linked_records() {
+        let (endpoint, count) = serve(vec![(200, None, records(3))]);
+
+        let links = LinkedRecords::from(vec![
+            "rec2".to_string(),
+            "rec0".to_string(),
+            "recGone".to_string(),
+        ]);
+        let linked = airtable(&endpoint)
+            .get_linked_records::<serde_json::Value>("table", &links)
+            .await
+            .unwrap();
+
+        // The records come back in the order of the links.
+        assert_eq!(
+            linked
+                .iter()
+                .map(|r| r.as_ref().unwrap().id.as_deref().unwrap())
+                .collect::<Vec<&str>>(),
+            vec!["rec2", "rec0"]
+        );
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // We don't need to ask Airtable for no links at all.
+        let linked = airtable(&endpoint)
+            .get_linked_records::<serde_json::Value>(
+                "table",
+                &LinkedRecords::default(),
+            )
+            .await
+            .unwrap();
+        assert!(linked.is_empty());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // A record that does not fit only fails on its own.
+        let body = json!({"records": [
+            {"id": "rec0", "fields": {"count": 0}},
+            {"id": "rec1", "fields": {"count": "one"}}
+        ]});
+        let (endpoint, _) = serve(vec![(200, None, body.to_string())]);
+        let links =
+            LinkedRecords::from(vec!["rec0".to_string(), "rec1".to_string()]);
+        let linked = airtable(&endpoint)
+            .get_linked_records::<BTreeMap<String, u64>>("table", &links)
+            .await
+            .unwrap();
+        assert_eq!(linked[0].as_ref().unwrap().fields["count"], 0);
+        assert_eq!(linked[1].as_ref().unwrap_err().id.as_deref(), Some("rec1"));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_get_table() {
+        let tables = json!({
+            "tables": [{
+                "id": "tbl1",
+                "name": "RFDs",
+                "primaryFieldId": "fld1",
+                "fields": [
+                    {"id": "fld1", "name": "number", "type": "number",
+                     "options": {"precision": 0}},
+                    {"id": "fld2", "name": "milestones", "type": "multipleRecordLinks"},
+                    {"id": "fld3", "name": "ai", "type": "aiText"}
+                ],
+                "views": [{"id": "viw1", "name": "Grid view", "type": "grid"}]
+            }]
+        });
+        let (endpoint, _) = serve(vec![
+            (200, None, tables.to_string()),
+            (200, None, tables.to_string()),
+        ]);
+        let airtable = airtable(&endpoint);
+
+        let table = airtable.get_table("RFDs").await.unwrap().unwrap();
+        assert_eq!(table.id, "tbl1");
+        assert_eq!(table.views[0].view_type, "grid");
+        assert_eq!(
+            table.field("milestones").unwrap().field_type,
+            FieldType::MultipleRecordLinks
+        );
+        // Types we don't know about yet don't fail the whole table.
+        assert_eq!(table.field("ai").unwrap().field_type, FieldType::Other);
+
+        assert!(airtable.get_table("Nope").await.unwrap().is_none());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_webhooks() {
+        let created = json!({
+            "id": "ach1",
+            "macSecretBase64": "c2VjcmV0",
+            "expirationTime": "2020-10-08T00:00:00.000Z"
+        });
+        let webhooks = json!({
+            "webhooks": [{
+                "id": "ach1",
+                "notificationUrl": "https://cio.example.com/airtable/webhook",
+                "cursorForNextPayload": 3,
+                "areNotificationsEnabled": true,
+                "isHookEnabled": true,
+                "specification": {
+                    "options": {"filters": {"dataTypes": ["tableData"]}}
+                }
+            }]
+        });
+        let refreshed = json!({"expirationTime": "2020-10-15T00:00:00.000Z"});
+        let (endpoint, count) = serve(vec![
+            (200, None, created.to_string()),
+            (200, None, webhooks.to_string()),
+            (200, None, refreshed.to_string()),
+        ]);
+        let airtable = airtable(&endpoint);
+
+        let webhook = airtable
+            .create_webhook(
+                "https://cio.example.com/airtable/webhook",
+                WebhookSpecification::table_data(None),
+            )
+            .await
+            .unwrap();
+        assert_eq!(webhook.id, "ach1");
+        assert_eq!(webhook.mac_secret_base64, "c2VjcmV0");
+
+        let webhooks = airtable.list_webhooks().await.unwrap();
+        assert_eq!(webhooks.len(), 1);
+        assert_eq!(webhooks[0].cursor_for_next_payload, 3);
+        assert_eq!(
+            webhooks[0].specification,
+            WebhookSpecification::table_data(None)
+        );
+
+        let expiration = airtable.refresh_webhook("ach1").await.unwrap();
+        assert_eq!(expiration.unwrap(), "2020-10-15T00:00:00.000Z");
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        let notification: WebhookNotification = serde_json::from_value(json!({
+            "base": {"id": "app1"},
+            "webhook": {"id": "ach1"},
+            "timestamp": "2020-10-01T00:00:00.000Z"
+        }))
+        .unwrap();
+        assert_eq!(notification.webhook.id, "ach1");
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_list_webhook_payloads() {
+        let page = |cursor: u64, more: bool| {
+            json!({
+                "payloads": [{
+                    "timestamp": "2020-10-01T00:00:00.000Z",
+                    "baseTransactionNumber": cursor,
+                    "actionMetadata": {"source": "client"},
+                    "payloadFormat": "v0",
+                    "changedTablesById": {
+                        "tbl1": {
+                            "changedRecordsById": {
+                                "rec1": {
+                                    "current": {
+                                        "cellValuesByFieldId": {"fld1": "Hired"}
+                                    },
+                                    "previous": {
+                                        "cellValuesByFieldId": {"fld1": "Onsite"}
+                                    }
+                                }
+                            },
+                            "destroyedRecordIds": ["rec2"]
+                        }
+                    }
+                }],
+                "cursor": cursor + 1,
+                "mightHaveMore": more,
+                "payloadFormat": "v0"
+            })
+            .to_string()
+        };
+        let (endpoint, count) = serve(vec![
+            (200, None, page(1, true)),
+            (200, None, page(2, false)),
+        ]);
+        let airtable = airtable(&endpoint);
+
+        let payloads = airtable.list_webhook_payloads("ach1", 1).await.unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        assert_eq!(payloads.cursor, 3);
+        assert_eq!(payloads.payloads.len(), 2);
+
+        let payload = &payloads.payloads[1];
+        assert_eq!(payload.base_transaction_number, 2);
+        assert_eq!(payload.action_metadata.as_ref().unwrap().source, "client");
+        let table = &payload.changed_tables_by_id["tbl1"];
+        assert_eq!(
+            table.changed_records_by_id["rec1"]
+                .current
+                .cell_values_by_field_id["fld1"],
+            json!("Hired")
+        );
+        assert!(table.created_records_by_id.is_empty());
+        assert_eq!(table.destroyed_record_ids, vec!["rec2".to_string()]);
+    }
+}
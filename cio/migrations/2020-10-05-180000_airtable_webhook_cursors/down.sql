@@ -0,0 +1 @@
+DROP TABLE airtable_webhook_cursors
@@ -0,0 +1,5 @@
+CREATE TABLE airtable_webhook_cursors (
+    id SERIAL PRIMARY KEY,
+    webhook_id VARCHAR NOT NULL UNIQUE,
+    next_cursor BIGINT NOT NULL
+)
@@ -0,0 +1,8 @@
+CREATE TABLE config_api_changes (
+    id SERIAL PRIMARY KEY,
+    section VARCHAR NOT NULL,
+    name VARCHAR NOT NULL,
+    deleted BOOLEAN NOT NULL DEFAULT 'f',
+    changed_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (section, name)
+)
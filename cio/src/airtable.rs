@@ -1,24 +1,1328 @@
+use std::collections::BTreeMap;
 use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
 
-pub static AIRTABLE_BASE_ID_RACK_ROADMAP: &str = "appvAEzcMvB2QNboC";
-pub static AIRTABLE_BASE_ID_PRODUCT_HUDDLE: &str = "appbQqnE3nykcnkbx";
-pub static AIRTABLE_BASE_ID_RECURITING_APPLICATIONS: &str = "appIw5FNBqWTXFTeV";
-pub static AIRTABLE_BASE_ID_CUSTOMER_LEADS: &str = "appr7imQLcR3pWaNa";
-pub static AIRTABLE_BASE_ID_MISC: &str = "appfWJK9kBNwnavG2";
-
-pub static AIRTABLE_RFD_TABLE: &str = "RFDs";
-pub static AIRTABLE_CUSTOMER_INTERACTIONS_TABLE: &str = "Interactions";
-pub static AIRTABLE_APPLICATIONS_TABLE: &str = "Applicants";
-pub static AIRTABLE_MAILING_LIST_SIGNUPS_TABLE: &str = "Mailing List Signups";
-pub static AIRTABLE_AUTH_USERS_TABLE: &str = "Auth Users";
-pub static AIRTABLE_AUTH_USER_LOGINS_TABLE: &str = "Auth User Logins";
-pub static AIRTABLE_MEETING_SCHEDULE_TABLE: &str = "Meeting schedule";
-pub static AIRTABLE_DISCUSSION_TOPICS_TABLE: &str = "Discussion topics";
-pub static AIRTABLE_JOURNAL_CLUB_MEETINGS_TABLE: &str = "Journal Club Meetings";
-pub static AIRTABLE_JOURNAL_CLUB_PAPERS_TABLE: &str = "Journal Club Papers";
-
-pub static AIRTABLE_GRID_VIEW: &str = "Grid view";
+use airtable_api::{
+    Airtable, Error, FieldType, Record, Table, WebhookNotification,
+};
+use dropshot::HttpError;
+use lazy_static::lazy_static;
+use ring::{constant_time, hmac};
+use schemars::schema::{InstanceType, RootSchema, Schema, SingleOrVec};
+use schemars::{schema_for, JsonSchema};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::{Database, DbError};
+use crate::models::{
+    Applicant, AuthUser, AuthUserLogin, JournalClubMeeting, JournalClubPaper,
+    MailingListSubscriber, NewAirtableWebhookCursor, RFD,
+};
+
+/// The tables we use by default, see the file for how to change them.
+static DEFAULT_CONFIG: &str = include_str!("../airtable.toml");
+
+/// The view we list records from, unless the config says otherwise.
+static DEFAULT_VIEW: &str = "Grid view";
+
+/// Where a table lives in Airtable.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AirtableTable {
+    pub base_id: String,
+    pub table: String,
+    #[serde(default = "default_view")]
+    pub view: String,
+    /// Delete the records in the table whose row is no longer in our
+    /// database when we sync to it. This is off by default, since a record
+    /// can also have no row because someone added it by hand.
+    #[serde(default)]
+    pub delete_missing: bool,
+}
+
+fn default_view() -> String {
+    DEFAULT_VIEW.to_string()
+}
+
+impl AirtableTable {
+    /// Get a client for the base the table is in.
+    pub fn client(&self) -> Airtable {
+        Airtable::new(airtable_api_key(), &self.base_id)
+    }
+}
+
+/// The Airtable tables we use, by their logical name, like `applicants`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct AirtableConfig {
+    pub tables: BTreeMap<String, AirtableTable>,
+}
+
+impl AirtableConfig {
+    /// Parse the config from TOML, with a table for each logical name.
+    pub fn from_toml(s: &str) -> Result<Self, AirtableConfigError> {
+        toml::from_str(s).map_err(AirtableConfigError::Parse)
+    }
+
+    /// Read the config from the environment.
+    ///
+    /// We start from the defaults in `cio/airtable.toml`. If
+    /// `CIO_AIRTABLE_CONFIG` is set, the tables in that file replace the
+    /// defaults. Then `CIO_AIRTABLE_<NAME>_BASE_ID`,
+    /// `CIO_AIRTABLE_<NAME>_TABLE` and `CIO_AIRTABLE_<NAME>_VIEW` override
+    /// single values, where the name is in uppercase.
+    pub fn from_env() -> Result<Self, AirtableConfigError> {
+        let mut config = AirtableConfig::from_toml(DEFAULT_CONFIG)?;
+
+        if let Ok(path) = env::var("CIO_AIRTABLE_CONFIG") {
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| AirtableConfigError::Read(path, e))?;
+            config
+                .tables
+                .append(&mut AirtableConfig::from_toml(&contents)?.tables);
+        }
+
+        for (name, table) in config.tables.iter_mut() {
+            let var = |field: &str| {
+                env::var(format!(
+                    "CIO_AIRTABLE_{}_{}",
+                    name.to_uppercase(),
+                    field
+                ))
+            };
+            if let Ok(base_id) = var("BASE_ID") {
+                table.base_id = base_id;
+            }
+            if let Ok(t) = var("TABLE") {
+                table.table = t;
+            }
+            if let Ok(view) = var("VIEW") {
+                table.view = view;
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Get a table by its logical name.
+    pub fn table(
+        &self,
+        name: &str,
+    ) -> Result<&AirtableTable, AirtableConfigError> {
+        self.tables
+            .get(name)
+            .ok_or_else(|| AirtableConfigError::MissingTable(name.to_string()))
+    }
+}
+
+lazy_static! {
+    /// Our Airtable config, read from the environment the first time it is
+    /// used.
+    static ref AIRTABLE_CONFIG: Result<AirtableConfig, AirtableConfigError> =
+        AirtableConfig::from_env();
+}
+
+/// Get our Airtable config. It is only read from the environment once.
+pub fn airtable_config() -> Result<&'static AirtableConfig, AirtableConfigError>
+{
+    AIRTABLE_CONFIG.as_ref().map_err(Clone::clone)
+}
+
+/// Read our Airtable config and check it has every table we sync to. The
+/// server calls this at startup, so a bad config stops it from starting
+/// instead of failing the first job that uses the config.
+pub fn check_airtable_config(
+) -> Result<&'static AirtableConfig, AirtableConfigError> {
+    let config = airtable_config()?;
+    for name in &[
+        Applicant::airtable_table(),
+        AuthUser::airtable_table(),
+        AuthUserLogin::airtable_table(),
+        JournalClubMeeting::airtable_table(),
+        JournalClubPaper::airtable_table(),
+        MailingListSubscriber::airtable_table(),
+        RFD::airtable_table(),
+    ] {
+        config.table(name)?;
+    }
+
+    Ok(config)
+}
+
+/// Get a table by its logical name, from our Airtable config.
+pub fn airtable_table(
+    name: &str,
+) -> Result<AirtableTable, AirtableConfigError> {
+    Ok(airtable_config()?.table(name)?.clone())
+}
+
+/// Error type returned when reading our Airtable config.
+pub enum AirtableConfigError {
+    /// The config file could not be read.
+    Read(String, io::Error),
+    /// The config is not valid TOML or is missing a value.
+    Parse(toml::de::Error),
+    /// There is no table with the logical name in the config.
+    MissingTable(String),
+}
+
+impl fmt::Display for AirtableConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AirtableConfigError::Read(path, e) => {
+                write!(f, "AirtableConfigError: read -> {}: {}", path, e)
+            }
+            AirtableConfigError::Parse(e) => {
+                write!(f, "AirtableConfigError: parse -> {}", e)
+            }
+            AirtableConfigError::MissingTable(name) => {
+                write!(f, "AirtableConfigError: missing table -> {}", name)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for AirtableConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// The config is only read once, so everyone after the first caller gets a
+// copy of the error.
+impl Clone for AirtableConfigError {
+    fn clone(&self) -> Self {
+        match self {
+            AirtableConfigError::Read(path, e) => AirtableConfigError::Read(
+                path.clone(),
+                io::Error::new(e.kind(), e.to_string()),
+            ),
+            AirtableConfigError::Parse(e) => {
+                AirtableConfigError::Parse(e.clone())
+            }
+            AirtableConfigError::MissingTable(name) => {
+                AirtableConfigError::MissingTable(name.clone())
+            }
+        }
+    }
+}
+
+impl error::Error for AirtableConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            AirtableConfigError::Read(_, e) => Some(e),
+            AirtableConfigError::Parse(e) => Some(e),
+            AirtableConfigError::MissingTable(_) => None,
+        }
+    }
+}
 
 pub fn airtable_api_key() -> String {
     env::var("AIRTABLE_API_KEY").unwrap()
 }
+
+/// A database row we keep in sync with a table in Airtable. This is
+/// implemented by `db_struct` for every struct with an `airtable_table`.
+///
+/// Rows are matched to records by the `match_on` fields of the struct, so
+/// they still match if the rows get new ids. The fields in `db_fields` are
+/// owned by our database and pushed to Airtable. The fields in
+/// `airtable_fields` are owned by Airtable, we send them when we create a
+/// record and keep them as they are in Airtable after that.
+pub trait AirtableSync:
+    Serialize + DeserializeOwned + JsonSchema + PartialEq + Send
+{
+    /// The logical name of the table in our `AirtableConfig`.
+    fn airtable_table() -> &'static str;
+
+    /// The key we match rows and records on.
+    fn key(&self) -> Vec<String>;
+
+    /// The names in Airtable of the fields Airtable owns.
+    fn airtable_fields() -> &'static [&'static str];
+
+    /// The names in Airtable of the fields our database owns.
+    fn db_fields() -> &'static [&'static str];
+
+    /// Copy the fields Airtable owns from the existing record.
+    fn keep_airtable_fields(&mut self, record: &Self);
+}
+
+/// Error type returned when syncing to Airtable.
+pub enum SyncError {
+    /// The table is missing from our config, or the config is invalid.
+    Config(AirtableConfigError),
+    /// Reading the rows from our database failed.
+    Db(DbError),
+    /// Talking to Airtable failed.
+    Airtable(Error),
+    /// The table in Airtable no longer matches our struct, so we did not
+    /// touch it.
+    Drift(SchemaDrift),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncError::Config(e) => write!(f, "SyncError: config -> {}", e),
+            SyncError::Db(e) => write!(f, "SyncError: db -> {}", e),
+            SyncError::Airtable(e) => write!(f, "SyncError: airtable -> {}", e),
+            SyncError::Drift(e) => write!(f, "SyncError: drift -> {}", e),
+        }
+    }
+}
+
+impl fmt::Debug for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl error::Error for SyncError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SyncError::Config(e) => Some(e),
+            SyncError::Db(e) => Some(e),
+            SyncError::Airtable(e) => Some(e),
+            SyncError::Drift(_) => None,
+        }
+    }
+}
+
+impl From<AirtableConfigError> for SyncError {
+    fn from(e: AirtableConfigError) -> Self {
+        SyncError::Config(e)
+    }
+}
+
+impl From<DbError> for SyncError {
+    fn from(e: DbError) -> Self {
+        SyncError::Db(e)
+    }
+}
+
+impl From<Error> for SyncError {
+    fn from(e: Error) -> Self {
+        SyncError::Airtable(e)
+    }
+}
+
+impl From<SyncError> for HttpError {
+    fn from(e: SyncError) -> Self {
+        match e {
+            SyncError::Db(e) => e.into(),
+            _ => HttpError::for_internal_error(e.to_string()),
+        }
+    }
+}
+
+/// The ways a table in Airtable no longer matches our struct.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaDrift {
+    pub table: String,
+    pub problems: Vec<DriftProblem>,
+}
+
+impl fmt::Display for SchemaDrift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "table `{}` does not match: {}",
+            self.table,
+            self.problems
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<String>>()
+                .join("; ")
+        )
+    }
+}
+
+/// A single way a table in Airtable no longer matches our struct.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriftProblem {
+    /// The table does not exist.
+    MissingTable,
+    /// A field in our struct has no column in Airtable.
+    MissingField(String),
+    /// A field in our struct has no column in Airtable, but there is a
+    /// column with a similar name it was probably renamed to.
+    RenamedField { field: String, column: String },
+    /// The column in Airtable holds a different type than our field.
+    TypeMismatch {
+        field: String,
+        field_type: FieldType,
+        ours: Vec<InstanceType>,
+    },
+}
+
+impl fmt::Display for DriftProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DriftProblem::MissingTable => write!(f, "missing table"),
+            DriftProblem::MissingField(field) => {
+                write!(f, "missing field `{}`", field)
+            }
+            DriftProblem::RenamedField { field, column } => {
+                write!(f, "field `{}` was renamed to `{}`", field, column)
+            }
+            DriftProblem::TypeMismatch {
+                field,
+                field_type,
+                ours,
+            } => write!(
+                f,
+                "field `{}` is {:?} in Airtable but {:?} in our struct",
+                field, field_type, ours
+            ),
+        }
+    }
+}
+
+/// Compare the JSON schema of our struct against the fields of its table in
+/// Airtable.
+pub fn check_schema<T: JsonSchema>(table: &Table) -> Vec<DriftProblem> {
+    let root = schema_for!(T);
+    let properties = match &root.schema.object {
+        Some(object) => &object.properties,
+        None => return vec![],
+    };
+
+    let mut problems: Vec<DriftProblem> = Default::default();
+    for (name, schema) in properties.iter() {
+        let field = match table.field(name) {
+            Some(field) => field,
+            None => {
+                // See if a column that isn't one of our fields has a similar
+                // name.
+                let renamed = table.fields.iter().find(|f| {
+                    !properties.contains_key(&f.name)
+                        && similar_names(name, &f.name)
+                });
+                problems.push(match renamed {
+                    Some(f) => DriftProblem::RenamedField {
+                        field: name.to_string(),
+                        column: f.name.to_string(),
+                    },
+                    None => DriftProblem::MissingField(name.to_string()),
+                });
+                continue;
+            }
+        };
+
+        let expected = match airtable_json_types(field.field_type) {
+            Some(expected) => expected,
+            None => continue,
+        };
+        let ours = instance_types(schema, &root);
+        if !ours.is_empty() && !ours.iter().any(|t| expected.contains(t)) {
+            problems.push(DriftProblem::TypeMismatch {
+                field: name.to_string(),
+                field_type: field.field_type,
+                ours,
+            });
+        }
+    }
+
+    problems
+}
+
+/// Check the table in Airtable for the struct still matches it.
+pub async fn check_airtable_schema<T: AirtableSync>(
+) -> Result<Option<SchemaDrift>, SyncError> {
+    let table = airtable_table(T::airtable_table())?;
+
+    Ok(schema_drift::<T>(&table.client(), &table).await?)
+}
+
+async fn schema_drift<T: AirtableSync>(
+    airtable: &Airtable,
+    table: &AirtableTable,
+) -> Result<Option<SchemaDrift>, Error> {
+    let problems = match airtable.get_table(&table.table).await? {
+        Some(t) => check_schema::<T>(&t),
+        None => vec![DriftProblem::MissingTable],
+    };
+    if problems.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(SchemaDrift {
+        table: table.table.to_string(),
+        problems,
+    }))
+}
+
+/// The JSON types Airtable uses for the values of a field type, or None if
+/// it depends on the field, like for formulas.
+fn airtable_json_types(
+    field_type: FieldType,
+) -> Option<&'static [InstanceType]> {
+    match field_type {
+        FieldType::Date
+        | FieldType::DateTime
+        | FieldType::CreatedTime
+        | FieldType::LastModifiedTime
+        | FieldType::Email
+        | FieldType::MultilineText
+        | FieldType::PhoneNumber
+        | FieldType::RichText
+        | FieldType::SingleLineText
+        | FieldType::SingleSelect
+        | FieldType::Url => Some(&[InstanceType::String]),
+        FieldType::AutoNumber
+        | FieldType::Count
+        | FieldType::Currency
+        | FieldType::Duration
+        | FieldType::Number
+        | FieldType::Percent
+        | FieldType::Rating => {
+            Some(&[InstanceType::Number, InstanceType::Integer])
+        }
+        FieldType::Checkbox => Some(&[InstanceType::Boolean]),
+        FieldType::MultipleAttachments
+        | FieldType::MultipleCollaborators
+        | FieldType::MultipleLookupValues
+        | FieldType::MultipleRecordLinks
+        | FieldType::MultipleSelects => Some(&[InstanceType::Array]),
+        FieldType::Barcode
+        | FieldType::Button
+        | FieldType::CreatedBy
+        | FieldType::LastModifiedBy
+        | FieldType::SingleCollaborator => Some(&[InstanceType::Object]),
+        FieldType::ExternalSyncSource
+        | FieldType::Formula
+        | FieldType::Rollup
+        | FieldType::Other => None,
+    }
+}
+
+/// The JSON types a schema allows, following references. Null is left out
+/// since any field in Airtable can be empty.
+fn instance_types(schema: &Schema, root: &RootSchema) -> Vec<InstanceType> {
+    let mut types = schema_types(schema, root);
+    types.retain(|t| *t != InstanceType::Null);
+    types
+}
+
+/// The JSON types a schema allows, including null.
+fn schema_types(schema: &Schema, root: &RootSchema) -> Vec<InstanceType> {
+    let object = match schema {
+        Schema::Object(object) => object,
+        Schema::Bool(_) => return vec![],
+    };
+
+    if let Some(reference) = &object.reference {
+        let name = reference.trim_start_matches("#/definitions/");
+        return match root.definitions.get(name) {
+            Some(schema) => schema_types(schema, root),
+            None => vec![],
+        };
+    }
+
+    let mut types: Vec<InstanceType> = match &object.instance_type {
+        Some(SingleOrVec::Single(t)) => vec![**t],
+        Some(SingleOrVec::Vec(t)) => t.clone(),
+        None => vec![],
+    };
+    if let Some(subschemas) = &object.subschemas {
+        let all = subschemas
+            .all_of
+            .iter()
+            .chain(subschemas.any_of.iter())
+            .chain(subschemas.one_of.iter())
+            .flatten();
+        for schema in all {
+            types.extend(schema_types(schema, root));
+        }
+    }
+
+    types
+}
+
+/// Returns true if two field names are close enough that one was probably
+/// renamed to the other, ignoring case, spaces and punctuation.
+fn similar_names(a: &str, b: &str) -> bool {
+    let normalize = |s: &str| -> Vec<char> {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let (a, b) = (normalize(a), normalize(b));
+    if a == b {
+        return true;
+    }
+
+    // Allow a couple of typos, but not in short names where that changes
+    // the whole name.
+    a.len().min(b.len()) > 4 && edit_distance(&a, &b) <= 2
+}
+
+/// The Levenshtein distance between two names.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = previous[j] + if ca == cb { 0 } else { 1 };
+            current
+                .push(substitute.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// What a sync to Airtable did.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SyncReport {
+    pub created: usize,
+    pub updated: usize,
+    /// The records that already matched our database.
+    pub unchanged: usize,
+    pub deleted: usize,
+    /// The records with no row in our database that we did not delete,
+    /// since the table does not have `delete_missing` set.
+    pub missing: usize,
+    /// The records with the same key as another record that we did not
+    /// delete, since the table does not have `delete_missing` set.
+    pub duplicates: usize,
+    /// The records we could not decode and the records and rows without a
+    /// key, these are left alone.
+    pub skipped: usize,
+}
+
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "created {}, updated {}, unchanged {}, deleted {}, missing {}, \
+             duplicates {}, skipped {}",
+            self.created,
+            self.updated,
+            self.unchanged,
+            self.deleted,
+            self.missing,
+            self.duplicates,
+            self.skipped
+        )
+    }
+}
+
+/// The changes to make to a table in Airtable so it matches our database.
+struct SyncPlan<T> {
+    creates: Vec<Record<T>>,
+    updates: Vec<Record<T>>,
+    deletes: Vec<String>,
+    unchanged: usize,
+    missing: usize,
+    duplicates: usize,
+    skipped: usize,
+}
+
+/// Work out the changes to make to the records in Airtable so they match the
+/// rows in our database. Records with no row, and records with the same key
+/// as one we already matched, are only deleted if `delete_missing` is set.
+/// Records and rows without a key are left alone, they cannot be matched.
+fn plan_sync<T: AirtableSync>(
+    records: Vec<Record<T>>,
+    rows: Vec<T>,
+    delete_missing: bool,
+) -> SyncPlan<T> {
+    let mut plan = SyncPlan {
+        creates: Default::default(),
+        updates: Default::default(),
+        deletes: Default::default(),
+        unchanged: 0,
+        missing: 0,
+        duplicates: 0,
+        skipped: 0,
+    };
+    let no_key = |key: &[String]| key.iter().all(|k| k.is_empty());
+
+    let mut existing: BTreeMap<Vec<String>, Record<T>> = Default::default();
+    for record in records {
+        let key = record.fields.key();
+        if no_key(&key) {
+            plan.skipped += 1;
+            continue;
+        }
+
+        // If a row ended up in Airtable twice, we match on the first one.
+        if existing.contains_key(&key) {
+            if delete_missing {
+                plan.deletes.extend(record.id);
+            } else {
+                plan.duplicates += 1;
+            }
+            continue;
+        }
+        existing.insert(key, record);
+    }
+
+    for mut row in rows {
+        let key = row.key();
+        if no_key(&key) {
+            plan.skipped += 1;
+            continue;
+        }
+
+        match existing.remove(&key) {
+            Some(mut record) => {
+                row.keep_airtable_fields(&record.fields);
+                if record.fields == row {
+                    plan.unchanged += 1;
+                    continue;
+                }
+
+                record.fields = row;
+                plan.updates.push(record);
+            }
+            None => {
+                // New records start with the values in our database.
+                plan.creates.push(Record {
+                    id: None,
+                    created_time: None,
+                    fields: row,
+                });
+            }
+        }
+    }
+
+    // Whatever is left in Airtable has no row in our database.
+    if delete_missing {
+        plan.deletes
+            .extend(existing.values().filter_map(|r| r.id.clone()));
+    } else {
+        plan.missing = existing.len();
+    }
+
+    plan
+}
+
+/// Sync the rows from our database to their table in Airtable. Records that
+/// changed are updated and new rows are created. Records for rows that no
+/// longer exist are deleted if the table has `delete_missing` set.
+///
+/// The table is checked against our struct first. If someone changed it in
+/// Airtable, we return the drift and leave the table alone.
+pub async fn sync_airtable<T: AirtableSync>(
+    rows: Vec<T>,
+) -> Result<SyncReport, SyncError> {
+    let table = airtable_table(T::airtable_table())?;
+
+    // Initialize the Airtable client.
+    let airtable = table.client();
+
+    if let Some(drift) = schema_drift::<T>(&airtable, &table).await? {
+        return Err(SyncError::Drift(drift));
+    }
+
+    let mut report = SyncReport::default();
+    let mut records: Vec<Record<T>> = Default::default();
+    for record in airtable
+        .list_records::<T>(&table.table, &table.view, vec![])
+        .await?
+    {
+        match record {
+            Ok(r) => records.push(r),
+            Err(e) => {
+                println!("skipping {} record: {}", table.table, e);
+                report.skipped += 1;
+            }
+        }
+    }
+
+    let plan = plan_sync(records, rows, table.delete_missing);
+    report.unchanged = plan.unchanged;
+    report.missing = plan.missing;
+    report.duplicates = plan.duplicates;
+    report.skipped += plan.skipped;
+
+    // The client sends these in chunks of 10, the most Airtable takes at once.
+    report.updated = airtable
+        .update_records(&table.table, plan.updates)
+        .await
+        .map_err(|e| e.error)?
+        .len();
+    report.created = airtable
+        .create_records(&table.table, plan.creates)
+        .await
+        .map_err(|e| e.error)?
+        .len();
+    report.deleted = airtable
+        .delete_records(&table.table, plan.deletes)
+        .await
+        .map_err(|e| e.error)?
+        .len();
+
+    Ok(report)
+}
+
+/// The tables we copy edits in Airtable back from, by their logical name,
+/// with the fields Airtable owns in them. When Airtable tells us one of these
+/// fields changed, we copy it back into our database.
+fn webhook_tables() -> [(&'static str, &'static [&'static str]); 2] {
+    [
+        (Applicant::airtable_table(), Applicant::airtable_fields()),
+        (RFD::airtable_table(), RFD::airtable_fields()),
+    ]
+}
+
+/// Get the secret Airtable signs the pings for a webhook with, from
+/// `AIRTABLE_WEBHOOK_SECRET_<ID>`, where the id is in uppercase. Airtable
+/// only returns the secret when the webhook is created.
+pub fn airtable_webhook_secret(webhook_id: &str) -> Option<String> {
+    match env::var(format!(
+        "AIRTABLE_WEBHOOK_SECRET_{}",
+        webhook_id.to_uppercase()
+    )) {
+        Ok(s) if !s.is_empty() => Some(s),
+        _ => None,
+    }
+}
+
+/// Verify a webhook ping came from Airtable. The `X-Airtable-Content-MAC`
+/// header is an HMAC-SHA256 of the body, keyed with the secret of the
+/// webhook.
+///
+/// Docs:
+/// https://airtable.com/developers/web/api/webhooks-overview#webhook-notification-delivery
+pub fn verify_airtable_webhook(
+    mac_secret_base64: &str,
+    body: &[u8],
+    mac: &str,
+) -> bool {
+    let secret = match base64::decode(mac_secret_base64) {
+        Ok(s) if !s.is_empty() => s,
+        _ => return false,
+    };
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &secret);
+    let expected = format!(
+        "hmac-sha256={}",
+        hmac::sign(&key, body)
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    );
+
+    constant_time::verify_slices_are_equal(expected.as_bytes(), mac.as_bytes())
+        .is_ok()
+}
+
+/// Handle a ping from an Airtable webhook: fetch the changes since the last
+/// one and copy the Airtable-owned fields that changed back into our
+/// database. Returns the number of rows we updated.
+///
+/// We keep the cursor of the next payload for each webhook in the database,
+/// so a restart does not apply every payload again.
+pub async fn handle_airtable_webhook(
+    db: &Database,
+    notification: &WebhookNotification,
+) -> Result<usize, SyncError> {
+    let base_id = &notification.base.id;
+    let webhook_id = &notification.webhook.id;
+
+    // Initialize the Airtable client.
+    let airtable = Airtable::new(airtable_api_key(), base_id);
+
+    // Airtable starts counting payloads at 1.
+    let cursor =
+        match db.get_airtable_webhook_cursor_by_webhook_id(webhook_id)? {
+            Some(c) => c.next_cursor as u64,
+            None => 1,
+        };
+    let payloads = airtable.list_webhook_payloads(webhook_id, cursor).await?;
+
+    // Collect the fields that changed for each record, by table.
+    let mut changes: BTreeMap<String, BTreeMap<String, Vec<String>>> =
+        Default::default();
+    for payload in payloads.payloads {
+        for (table_id, table) in payload.changed_tables_by_id {
+            let records = changes.entry(table_id).or_default();
+            for (record_id, record) in table.changed_records_by_id {
+                records.entry(record_id).or_default().extend(
+                    record.current.cell_values_by_field_id.keys().cloned(),
+                );
+            }
+        }
+    }
+
+    let mut updated = 0;
+    if !changes.is_empty() {
+        let config = airtable_config()?;
+        for table in airtable.get_tables().await? {
+            let records = match changes.get(&table.id) {
+                Some(r) => r,
+                None => continue,
+            };
+
+            // Find the logical name of the table, so we know what is in it.
+            let name = config.tables.iter().find_map(|(name, t)| {
+                if &t.base_id == base_id
+                    && (t.table == table.name || t.table == table.id)
+                {
+                    Some(name.as_str())
+                } else {
+                    None
+                }
+            });
+            let fields =
+                match webhook_tables().iter().find(|(n, _)| Some(*n) == name) {
+                    Some((_, fields)) => *fields,
+                    None => continue,
+                };
+
+            // Only look at the records where one of our fields changed.
+            let field_ids: Vec<&str> = table
+                .fields
+                .iter()
+                .filter(|f| fields.contains(&f.name.as_str()))
+                .map(|f| f.id.as_str())
+                .collect();
+            let record_ids: Vec<&String> = records
+                .iter()
+                .filter(|(_, changed)| {
+                    changed.iter().any(|f| field_ids.contains(&f.as_str()))
+                })
+                .map(|(id, _)| id)
+                .collect();
+
+            for id in record_ids {
+                let changed = match name {
+                    Some("applicants") => {
+                        apply_airtable_change::<Applicant, _, _>(
+                            db,
+                            &airtable,
+                            &table.id,
+                            id,
+                            fields,
+                            |db, a| {
+                                db.get_applicant_by_email_and_sheet_id(
+                                    &a.email,
+                                    &a.sheet_id,
+                                )
+                            },
+                            |db, row| db.upsert_applicant(&row.clone().into()),
+                        )
+                        .await?
+                    }
+                    Some("rfds") => {
+                        apply_airtable_change::<RFD, _, _>(
+                            db,
+                            &airtable,
+                            &table.id,
+                            id,
+                            fields,
+                            |db, rfd| db.get_rfd_by_number(rfd.number),
+                            |db, row| db.upsert_rfd(&row.clone().into()),
+                        )
+                        .await?
+                    }
+                    _ => false,
+                };
+                if changed {
+                    updated += 1;
+                }
+            }
+        }
+    }
+
+    db.upsert_airtable_webhook_cursor(&NewAirtableWebhookCursor {
+        webhook_id: webhook_id.to_string(),
+        next_cursor: payloads.cursor as i64,
+    })?;
+
+    Ok(updated)
+}
+
+/// Copy the Airtable-owned fields of a record into its row in our database.
+/// Returns true if the row changed.
+async fn apply_airtable_change<T, G, U>(
+    db: &Database,
+    airtable: &Airtable,
+    table: &str,
+    record_id: &str,
+    fields: &[&str],
+    get: G,
+    upsert: U,
+) -> Result<bool, SyncError>
+where
+    T: AirtableSync,
+    G: Fn(&Database, &T) -> Result<Option<T>, DbError>,
+    U: Fn(&Database, &T) -> Result<T, DbError>,
+{
+    // Get the whole record, the webhook payloads format cells differently
+    // than the rest of the API.
+    let mut record: Record<Value> =
+        airtable.get_record(table, record_id).await?;
+    fill_empty_fields::<T>(&mut record.fields);
+    let in_airtable: T =
+        serde_json::from_value(record.fields.clone()).map_err(Error::from)?;
+
+    let row = match get(db, &in_airtable)? {
+        Some(row) => row,
+        None => {
+            println!(
+                "skipping {} record {}: row {:?} is not in the database",
+                table,
+                record_id,
+                in_airtable.key()
+            );
+            return Ok(false);
+        }
+    };
+
+    let merged = merge_airtable_fields(&row, &record.fields, fields)
+        .map_err(Error::from)?;
+    if merged == row {
+        return Ok(false);
+    }
+
+    upsert(db, &merged)?;
+
+    Ok(true)
+}
+
+/// Copy the fields with the given names in Airtable from the fields of the
+/// record into the row. A field that is empty in Airtable is set to the
+/// empty value of its type.
+fn merge_airtable_fields<T: AirtableSync>(
+    row: &T,
+    record: &Value,
+    fields: &[&str],
+) -> Result<T, serde_json::Error> {
+    let mut merged = serde_json::to_value(row)?;
+    let mut record = record.clone();
+    fill_empty_fields::<T>(&mut record);
+
+    if let (Some(merged), Some(record)) =
+        (merged.as_object_mut(), record.as_object())
+    {
+        for field in fields {
+            if let Some(value) = record.get(*field) {
+                merged.insert(field.to_string(), value.clone());
+            }
+        }
+    }
+
+    serde_json::from_value(merged)
+}
+
+/// Airtable leaves empty cells out of a record. Add the fields of our struct
+/// that are missing from the fields of a record, with the value of an empty
+/// cell for their type.
+fn fill_empty_fields<T: JsonSchema>(fields: &mut Value) {
+    let root = schema_for!(T);
+    let (properties, fields) =
+        match (&root.schema.object, fields.as_object_mut()) {
+            (Some(object), Some(fields)) => (&object.properties, fields),
+            _ => return,
+        };
+
+    for (name, schema) in properties.iter() {
+        if !fields.contains_key(name) {
+            fields.insert(name.to_string(), empty_value(schema, &root));
+        }
+    }
+}
+
+/// The value of an empty cell for a field: null if it is optional, otherwise
+/// the empty value of its type.
+fn empty_value(schema: &Schema, root: &RootSchema) -> Value {
+    let types = schema_types(schema, root);
+    if types.contains(&InstanceType::Null) {
+        return Value::Null;
+    }
+
+    match types.first() {
+        Some(InstanceType::Array) => Value::Array(vec![]),
+        Some(InstanceType::Boolean) => Value::Bool(false),
+        Some(InstanceType::Integer) | Some(InstanceType::Number) => 0.into(),
+        Some(InstanceType::Object) => Value::Object(Default::default()),
+        Some(InstanceType::String) => Value::String(String::new()),
+        _ => Value::Null,
+    }
+}
+
+/// Extend the life of the webhooks on the bases of our tables that we have
+/// secrets for, Airtable turns webhooks off after 7 days otherwise.
+pub async fn refresh_airtable_webhooks() -> Result<(), SyncError> {
+    let config = airtable_config()?;
+
+    let mut base_ids: Vec<&str> = webhook_tables()
+        .iter()
+        .filter_map(|(name, _)| config.tables.get(*name))
+        .map(|t| t.base_id.as_str())
+        .collect();
+    base_ids.sort_unstable();
+    base_ids.dedup();
+
+    for base_id in base_ids {
+        // Initialize the Airtable client.
+        let airtable = Airtable::new(airtable_api_key(), base_id);
+
+        for webhook in airtable.list_webhooks().await? {
+            if airtable_webhook_secret(&webhook.id).is_none() {
+                continue;
+            }
+
+            let expiration = airtable.refresh_webhook(&webhook.id).await?;
+            println!(
+                "refreshed airtable webhook {} on base {}, expires {}",
+                webhook.id,
+                base_id,
+                expiration.unwrap_or_default()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use airtable_api::{Field, FieldType, Record, Table};
+    use schemars::schema::InstanceType;
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    use crate::airtable::{
+        check_schema, merge_airtable_fields, plan_sync,
+        verify_airtable_webhook, AirtableConfig, AirtableSync, AirtableTable,
+        DriftProblem, DEFAULT_CONFIG,
+    };
+
+    #[derive(Debug, Clone, PartialEq, JsonSchema, Deserialize, Serialize)]
+    struct Row {
+        id: i32,
+        name: String,
+        #[serde(default)]
+        links: Vec<String>,
+    }
+
+    impl AirtableSync for Row {
+        fn airtable_table() -> &'static str {
+            "rows"
+        }
+
+        fn key(&self) -> Vec<String> {
+            vec![self.name.clone()]
+        }
+
+        fn airtable_fields() -> &'static [&'static str] {
+            &["links"]
+        }
+
+        fn db_fields() -> &'static [&'static str] {
+            &["id", "name"]
+        }
+
+        fn keep_airtable_fields(&mut self, record: &Self) {
+            self.links = record.links.clone();
+        }
+    }
+
+    fn row(id: i32, name: &str, links: Vec<&str>) -> Row {
+        Row {
+            id,
+            name: name.to_string(),
+            links: links.into_iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    fn record(record_id: &str, fields: Row) -> Record<Row> {
+        Record {
+            id: Some(record_id.to_string()),
+            created_time: None,
+            fields,
+        }
+    }
+
+    fn plan_sync_input() -> (Vec<Record<Row>>, Vec<Row>) {
+        let records = vec![
+            record("rec1", row(1, "same", vec!["recA"])),
+            record("rec2", row(2, "renumbered", vec!["recB"])),
+            record("rec3", row(3, "missing", vec![])),
+            record("rec4", row(1, "same", vec!["recA"])),
+            record("rec5", row(4, "", vec!["recD"])),
+            record("rec6", row(6, "", vec![])),
+        ];
+        let rows = vec![
+            row(1, "same", vec![]),
+            row(7, "renumbered", vec![]),
+            row(5, "created", vec!["recC"]),
+        ];
+
+        (records, rows)
+    }
+
+    #[test]
+    fn test_plan_sync() {
+        let (records, rows) = plan_sync_input();
+        let plan = plan_sync(records, rows, true);
+
+        // The links only exist in Airtable so they do not count as changes.
+        assert_eq!(plan.unchanged, 1);
+
+        // Rows are matched on their key, so a new id is just an update.
+        assert_eq!(plan.updates.len(), 1);
+        assert_eq!(plan.updates[0].id.as_deref(), Some("rec2"));
+        assert_eq!(plan.updates[0].fields, row(7, "renumbered", vec!["recB"]));
+
+        assert_eq!(plan.creates.len(), 1);
+        assert_eq!(plan.creates[0].id, None);
+        assert_eq!(plan.creates[0].fields, row(5, "created", vec!["recC"]));
+
+        // The duplicate and the record with no row are deleted, the records
+        // without a key are left alone.
+        assert_eq!(plan.deletes, vec!["rec4", "rec3"]);
+        assert_eq!(plan.missing, 0);
+        assert_eq!(plan.duplicates, 0);
+        assert_eq!(plan.skipped, 2);
+    }
+
+    #[test]
+    fn test_plan_sync_keeps_missing() {
+        let (records, rows) = plan_sync_input();
+        let plan = plan_sync(records, rows, false);
+
+        // Nothing is deleted, the duplicate and the record with no row are
+        // only counted.
+        assert!(plan.deletes.is_empty());
+        assert_eq!(plan.missing, 1);
+        assert_eq!(plan.duplicates, 1);
+        assert_eq!(plan.skipped, 2);
+        assert_eq!(plan.unchanged, 1);
+        assert_eq!(plan.updates.len(), 1);
+        assert_eq!(plan.creates.len(), 1);
+    }
+
+    #[test]
+    fn test_check_schema() {
+        #[derive(JsonSchema, Serialize)]
+        struct Applicant {
+            id: i32,
+            name: String,
+            email_address: String,
+            sent_email: bool,
+            interviewers: Vec<String>,
+            resume: Option<String>,
+            score: String,
+        }
+
+        let field = |name: &str, field_type: FieldType| Field {
+            id: format!("fld{}", name),
+            name: name.to_string(),
+            field_type,
+            description: Default::default(),
+            options: None,
+        };
+        let table = Table {
+            id: "tbl1".to_string(),
+            name: "Applicants".to_string(),
+            fields: vec![
+                field("id", FieldType::AutoNumber),
+                field("name", FieldType::SingleLineText),
+                field("Email Address", FieldType::Email),
+                field("sent_email", FieldType::SingleLineText),
+                field("interviewers", FieldType::MultipleRecordLinks),
+                field("resume", FieldType::Url),
+                field("score", FieldType::Formula),
+                field("Notes", FieldType::RichText),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            check_schema::<Applicant>(&table),
+            vec![
+                DriftProblem::RenamedField {
+                    field: "email_address".to_string(),
+                    column: "Email Address".to_string(),
+                },
+                DriftProblem::TypeMismatch {
+                    field: "sent_email".to_string(),
+                    field_type: FieldType::SingleLineText,
+                    ours: vec![InstanceType::Boolean],
+                },
+            ]
+        );
+
+        let table = Table {
+            fields: vec![field("id", FieldType::Number)],
+            ..table
+        };
+        assert!(check_schema::<Applicant>(&table)
+            .contains(&DriftProblem::MissingField("name".to_string())));
+    }
+
+    #[test]
+    fn test_airtable_config() {
+        let config = AirtableConfig::from_toml(DEFAULT_CONFIG).unwrap();
+        let rfds = config.table("rfds").unwrap();
+        assert_eq!(rfds.table, "RFDs");
+        assert_eq!(rfds.view, "Grid view");
+        assert!(config.table("nope").is_err());
+
+        let config = AirtableConfig::from_toml(
+            r#"
+            [applicants]
+            base_id = "appStaging"
+            table = "Applicants"
+            view = "Everyone"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.table("applicants").unwrap(),
+            &AirtableTable {
+                base_id: "appStaging".to_string(),
+                table: "Applicants".to_string(),
+                view: "Everyone".to_string(),
+                delete_missing: false,
+            }
+        );
+
+        // Every table needs a base and a name.
+        assert!(AirtableConfig::from_toml("[rfds]\ntable = \"RFDs\"").is_err());
+    }
+
+    #[test]
+    fn test_merge_airtable_fields() {
+        let ours = row(1, "ours", vec!["recA"]);
+        let merged = merge_airtable_fields(
+            &ours,
+            &json!({"id": 1, "name": "theirs", "links": ["recB"]}),
+            &["links"],
+        )
+        .unwrap();
+        assert_eq!(merged, row(1, "ours", vec!["recB"]));
+
+        // Airtable leaves out empty cells, so clearing a field in Airtable
+        // clears it in our row too.
+        let cleared =
+            merge_airtable_fields(&ours, &json!({"id": 1}), &["name", "links"])
+                .unwrap();
+        assert_eq!(cleared, row(1, "", vec![]));
+    }
+
+    #[test]
+    fn test_verify_airtable_webhook() {
+        let body = br#"{"base":{"id":"app1"}}"#;
+        let mac = "hmac-sha256=\
+            1d0cbcba0b93659584cc9494f435d7c15c8fb9cd6e190d31797f36e9fdd8680b";
+
+        assert!(verify_airtable_webhook("c2VjcmV0", body, mac));
+        assert!(!verify_airtable_webhook("c2VjcmV0", b"{}", mac));
+        assert!(!verify_airtable_webhook("b3RoZXI=", body, mac));
+        assert!(!verify_airtable_webhook("", body, mac));
+    }
+}
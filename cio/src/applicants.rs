@@ -1,116 +1,78 @@
-use std::collections::BTreeMap;
 use std::env;
 use std::fs;
 use std::io::{copy, stderr, stdout, Write};
 use std::process::Command;
 
-use airtable_api::{Airtable, Record};
 use google_drive::GoogleDrive;
 use html2text::from_read;
 use hubcaps::issues::{IssueListOptions, State};
 use pandoc::OutputKind;
 use sendgrid_api::SendGrid;
 use serde::{Deserialize, Serialize};
-use sheets::Sheets;
-
-use crate::airtable::{
-    airtable_api_key, AIRTABLE_APPLICATIONS_TABLE,
-    AIRTABLE_BASE_ID_RECURITING_APPLICATIONS, AIRTABLE_GRID_VIEW,
-};
-use crate::db::Database;
-use crate::models::{Applicant, NewApplicant};
+use sheets::{read_rows, A1Range, HeaderMap, Sheets};
+
+use crate::airtable::{sync_airtable, SyncError};
+use crate::db::{log_or_fail, Database, DbError};
+use crate::models::NewApplicant;
 use crate::slack::{get_hiring_channel_post_url, post_to_channel};
 use crate::utils::{authenticate_github, get_gsuite_token, github_org};
 
-/// The data type for a Google Sheet applicant columns, we use this when
-/// parsing the Google Sheets for applicants.
+/// A row of a Google Sheet of applications, as the form writes it. We use
+/// this when parsing the Google Sheets for applicants.
 #[derive(Debug, Default, Deserialize, Serialize)]
-pub struct ApplicantSheetColumns {
-    pub timestamp: usize,
-    pub name: usize,
-    pub email: usize,
-    pub location: usize,
-    pub phone: usize,
-    pub github: usize,
-    pub portfolio: usize,
-    pub website: usize,
-    pub linkedin: usize,
-    pub resume: usize,
-    pub materials: usize,
-    pub status: usize,
-    pub sent_email_received: usize,
-    pub value_reflected: usize,
-    pub value_violated: usize,
-    pub value_in_tension_1: usize,
-    pub value_in_tension_2: usize,
+#[serde(default)]
+pub struct ApplicantRow {
+    pub timestamp: String,
+    pub name: String,
+    pub email: String,
+    pub location: String,
+    pub phone: String,
+    pub github: String,
+    pub portfolio: String,
+    pub website: String,
+    pub linkedin: String,
+    pub resume: String,
+    pub materials: String,
+    pub status: String,
+    /// If we sent them an email that we received their application. We only
+    /// send it if the cell says `FALSE`.
+    #[serde(default = "default_sent_email_received")]
+    pub sent_email_received: bool,
+    pub value_reflected: String,
+    pub value_violated: String,
+    pub value_in_tension_1: String,
+    pub value_in_tension_2: String,
 }
 
-impl ApplicantSheetColumns {
-    /// Parse the sheet columns from Google Sheets values.
-    pub fn parse(values: &[Vec<String>]) -> Self {
-        // Iterate over the columns.
-        // TODO: make this less horrible
-        let mut columns: ApplicantSheetColumns = Default::default();
-
-        // Get the first row.
-        let row = values.get(0).unwrap();
-
-        for (index, col) in row.iter().enumerate() {
-            let c = col.to_lowercase();
+fn default_sent_email_received() -> bool {
+    true
+}
 
-            if c.contains("timestamp") {
-                columns.timestamp = index;
-            }
-            if c.contains("name") {
-                columns.name = index;
-            }
-            if c.contains("email address") {
-                columns.email = index;
-            }
-            if c.contains("location") {
-                columns.location = index;
-            }
-            if c.contains("phone") {
-                columns.phone = index;
-            }
-            if c.contains("github") {
-                columns.github = index;
-            }
-            if c.contains("portfolio url") {
-                columns.portfolio = index;
-            }
-            if c.contains("website") {
-                columns.website = index;
-            }
-            if c.contains("linkedin") {
-                columns.linkedin = index;
-            }
-            if c.contains("resume") {
-                columns.resume = index;
-            }
-            if c.contains("materials") {
-                columns.materials = index;
-            }
-            if c.contains("status") {
-                columns.status = index;
-            }
-            if c.contains("value reflected") {
-                columns.value_reflected = index;
-            }
-            if c.contains("value violated") {
-                columns.value_violated = index;
-            }
-            if c.contains("value in tension [1") {
-                columns.value_in_tension_1 = index;
-            }
-            if c.contains("value in tension [2") {
-                columns.value_in_tension_2 = index;
-            }
-            if c.contains("sent email that we received their application") {
-                columns.sent_email_received = index;
-            }
-        }
-        columns
+impl ApplicantRow {
+    /// The columns of the fields in the sheets. The headers are the
+    /// questions of the form, so we look for a phrase in them.
+    pub fn headers() -> HeaderMap {
+        HeaderMap::new()
+            .field_containing("timestamp", &["timestamp"])
+            .field_containing("name", &["name"])
+            .field_containing("email", &["email address"])
+            .field_containing("location", &["location"])
+            .field_containing("phone", &["phone"])
+            .field_containing("github", &["github"])
+            .field_containing("portfolio", &["portfolio url"])
+            .field_containing("website", &["website"])
+            .field_containing("linkedin", &["linkedin"])
+            .field_containing("resume", &["resume"])
+            .field_containing("materials", &["materials"])
+            .field_containing("status", &["status"])
+            .field_containing(
+                "sent_email_received",
+                &["sent email that we received their application"],
+            )
+            .field_containing("value_reflected", &["value reflected"])
+            .field_containing("value_violated", &["value violated"])
+            .field_containing("value_in_tension_1", &["value in tension [1"])
+            .field_containing("value_in_tension_2", &["value in tension [2"])
     }
 }
 
@@ -364,7 +326,10 @@ pub async fn get_raw_applicants() -> Vec<NewApplicant> {
     for (sheet_name, sheet_id) in sheets {
         // Get the values in the sheet.
         let sheet_values = sheets_client
-            .get_values(&sheet_id, "Form Responses 1!A1:S1000".to_string())
+            .get_values(
+                &sheet_id,
+                A1Range::sheet("Form Responses 1").to_string(),
+            )
             .await
             .unwrap();
         let values = sheet_values.values.unwrap();
@@ -376,30 +341,47 @@ pub async fn get_raw_applicants() -> Vec<NewApplicant> {
             );
         }
 
-        // Parse the sheet columns.
-        let columns = ApplicantSheetColumns::parse(&values);
-
-        // Iterate over the rows.
-        for (row_index, row) in values.iter().enumerate() {
-            if row_index == 0 {
-                // Continue the loop since we were on the header row.
-                continue;
-            } // End get header information.
+        // Find the column we mark once we sent the email that we received
+        // their application.
+        let headers = ApplicantRow::headers();
+        let sent_email_received_column =
+            headers.column("sent_email_received", &values[0]);
+
+        // Iterate over the rows, the header is row 0.
+        let rows = read_rows::<ApplicantRow>(&values, &headers);
+        for (row_index, row) in rows.into_iter().enumerate() {
+            let row_index = row_index + 1;
+            let row = match row {
+                Ok(row) => row,
+                Err(e) => {
+                    println!(
+                        "[applicants] skipping row in sheet {}: {}",
+                        sheet_name, e
+                    );
+                    continue;
+                }
+            };
 
             // Break the loop early if we reached an empty row.
-            if row[columns.email].is_empty() {
+            if row.email.is_empty() {
                 break;
             }
 
+            let sent_email_received_cell =
+                sent_email_received_column.map(|column| {
+                    A1Range::sheet("Form Responses 1")
+                        .cell(column, row_index)
+                        .to_string()
+                });
+
             // Parse the applicant out of the row information.
             let (applicant, is_new_applicant) = NewApplicant::parse(
                 &drive_client,
                 &sheets_client,
                 sheet_name,
                 sheet_id,
-                &columns,
                 &row,
-                row_index,
+                sent_email_received_cell,
             )
             .await;
 
@@ -475,86 +457,108 @@ pub async fn email_send_new_applicant_notification(
         .await;
 }
 
-pub async fn refresh_airtable_applicants() {
-    // Initialize the Airtable client.
-    let airtable = Airtable::new(
-        airtable_api_key(),
-        AIRTABLE_BASE_ID_RECURITING_APPLICATIONS,
-    );
-
-    let records = airtable
-        .list_records(AIRTABLE_APPLICATIONS_TABLE, AIRTABLE_GRID_VIEW, vec![])
-        .await
-        .unwrap();
-
-    let mut airtable_applicants: BTreeMap<i32, (Record, Applicant)> =
-        Default::default();
-    for record in records {
-        let fields: Applicant =
-            serde_json::from_value(record.fields.clone()).unwrap();
-
-        airtable_applicants.insert(fields.id, (record, fields));
-    }
-
-    // Initialize our database.
-    let db = Database::new();
-    let applicants = db.get_applicants();
-
-    let mut updated: i32 = 0;
-    for applicant in applicants {
-        // See if we have it in our fields.
-        match airtable_applicants.get(&applicant.id) {
-            Some((r, _in_airtable_fields)) => {
-                let mut record = r.clone();
+pub async fn refresh_airtable_applicants(
+    db: &Database,
+) -> Result<(), SyncError> {
+    let applicants = db.get_applicants()?;
 
-                record.fields = json!(applicant);
+    let report = sync_airtable(applicants).await?;
+    println!("synced applicants to airtable: {}", report);
 
-                airtable
-                    .update_records(
-                        AIRTABLE_APPLICATIONS_TABLE,
-                        vec![record.clone()],
-                    )
-                    .await
-                    .unwrap();
-
-                updated += 1;
-            }
-            None => {
-                // Create the record.
-                applicant.push_to_airtable().await;
-            }
-        }
-    }
-
-    println!("updated {} applicants", updated);
+    Ok(())
 }
 
 // Sync the applicants with our database.
-pub async fn refresh_db_applicants() {
+pub async fn refresh_db_applicants(db: &Database) -> Result<(), DbError> {
     let applicants = get_raw_applicants().await;
 
-    // Initialize our database.
-    let db = Database::new();
-
     // Sync applicants.
-    for applicant in applicants {
-        db.upsert_applicant(&applicant);
+    for mut applicant in applicants {
+        // The hiring team edits fields like the status in Airtable, so once
+        // we have an applicant we keep those instead of the sheet's.
+        let upserted = db
+            .get_applicant_by_email_and_sheet_id(
+                &applicant.email,
+                &applicant.sheet_id,
+            )
+            .and_then(|existing| {
+                if let Some(existing) = existing {
+                    applicant.keep_airtable_fields(&existing);
+                }
+                db.upsert_applicant(&applicant)
+            });
+        log_or_fail(
+            upserted,
+            format_args!("upserting applicant {}", applicant.email),
+        )?;
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use sheets::read_rows;
+
     use crate::applicants::{
-        refresh_airtable_applicants, refresh_db_applicants,
+        refresh_airtable_applicants, refresh_db_applicants, ApplicantRow,
     };
+    use crate::db::Database;
+
+    #[test]
+    fn test_applicant_rows() {
+        let strings = |s: &[&str]| -> Vec<String> {
+            s.iter().map(|s| s.to_string()).collect()
+        };
+        let values = vec![
+            strings(&[
+                "Timestamp",
+                "Email Address",
+                "Name",
+                "LinkedIn profile URL",
+                "Status",
+                "Sent email that we received their application",
+            ]),
+            strings(&[
+                "9/1/2020 10:00:00",
+                "ada@example.com",
+                "Ada",
+                "",
+                "Next steps",
+                "FALSE",
+            ]),
+            // The sheet has no value for the last columns yet.
+            strings(&["9/2/2020 11:00:00", "grace@example.com", "Grace"]),
+        ];
+
+        let headers = ApplicantRow::headers();
+        let rows: Vec<ApplicantRow> = read_rows(&values, &headers)
+            .into_iter()
+            .map(|r| r.unwrap())
+            .collect();
+
+        assert_eq!(rows[0].email, "ada@example.com");
+        assert_eq!(rows[0].status, "Next steps");
+        assert_eq!(rows[0].linkedin, "");
+        assert!(!rows[0].sent_email_received);
+        // Columns the form does not have are empty, not the first column.
+        assert_eq!(rows[0].github, "");
+        assert_eq!(rows[1].name, "Grace");
+        assert_eq!(rows[1].status, "");
+        assert!(rows[1].sent_email_received);
+
+        assert_eq!(headers.column("sent_email_received", &values[0]), Some(5));
+    }
 
     #[tokio::test(threaded_scheduler)]
     async fn test_applicants() {
-        refresh_db_applicants().await;
+        let db = Database::new().unwrap();
+        refresh_db_applicants(&db).await.unwrap();
     }
 
     #[tokio::test(threaded_scheduler)]
     async fn test_applicants_airtable() {
-        refresh_airtable_applicants().await;
+        let db = Database::new().unwrap();
+        refresh_airtable_applicants(&db).await.unwrap();
     }
 }
@@ -0,0 +1,444 @@
+use std::env;
+use std::error;
+use std::fmt;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use dropshot::HttpError;
+use hyper::header::{HeaderMap, AUTHORIZATION};
+use hyper::StatusCode;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+/// How long we keep using a JSON Web Key Set before fetching it again.
+const JWKS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// How long we wait before fetching the JSON Web Key Set again when we see a
+/// key id we don't know. Auth0 rotates its signing keys, but we don't want
+/// every request with a bogus key id to hit the JWKS endpoint.
+const JWKS_MIN_AGE: Duration = Duration::from_secs(60);
+
+/// The configuration for validating the tokens sent to our API.
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// The issuer of the tokens, for Auth0 this is `https://{domain}.auth0.com/`.
+    pub issuer: String,
+    /// The audience the tokens must be issued for, this is the identifier of
+    /// our API in Auth0.
+    pub audience: String,
+    /// The URL of the JSON Web Key Set the tokens are signed with.
+    pub jwks_url: String,
+}
+
+impl AuthConfig {
+    /// Create the configuration for an Auth0 tenant.
+    pub fn for_auth0_domain(domain: &str, audience: &str) -> Self {
+        AuthConfig {
+            issuer: format!("https://{}.auth0.com/", domain),
+            audience: audience.to_string(),
+            jwks_url: format!(
+                "https://{}.auth0.com/.well-known/jwks.json",
+                domain
+            ),
+        }
+    }
+
+    /// Create the configuration from the environment.
+    ///
+    /// `CIO_AUTH0_AUDIENCE` is required, `CIO_AUTH0_DOMAIN` defaults to our
+    /// own tenant.
+    pub fn from_env() -> Result<Self, AuthError> {
+        let domain = env::var("CIO_AUTH0_DOMAIN")
+            .unwrap_or_else(|_| "oxide".to_string());
+        let audience = env::var("CIO_AUTH0_AUDIENCE").map_err(|_| {
+            AuthError::Config("CIO_AUTH0_AUDIENCE must be set".to_string())
+        })?;
+
+        Ok(AuthConfig::for_auth0_domain(&domain, &audience))
+    }
+}
+
+/// A JSON Web Key Set.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// A JSON Web Key. We only support RSA keys since that is what Auth0 signs
+/// tokens with.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Jwk {
+    pub kid: String,
+    pub kty: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub alg: String,
+    pub n: String,
+    pub e: String,
+}
+
+/// The claims we care about in a token.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: usize,
+    /// The scopes granted to the token, separated by spaces.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub scope: String,
+    /// The permissions granted to the token, Auth0 sets these when RBAC is
+    /// enabled for the API.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>,
+}
+
+impl Claims {
+    /// Returns true if the token was granted the scope.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope.split_whitespace().any(|s| s == scope)
+            || self.permissions.iter().any(|p| p == scope)
+    }
+}
+
+/// Error type returned when authenticating a request.
+#[derive(Debug)]
+pub enum AuthError {
+    /// The auth configuration is missing or invalid.
+    Config(String),
+    /// The request did not have a bearer token.
+    MissingToken,
+    /// The token could not be decoded or failed validation.
+    InvalidToken(jsonwebtoken::errors::Error),
+    /// The token was signed with a key that is not in the JWKS.
+    UnknownKey(String),
+    /// Fetching the JWKS failed.
+    Jwks(reqwest::Error),
+    /// The token does not have a scope the route requires.
+    MissingScope(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::Config(e) => write!(f, "AuthError: config -> {}", e),
+            AuthError::MissingToken => {
+                write!(f, "AuthError: missing bearer token")
+            }
+            AuthError::InvalidToken(e) => {
+                write!(f, "AuthError: invalid token -> {}", e)
+            }
+            AuthError::UnknownKey(kid) => {
+                write!(f, "AuthError: unknown signing key -> {}", kid)
+            }
+            AuthError::Jwks(e) => write!(f, "AuthError: jwks -> {}", e),
+            AuthError::MissingScope(scope) => {
+                write!(f, "AuthError: missing scope -> {}", scope)
+            }
+        }
+    }
+}
+
+impl error::Error for AuthError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            AuthError::InvalidToken(e) => Some(e),
+            AuthError::Jwks(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for AuthError {
+    fn from(e: reqwest::Error) -> Self {
+        AuthError::Jwks(e)
+    }
+}
+
+impl From<AuthError> for HttpError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::MissingToken
+            | AuthError::InvalidToken(_)
+            | AuthError::UnknownKey(_) => HttpError::for_client_error(
+                None,
+                StatusCode::UNAUTHORIZED,
+                e.to_string(),
+            ),
+            AuthError::MissingScope(_) => HttpError::for_client_error(
+                None,
+                StatusCode::FORBIDDEN,
+                e.to_string(),
+            ),
+            AuthError::Jwks(_) => HttpError::for_unavail(None, e.to_string()),
+            AuthError::Config(_) => {
+                HttpError::for_internal_error(e.to_string())
+            }
+        }
+    }
+}
+
+/// Validates bearer tokens against the JSON Web Key Set of the issuer.
+///
+/// The key set is cached and shared between requests.
+pub struct Authenticator {
+    config: AuthConfig,
+    client: Client,
+    jwks: RwLock<Option<(Instant, Jwks)>>,
+}
+
+impl Authenticator {
+    /// Create a new authenticator.
+    pub fn new(config: AuthConfig) -> Self {
+        Authenticator {
+            config,
+            client: Client::new(),
+            jwks: RwLock::new(None),
+        }
+    }
+
+    /// Validate the bearer token in the request headers and make sure it was
+    /// granted all of the scopes.
+    pub async fn authorize(
+        &self,
+        headers: &HeaderMap,
+        scopes: &[&str],
+    ) -> Result<Claims, AuthError> {
+        let claims = self.validate(bearer_token(headers)?).await?;
+
+        for scope in scopes {
+            if !claims.has_scope(scope) {
+                return Err(AuthError::MissingScope(scope.to_string()));
+            }
+        }
+
+        Ok(claims)
+    }
+
+    /// Validate the token and return its claims.
+    pub async fn validate(&self, token: &str) -> Result<Claims, AuthError> {
+        let header = decode_header(token).map_err(AuthError::InvalidToken)?;
+        let kid = header.kid.unwrap_or_default();
+        let jwk = self.get_key(&kid).await?;
+
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.iss = Some(self.config.issuer.to_string());
+        validation.set_audience(&[&self.config.audience]);
+
+        let data = decode::<Claims>(
+            token,
+            &DecodingKey::from_rsa_components(&jwk.n, &jwk.e),
+            &validation,
+        )
+        .map_err(AuthError::InvalidToken)?;
+
+        Ok(data.claims)
+    }
+
+    /// Get a key from the JSON Web Key Set, fetching the set if our copy is
+    /// too old or doesn't have the key.
+    async fn get_key(&self, kid: &str) -> Result<Jwk, AuthError> {
+        {
+            let cached = self.jwks.read().unwrap();
+            if let Some((fetched, jwks)) = &*cached {
+                let age = fetched.elapsed();
+                if age < JWKS_MAX_AGE {
+                    if let Some(key) = jwks.keys.iter().find(|k| k.kid == kid) {
+                        return Ok(key.clone());
+                    }
+
+                    if age < JWKS_MIN_AGE {
+                        return Err(AuthError::UnknownKey(kid.to_string()));
+                    }
+                }
+            }
+        }
+
+        let jwks: Jwks = self
+            .client
+            .get(&self.config.jwks_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let key = jwks.keys.iter().find(|k| k.kid == kid).cloned();
+        *self.jwks.write().unwrap() = Some((Instant::now(), jwks));
+
+        key.ok_or_else(|| AuthError::UnknownKey(kid.to_string()))
+    }
+}
+
+/// Get the bearer token from the `Authorization` header.
+fn bearer_token(headers: &HeaderMap) -> Result<&str, AuthError> {
+    let value = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .ok_or(AuthError::MissingToken)?;
+
+    if value.len() > 7 && value[..7].eq_ignore_ascii_case("bearer ") {
+        Ok(value[7..].trim())
+    } else {
+        Err(AuthError::MissingToken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use dropshot::HttpError;
+    use hyper::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Response, Server, StatusCode};
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use openssl::rsa::Rsa;
+    use serde::Serialize;
+
+    use crate::auth::{AuthConfig, AuthError, Authenticator, Jwk, Jwks};
+
+    const ISSUER: &str = "https://test.auth0.com/";
+    const AUDIENCE: &str = "https://api.cio.test";
+
+    #[derive(Serialize)]
+    struct TestClaims<'a> {
+        sub: &'a str,
+        iss: &'a str,
+        aud: &'a str,
+        exp: u64,
+        scope: &'a str,
+    }
+
+    /// A locally generated keypair, returned as the private key PEM and the
+    /// public key as a JWK.
+    fn keypair(kid: &str) -> (Vec<u8>, Jwk) {
+        let rsa = Rsa::generate(2048).unwrap();
+        let b64 =
+            |b: Vec<u8>| base64::encode_config(b, base64::URL_SAFE_NO_PAD);
+
+        let jwk = Jwk {
+            kid: kid.to_string(),
+            kty: "RSA".to_string(),
+            alg: "RS256".to_string(),
+            n: b64(rsa.n().to_vec()),
+            e: b64(rsa.e().to_vec()),
+        };
+
+        (rsa.private_key_to_pem().unwrap(), jwk)
+    }
+
+    /// Serve the JWKS from a local server in place of Auth0.
+    fn serve_jwks(jwks: Jwks) -> String {
+        let body = serde_json::to_string(&jwks).unwrap();
+        let make_svc = make_service_fn(move |_| {
+            let body = body.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_| {
+                    let body = body.clone();
+                    async move {
+                        Ok::<_, Infallible>(Response::new(Body::from(body)))
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let url =
+            format!("http://{}/.well-known/jwks.json", server.local_addr());
+        tokio::spawn(server);
+
+        url
+    }
+
+    fn sign(pem: &[u8], kid: &str, claims: &TestClaims) -> HeaderMap {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+        let token =
+            encode(&header, claims, &EncodingKey::from_rsa_pem(pem).unwrap())
+                .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+        );
+        headers
+    }
+
+    fn claims(scope: &str) -> TestClaims {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        TestClaims {
+            sub: "auth0|jess",
+            iss: ISSUER,
+            aud: AUDIENCE,
+            exp: now + 600,
+            scope,
+        }
+    }
+
+    fn authenticator(jwks: Jwks) -> Authenticator {
+        Authenticator::new(AuthConfig {
+            issuer: ISSUER.to_string(),
+            audience: AUDIENCE.to_string(),
+            jwks_url: serve_jwks(jwks),
+        })
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_authorize() {
+        let (pem, jwk) = keypair("test-key");
+        let auth = authenticator(Jwks { keys: vec![jwk] });
+
+        let headers = sign(&pem, "test-key", &claims("hiring:read rfds:read"));
+        let c = auth.authorize(&headers, &["hiring:read"]).await.unwrap();
+        assert_eq!(c.sub, "auth0|jess");
+
+        // A token without the scope is forbidden.
+        let headers = sign(&pem, "test-key", &claims("rfds:read"));
+        let e = auth
+            .authorize(&headers, &["hiring:read"])
+            .await
+            .unwrap_err();
+        assert!(matches!(e, AuthError::MissingScope(_)));
+        let http: HttpError = e.into();
+        assert_eq!(http.status_code, StatusCode::FORBIDDEN);
+
+        // A request without a token is unauthorized.
+        let e = auth.authorize(&HeaderMap::new(), &[]).await.unwrap_err();
+        let http: HttpError = e.into();
+        assert_eq!(http.status_code, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_authorize_invalid_tokens() {
+        let (pem, jwk) = keypair("test-key");
+        let auth = authenticator(Jwks { keys: vec![jwk] });
+
+        // Signed by a key that isn't the one in the JWKS.
+        let (other_pem, _) = keypair("test-key");
+        let headers = sign(&other_pem, "test-key", &claims("hiring:read"));
+        let e = auth.authorize(&headers, &[]).await.unwrap_err();
+        assert!(matches!(e, AuthError::InvalidToken(_)));
+
+        // Signed with a key id that isn't in the JWKS.
+        let headers = sign(&pem, "other-key", &claims("hiring:read"));
+        let e = auth.authorize(&headers, &[]).await.unwrap_err();
+        assert!(matches!(e, AuthError::UnknownKey(_)));
+
+        // Issued for another audience.
+        let mut c = claims("hiring:read");
+        c.aud = "https://api.other.test";
+        let headers = sign(&pem, "test-key", &c);
+        let e = auth.authorize(&headers, &[]).await.unwrap_err();
+        assert!(matches!(e, AuthError::InvalidToken(_)));
+
+        // Expired.
+        let mut c = claims("hiring:read");
+        c.exp -= 3600;
+        let headers = sign(&pem, "test-key", &c);
+        let e = auth.authorize(&headers, &[]).await.unwrap_err();
+        assert!(matches!(e, AuthError::InvalidToken(_)));
+    }
+}
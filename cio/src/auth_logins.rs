@@ -3,7 +3,6 @@ use std::collections::HashMap;
 use std::env;
 use std::{thread, time};
 
-use airtable_api::{Airtable, Record};
 use chrono::naive::NaiveDateTime;
 use chrono::offset::Utc;
 use chrono::DateTime;
@@ -11,13 +10,9 @@ use chrono_humanize::HumanTime;
 use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 
-use crate::airtable::{
-    airtable_api_key, AIRTABLE_AUTH_USERS_TABLE,
-    AIRTABLE_AUTH_USER_LOGINS_TABLE, AIRTABLE_BASE_ID_CUSTOMER_LEADS,
-    AIRTABLE_GRID_VIEW,
-};
-use crate::db::Database;
-use crate::models::{AuthUser, AuthUserLogin, NewAuthUser, NewAuthUserLogin};
+use crate::airtable::{airtable_table, sync_airtable, SyncError};
+use crate::db::{log_or_fail, Database, DbError};
+use crate::models::{AuthUser, NewAuthUser, NewAuthUserLogin};
 
 /// The data type for an Auth0 user.
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -122,7 +117,10 @@ pub struct Token {
 }
 
 /// List users.
-pub async fn get_auth_users(domain: String, db: &Database) -> Vec<NewAuthUser> {
+pub async fn get_auth_users(
+    domain: String,
+    db: &Database,
+) -> Result<Vec<NewAuthUser>, DbError> {
     let client = Client::new();
     // Get our token.
     let client_id = env::var("CIO_AUTH0_CLIENT_ID").unwrap();
@@ -191,11 +189,17 @@ pub async fn get_auth_users(domain: String, db: &Database) -> Vec<NewAuthUser> {
         // Update our database with all the auth_user_logins.
         for mut auth_user_login in auth_user_logins {
             auth_user_login.email = user.email.to_string();
-            db.upsert_auth_user_login(&auth_user_login);
+            log_or_fail(
+                db.upsert_auth_user_login(&auth_user_login),
+                format_args!(
+                    "upserting auth user login {}",
+                    auth_user_login.log_id
+                ),
+            )?;
         }
     }
 
-    auth_users
+    Ok(auth_users)
 }
 
 // TODO: clean this all up to be an auth0 api library.
@@ -295,119 +299,30 @@ async fn get_auth_users_page(
     resp.json::<Vec<User>>().await.unwrap()
 }
 
-pub async fn refresh_airtable_auth_users() {
-    // Initialize the Airtable client.
-    let airtable =
-        Airtable::new(airtable_api_key(), AIRTABLE_BASE_ID_CUSTOMER_LEADS);
+pub async fn refresh_airtable_auth_users(
+    db: &Database,
+) -> Result<(), SyncError> {
+    let auth_users = db.get_auth_users()?;
 
-    let records = airtable
-        .list_records(
-            AIRTABLE_AUTH_USERS_TABLE,
-            AIRTABLE_GRID_VIEW,
-            vec![
-                "id",
-                "link_to_people",
-                "logins_count",
-                "updated_at",
-                "created_at",
-                "user_id",
-                "last_login",
-                "email_verified",
-                "link_to_auth_user_logins",
-                "last_application_accessed",
-                "company",
-            ],
-        )
-        .await
-        .unwrap();
-
-    let mut airtable_auth_users: BTreeMap<i32, (Record, AuthUser)> =
-        Default::default();
-    for record in records {
-        let fields: AuthUser =
-            serde_json::from_value(record.fields.clone()).unwrap();
-
-        airtable_auth_users.insert(fields.id, (record, fields));
-    }
-
-    // Initialize our database.
-    let db = Database::new();
-    let auth_users = db.get_auth_users();
-
-    let mut updated: i32 = 0;
-    for mut auth_user in auth_users {
-        // See if we have it in our fields.
-        match airtable_auth_users.get(&auth_user.id) {
-            Some((r, in_airtable_fields)) => {
-                let mut record = r.clone();
-
-                if in_airtable_fields.user_id == auth_user.user_id
-                    && in_airtable_fields.last_login == auth_user.last_login
-                    && in_airtable_fields.logins_count == auth_user.logins_count
-                    && in_airtable_fields.last_application_accessed
-                        == auth_user.last_application_accessed
-                    && in_airtable_fields.company == auth_user.company
-                {
-                    // We do not need to update the record.
-                    continue;
-                }
-
-                // Set the link_to_people and link_to_auth_user_logins from the original so it stays intact.
-                auth_user.link_to_people =
-                    in_airtable_fields.link_to_people.clone();
-                auth_user.link_to_auth_user_logins =
-                    in_airtable_fields.link_to_auth_user_logins.clone();
-
-                record.fields = json!(auth_user);
-
-                airtable
-                    .update_records(
-                        AIRTABLE_AUTH_USERS_TABLE,
-                        vec![record.clone()],
-                    )
-                    .await
-                    .unwrap();
-
-                updated += 1;
-            }
-            None => {
-                // Create the record.
-                auth_user.push_to_airtable().await;
-            }
-        }
-    }
+    let report = sync_airtable(auth_users).await?;
+    println!("synced auth_users to airtable: {}", report);
 
-    println!("updated {} auth_users", updated);
+    Ok(())
 }
 
-pub async fn refresh_airtable_auth_user_logins() {
-    // Initialize the Airtable client.
-    let airtable =
-        Airtable::new(airtable_api_key(), AIRTABLE_BASE_ID_CUSTOMER_LEADS);
-
-    let records = airtable
-        .list_records(
-            AIRTABLE_AUTH_USER_LOGINS_TABLE,
-            AIRTABLE_GRID_VIEW,
-            vec![],
-        )
-        .await
-        .unwrap();
-
-    let mut airtable_auth_user_logins: BTreeMap<i32, (Record, AuthUserLogin)> =
-        Default::default();
-    for record in records {
-        let fields: AuthUserLogin =
-            serde_json::from_value(record.fields.clone()).unwrap();
+pub async fn refresh_airtable_auth_user_logins(
+    db: &Database,
+) -> Result<(), SyncError> {
+    let users = airtable_table("auth_users")?;
 
-        airtable_auth_user_logins.insert(fields.id, (record, fields));
-    }
+    // Initialize the Airtable client.
+    let airtable = users.client();
 
     // We need to get the user records to link the log records to the user table.
     let user_records = airtable
-        .list_records(
-            AIRTABLE_AUTH_USERS_TABLE,
-            AIRTABLE_GRID_VIEW,
+        .list_records::<AuthUser>(
+            &users.table,
+            &users.view,
             vec![
                 "id",
                 "link_to_people",
@@ -422,85 +337,45 @@ pub async fn refresh_airtable_auth_user_logins() {
                 "company",
             ],
         )
-        .await
-        .unwrap();
+        .await?;
 
     let mut airtable_auth_users: BTreeMap<String, String> = Default::default();
     for user_record in user_records {
-        let user_fields: AuthUser =
-            serde_json::from_value(user_record.fields.clone()).unwrap();
-
-        airtable_auth_users
-            .insert(user_fields.user_id, user_record.id.unwrap());
+        match user_record {
+            Ok(r) => {
+                airtable_auth_users.insert(r.fields.user_id, r.id.unwrap());
+            }
+            Err(e) => println!("skipping auth_user record: {}", e),
+        }
     }
 
-    // Initialize our database.
-    let db = Database::new();
-    let auth_user_logins = db.get_auth_user_logins();
-
-    let mut updated: i32 = 0;
-    for mut auth_user_login in auth_user_logins {
+    let mut auth_user_logins = db.get_auth_user_logins()?;
+    for auth_user_login in auth_user_logins.iter_mut() {
         // Set the link_to_auth_user to the right user.
-        let user_record_id = if let Some(u) =
-            airtable_auth_users.get(&auth_user_login.user_id)
-        {
-            u.to_string()
-        } else {
-            "".to_string()
-        };
-        if !user_record_id.is_empty() {
-            auth_user_login.link_to_auth_user = vec![user_record_id];
-        }
-
-        // See if we have it in our fields.
-        match airtable_auth_user_logins.get(&auth_user_login.id) {
-            Some((r, in_airtable_fields)) => {
-                let mut record = r.clone();
-
-                if in_airtable_fields.log_id == auth_user_login.log_id
-                    && in_airtable_fields.date == auth_user_login.date
-                    && in_airtable_fields.id == auth_user_login.id
-                    && in_airtable_fields.email == auth_user_login.email
-                    && in_airtable_fields.link_to_auth_user
-                        == auth_user_login.link_to_auth_user
-                {
-                    // We do not need to update the record.
-                    continue;
-                }
-
-                record.fields = json!(auth_user_login);
-
-                airtable
-                    .update_records(
-                        AIRTABLE_AUTH_USER_LOGINS_TABLE,
-                        vec![record.clone()],
-                    )
-                    .await
-                    .unwrap();
-
-                updated += 1;
-            }
-            None => {
-                // Create the record.
-                auth_user_login.push_to_airtable().await;
-            }
+        if let Some(u) = airtable_auth_users.get(&auth_user_login.user_id) {
+            auth_user_login.link_to_auth_user = vec![u.to_string()];
         }
     }
 
-    println!("updated {} auth_user_logins", updated);
+    let report = sync_airtable(auth_user_logins).await?;
+    println!("synced auth_user_logins to airtable: {}", report);
+
+    Ok(())
 }
 
 // Sync the auth_users with our database.
-pub async fn refresh_db_auth() {
-    // Initialize our database.
-    let db = Database::new();
-
-    let auth_users = get_auth_users("oxide".to_string(), &db).await;
+pub async fn refresh_db_auth(db: &Database) -> Result<(), DbError> {
+    let auth_users = get_auth_users("oxide".to_string(), db).await?;
 
     // Sync auth users.
     for auth_user in auth_users {
-        db.upsert_auth_user(&auth_user);
+        log_or_fail(
+            db.upsert_auth_user(&auth_user),
+            format_args!("upserting auth user {}", auth_user.user_id),
+        )?;
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -509,19 +384,23 @@ mod tests {
         refresh_airtable_auth_user_logins, refresh_airtable_auth_users,
         refresh_db_auth,
     };
+    use crate::db::Database;
 
     #[tokio::test(threaded_scheduler)]
     async fn test_auth_refresh_db() {
-        refresh_db_auth().await;
+        let db = Database::new().unwrap();
+        refresh_db_auth(&db).await.unwrap();
     }
 
     #[tokio::test(threaded_scheduler)]
     async fn test_auth_users_airtable() {
-        refresh_airtable_auth_users().await;
+        let db = Database::new().unwrap();
+        refresh_airtable_auth_users(&db).await.unwrap();
     }
 
     #[tokio::test(threaded_scheduler)]
     async fn test_auth_user_logins_airtable() {
-        refresh_airtable_auth_user_logins().await;
+        let db = Database::new().unwrap();
+        refresh_airtable_auth_user_logins(&db).await.unwrap();
     }
 }
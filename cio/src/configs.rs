@@ -1,17 +1,22 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
+use std::error;
+use std::fmt;
 use std::fs;
 use std::str::from_utf8;
 
+use chrono::{DateTime, Utc};
 use clap::ArgMatches;
+use dropshot::HttpError;
 use futures_util::stream::TryStreamExt;
 use hubcaps::Github;
 use macros::db_struct;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::db::Database;
+use crate::db::{log_or_fail, Database, DbError};
 use crate::schema::{
-    buildings, conference_rooms, github_labels, groups, links, users,
+    buildings, conference_rooms, config_api_changes, github_labels, groups,
+    links, users,
 };
 use crate::utils::github_org;
 
@@ -64,6 +69,7 @@ impl Config {
 /// The data type for a user.
 #[db_struct {
     new_name = "User",
+    match_on = ["username"],
 }]
 #[serde(rename_all = "camelCase")]
 #[derive(
@@ -121,6 +127,7 @@ pub struct UserConfig {
 /// The data type for a group. This applies to Google Groups.
 #[db_struct {
     new_name = "Group",
+    match_on = ["name"],
 }]
 #[serde(rename_all = "camelCase")]
 #[derive(
@@ -251,6 +258,7 @@ pub struct GroupConfig {
 /// The data type for a building.
 #[db_struct {
     new_name = "Building",
+    match_on = ["name"],
 }]
 #[derive(
     Debug,
@@ -279,6 +287,7 @@ pub struct BuildingConfig {
 /// through GSuite or Zoom.
 #[db_struct {
     new_name = "ConferenceRoom",
+    match_on = ["name"],
 }]
 #[derive(
     Debug,
@@ -307,6 +316,7 @@ pub struct ResourceConfig {
 /// `{name}.corp.oxide.compuer` by the `shorturls` subcommand.
 #[db_struct {
     new_name = "Link",
+    match_on = ["name"],
 }]
 #[derive(
     Debug,
@@ -334,6 +344,7 @@ pub struct LinkConfig {
 /// in our organization.
 #[db_struct {
     new_name = "GithubLabel",
+    match_on = ["name"],
 }]
 #[derive(
     Debug,
@@ -353,6 +364,281 @@ pub struct LabelConfig {
     pub color: String,
 }
 
+/// A config that was created, updated or deleted through the API. Until the
+/// configs repository gets a newer commit, the refresh from the repository
+/// leaves the record alone so it does not undo the change.
+#[db_struct {
+    new_name = "ConfigApiChange",
+    match_on = ["section", "name"],
+}]
+#[derive(
+    Debug, Insertable, AsChangeset, PartialEq, Clone, Deserialize, Serialize,
+)]
+#[table_name = "config_api_changes"]
+pub struct NewConfigApiChange {
+    /// The section of the config files, see `ConfigRecord::SECTION`.
+    pub section: String,
+    /// The key of the record in its section.
+    pub name: String,
+    pub deleted: bool,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Error returned when a config is not valid.
+#[derive(Debug)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new(field: &str, message: &str) -> Self {
+        ValidationError {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ValidationError: {} -> {}", self.field, self.message)
+    }
+}
+
+impl error::Error for ValidationError {}
+
+impl From<ValidationError> for HttpError {
+    fn from(e: ValidationError) -> Self {
+        HttpError::for_bad_request(None, e.to_string())
+    }
+}
+
+/// A config that can be changed through the API as well as in the configs
+/// repository.
+pub trait ConfigRecord: Serialize {
+    /// The section of the config files the record lives in, for example
+    /// `users` for `[users.jess]`.
+    const SECTION: &'static str;
+
+    /// The key of the record in its section.
+    fn key(&self) -> &str;
+
+    /// Set the key of the record.
+    fn set_key(&mut self, key: &str);
+
+    /// Validate the record before it gets written to the database.
+    fn validate(&self) -> Result<(), ValidationError>;
+
+    /// Export the record as a patch for the TOML in the configs repository.
+    fn to_toml_patch(&self) -> Result<String, toml::ser::Error> {
+        let mut records = BTreeMap::new();
+        records.insert(self.key(), self);
+        let mut section = BTreeMap::new();
+        section.insert(Self::SECTION, records);
+
+        toml::to_string(&section)
+    }
+}
+
+/// Make sure a name can be used as a key in the config files and as part of
+/// an email address or URL.
+fn validate_name(field: &str, name: &str) -> Result<(), ValidationError> {
+    if name.is_empty() {
+        return Err(ValidationError::new(field, "must not be empty"));
+    }
+
+    if !name.chars().all(|c| {
+        c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.'
+    }) {
+        return Err(ValidationError::new(
+            field,
+            "must only contain lowercase letters, digits, dashes and dots",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Make sure a field is not empty.
+fn validate_not_empty(field: &str, value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(ValidationError::new(field, "must not be empty"));
+    }
+
+    Ok(())
+}
+
+impl ConfigRecord for UserConfig {
+    const SECTION: &'static str = "users";
+
+    fn key(&self) -> &str {
+        &self.username
+    }
+
+    fn set_key(&mut self, key: &str) {
+        self.username = key.to_string();
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        validate_name("username", &self.username)?;
+        validate_not_empty("first_name", &self.first_name)?;
+        validate_not_empty("last_name", &self.last_name)?;
+        for alias in self.aliases.iter() {
+            validate_name("aliases", alias)?;
+        }
+        if !self.recovery_email.is_empty() && !self.recovery_email.contains('@')
+        {
+            return Err(ValidationError::new(
+                "recovery_email",
+                "must be an email address",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl ConfigRecord for GroupConfig {
+    const SECTION: &'static str = "groups";
+
+    fn key(&self) -> &str {
+        &self.name
+    }
+
+    fn set_key(&mut self, key: &str) {
+        self.name = key.to_string();
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        validate_name("name", &self.name)?;
+        for alias in self.aliases.iter() {
+            validate_name("aliases", alias)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ConfigRecord for BuildingConfig {
+    const SECTION: &'static str = "buildings";
+
+    fn key(&self) -> &str {
+        &self.name
+    }
+
+    fn set_key(&mut self, key: &str) {
+        self.name = key.to_string();
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        validate_not_empty("name", &self.name)?;
+        validate_not_empty("address", &self.address)?;
+
+        Ok(())
+    }
+}
+
+impl ConfigRecord for ResourceConfig {
+    const SECTION: &'static str = "resources";
+
+    fn key(&self) -> &str {
+        &self.name
+    }
+
+    fn set_key(&mut self, key: &str) {
+        self.name = key.to_string();
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        validate_not_empty("name", &self.name)?;
+        validate_not_empty("building", &self.building)?;
+        if self.capacity < 0 {
+            return Err(ValidationError::new(
+                "capacity",
+                "must not be negative",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl ConfigRecord for LinkConfig {
+    const SECTION: &'static str = "links";
+
+    fn key(&self) -> &str {
+        &self.name
+    }
+
+    fn set_key(&mut self, key: &str) {
+        self.name = key.to_string();
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        validate_name("name", &self.name)?;
+        for alias in self.aliases.iter() {
+            validate_name("aliases", alias)?;
+        }
+        if !self.link.starts_with("https://")
+            && !self.link.starts_with("http://")
+        {
+            return Err(ValidationError::new("link", "must be a URL"));
+        }
+
+        Ok(())
+    }
+
+    fn to_toml_patch(&self) -> Result<String, toml::ser::Error> {
+        // The name is the key of the link, it is not used in the config files.
+        let link = LinkConfig {
+            name: String::new(),
+            ..self.clone()
+        };
+
+        let mut records = BTreeMap::new();
+        records.insert(self.key(), link);
+        let mut section = BTreeMap::new();
+        section.insert(Self::SECTION, records);
+
+        toml::to_string(&section)
+    }
+}
+
+impl ConfigRecord for LabelConfig {
+    const SECTION: &'static str = "labels";
+
+    fn key(&self) -> &str {
+        &self.name
+    }
+
+    fn set_key(&mut self, key: &str) {
+        self.name = key.to_string();
+    }
+
+    fn validate(&self) -> Result<(), ValidationError> {
+        validate_not_empty("name", &self.name)?;
+        if self.color.len() != 6
+            || !self.color.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(ValidationError::new(
+                "color",
+                "must be a hex color without the leading #",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn to_toml_patch(&self) -> Result<String, toml::ser::Error> {
+        // Labels are an array of tables rather than a table keyed by name.
+        let mut section = BTreeMap::new();
+        section.insert(Self::SECTION, vec![self]);
+
+        toml::to_string(&section)
+    }
+}
+
 /// Get the configs from the GitHub repository and parse them.
 pub async fn get_configs_from_repo(github: &Github) -> Config {
     let repo_contents = github.repo(github_org(), "configs").content();
@@ -384,52 +670,257 @@ pub async fn get_configs_from_repo(github: &Github) -> Config {
     config
 }
 
-pub async fn refresh_db_configs(github: &Github) {
+/// Get the time of the last commit to the configs repository.
+pub async fn get_configs_last_commit(github: &Github) -> DateTime<Utc> {
+    let repo = github.repo(github_org(), "configs");
+    let commit = repo.commits().get("master").await.unwrap();
+
+    let commit_date = format!("{}-00:00", commit.commit.author.date);
+    DateTime::parse_from_str(&commit_date, "%Y-%m-%dT%H:%M:%SZ%:z")
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// Remember that a config was changed through the API, so the refresh from
+/// the configs repository does not undo the change before it makes it into
+/// the repository.
+pub fn record_api_change<C: ConfigRecord>(
+    db: &Database,
+    name: &str,
+    deleted: bool,
+) -> Result<ConfigApiChange, DbError> {
+    db.upsert_config_api_change(&NewConfigApiChange {
+        section: C::SECTION.to_string(),
+        name: name.to_string(),
+        deleted,
+        changed_at: Utc::now(),
+    })
+}
+
+pub async fn refresh_db_configs(
+    db: &Database,
+    github: &Github,
+) -> Result<(), DbError> {
     let configs = get_configs_from_repo(&github).await;
+    let last_commit = get_configs_last_commit(&github).await;
+
+    sync_db_configs(db, configs, last_commit)
+}
 
-    // Initialize our database.
-    let db = Database::new();
+/// Write the configs from the repository to the database. The records that
+/// were changed through the API after the last commit to the repository are
+/// left alone, once the repository has a newer commit it wins again.
+pub fn sync_db_configs(
+    db: &Database,
+    configs: Config,
+    last_commit: DateTime<Utc>,
+) -> Result<(), DbError> {
+    let mut api_changes: HashSet<(String, String)> = Default::default();
+    for change in db.get_config_api_changes()? {
+        if change.changed_at > last_commit {
+            api_changes.insert((change.section, change.name));
+        } else {
+            log_or_fail(
+                db.delete_config_api_change(change.id),
+                format_args!(
+                    "deleting api change to {} {}",
+                    change.section, change.name
+                ),
+            )?;
+        }
+    }
+    let changed_via_api = |section: &str, name: &str| {
+        let changed =
+            api_changes.contains(&(section.to_string(), name.to_string()));
+        if changed {
+            println!(
+                "[configs] keeping the api change to {} {}",
+                section, name
+            );
+        }
+        changed
+    };
 
     // Sync buildings.
     for (_, building) in configs.buildings {
-        db.upsert_building(&building);
+        if changed_via_api(BuildingConfig::SECTION, building.key()) {
+            continue;
+        }
+        log_or_fail(
+            db.upsert_building(&building),
+            format_args!("upserting building {}", building.name),
+        )?;
     }
 
     // Sync conference rooms.
     for (_, room) in configs.resources {
-        db.upsert_conference_room(&room);
+        if changed_via_api(ResourceConfig::SECTION, room.key()) {
+            continue;
+        }
+        log_or_fail(
+            db.upsert_conference_room(&room),
+            format_args!("upserting conference room {}", room.name),
+        )?;
     }
 
     // Sync GitHub labels.
     for label in configs.labels {
-        db.upsert_github_label(&label);
+        if changed_via_api(LabelConfig::SECTION, label.key()) {
+            continue;
+        }
+        log_or_fail(
+            db.upsert_github_label(&label),
+            format_args!("upserting github label {}", label.name),
+        )?;
     }
 
     // Sync groups.
     for (_, group) in configs.groups {
-        db.upsert_group(&group);
+        if changed_via_api(GroupConfig::SECTION, group.key()) {
+            continue;
+        }
+        log_or_fail(
+            db.upsert_group(&group),
+            format_args!("upserting group {}", group.name),
+        )?;
     }
 
     // Sync links.
     for (name, mut link) in configs.links {
         link.name = name;
-        db.upsert_link(&link);
+        if changed_via_api(LinkConfig::SECTION, link.key()) {
+            continue;
+        }
+        log_or_fail(
+            db.upsert_link(&link),
+            format_args!("upserting link {}", link.name),
+        )?;
     }
 
     // Sync users.
     for (_, user) in configs.users {
-        db.upsert_user(&user);
+        if changed_via_api(UserConfig::SECTION, user.key()) {
+            continue;
+        }
+        log_or_fail(
+            db.upsert_user(&user),
+            format_args!("upserting user {}", user.username),
+        )?;
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::configs::refresh_db_configs;
+    use chrono::{Duration, Utc};
+
+    use crate::configs::{
+        record_api_change, refresh_db_configs, sync_db_configs, BuildingConfig,
+        Config, ConfigRecord, LabelConfig, LinkConfig, UserConfig,
+    };
+    use crate::db::Database;
     use crate::utils::authenticate_github;
 
+    #[test]
+    fn test_config_validate() {
+        let mut user = UserConfig {
+            first_name: "Jess".to_string(),
+            last_name: "Frazelle".to_string(),
+            username: "jess".to_string(),
+            ..Default::default()
+        };
+        user.validate().unwrap();
+
+        user.username = "Jess Frazelle".to_string();
+        let e = user.validate().unwrap_err();
+        assert_eq!(e.field, "username");
+
+        let label = LabelConfig {
+            name: "bug".to_string(),
+            description: "Something isn't working".to_string(),
+            color: "#d73a4a".to_string(),
+        };
+        let e = label.validate().unwrap_err();
+        assert_eq!(e.field, "color");
+    }
+
+    #[test]
+    fn test_config_toml_patch() {
+        let link = LinkConfig {
+            name: "rfd".to_string(),
+            description: "Requests for Discussion".to_string(),
+            link: "https://github.com/oxidecomputer/rfd".to_string(),
+            aliases: vec![],
+        };
+        assert_eq!(
+            link.to_toml_patch().unwrap(),
+            r#"[links.rfd]
+description = "Requests for Discussion"
+link = "https://github.com/oxidecomputer/rfd"
+"#
+        );
+
+        let label = LabelConfig {
+            name: "bug".to_string(),
+            description: "Something isn't working".to_string(),
+            color: "d73a4a".to_string(),
+        };
+        assert_eq!(
+            label.to_toml_patch().unwrap(),
+            r#"[[labels]]
+name = "bug"
+description = "Something isn't working"
+color = "d73a4a"
+"#
+        );
+    }
+
     #[tokio::test(threaded_scheduler)]
     async fn test_configs() {
+        let db = Database::new().unwrap();
         let github = authenticate_github();
-        refresh_db_configs(&github).await;
+        refresh_db_configs(&db, &github).await.unwrap();
+    }
+
+    #[test]
+    fn test_api_change_survives_refresh() {
+        let db = Database::new().unwrap();
+
+        // The building as it is in the configs repo.
+        let mut configs: Config = Default::default();
+        let building = BuildingConfig {
+            name: "test-api-change".to_string(),
+            description: "From the repo".to_string(),
+            ..Default::default()
+        };
+        configs
+            .buildings
+            .insert(building.name.to_string(), building.clone());
+        let last_commit = Utc::now() - Duration::minutes(15);
+
+        // Update it through the API, like a PUT does.
+        let mut updated = building.clone();
+        updated.description = "From the API".to_string();
+        db.upsert_building(&updated).unwrap();
+        record_api_change::<BuildingConfig>(&db, updated.key(), false).unwrap();
+
+        sync_db_configs(&db, configs.clone(), last_commit).unwrap();
+        let row = db.get_building_by_name(&building.name).unwrap().unwrap();
+        assert_eq!(row.description, "From the API");
+
+        // Once the repo has a newer commit it wins again.
+        sync_db_configs(&db, configs, Utc::now()).unwrap();
+        let row = db.get_building_by_name(&building.name).unwrap().unwrap();
+        assert_eq!(row.description, "From the repo");
+        assert!(db
+            .get_config_api_change_by_section_and_name(
+                BuildingConfig::SECTION,
+                &building.name
+            )
+            .unwrap()
+            .is_none());
+
+        db.delete_building(row.id).unwrap();
     }
 }
@@ -1,4 +1,4 @@
-use airtable_api::User as AirtableUser;
+use airtable_api::{Collaborator, LinkedRecords};
 use chrono::naive::NaiveDate;
 use serde::{Deserialize, Serialize};
 
@@ -9,7 +9,7 @@ pub struct CustomerInteraction {
     #[serde(rename = "Name")]
     pub name: String,
     #[serde(rename = "Company")]
-    pub company: Vec<String>,
+    pub company: LinkedRecords,
     #[serde(with = "meeting_date_format", rename = "Date")]
     pub date: NaiveDate,
     #[serde(rename = "Type")]
@@ -17,9 +17,9 @@ pub struct CustomerInteraction {
     #[serde(rename = "Phase")]
     pub phase: String,
     #[serde(rename = "People")]
-    pub people: Vec<String>,
+    pub people: LinkedRecords,
     #[serde(rename = "Oxide Folks")]
-    pub oxide_folks: Vec<AirtableUser>,
+    pub oxide_folks: Vec<Collaborator>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "Link to Notes")]
     pub notes_link: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "Notes")]
@@ -33,14 +33,14 @@ pub struct DiscussionTopic {
     #[serde(rename = "Topic")]
     pub topic: String,
     #[serde(rename = "Submitter")]
-    pub submitter: AirtableUser,
+    pub submitter: Collaborator,
     #[serde(rename = "Priority")]
     pub priority: String,
     #[serde(skip_serializing_if = "Option::is_none", rename = "Notes")]
     pub notes: Option<String>,
     // Never modify this, it is a linked record.
     #[serde(rename = "Associated meetings")]
-    pub associated_meetings: Vec<String>,
+    pub associated_meetings: LinkedRecords,
 }
 
 /// The data type for a meeting.
@@ -63,11 +63,11 @@ pub struct Meeting {
         skip_serializing_if = "Option::is_none",
         rename = "Proposed discussion"
     )]
-    pub proposed_discussion: Option<Vec<String>>,
+    pub proposed_discussion: Option<LinkedRecords>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "Recording")]
     pub recording: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "Attendees")]
-    pub attendees: Option<Vec<AirtableUser>>,
+    pub attendees: Option<Vec<Collaborator>>,
 }
 
 /// Convert the date format `%Y-%m-%d` to a NaiveDate.
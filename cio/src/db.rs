@@ -1,688 +1,442 @@
 use std::env;
+use std::error;
+use std::fmt;
+use std::time::Duration;
 
-use diesel::pg::PgConnection;
+use diesel::pg::{Pg, PgConnection};
 use diesel::prelude::*;
-
-use crate::configs::{
-    Building, BuildingConfig, ConferenceRoom, GithubLabel, Group, GroupConfig,
-    LabelConfig, Link, LinkConfig, ResourceConfig, User, UserConfig,
-};
-use crate::models::{
-    Applicant, AuthUser, AuthUserLogin, GithubRepo, JournalClubMeeting,
-    JournalClubPaper, MailingListSubscriber, NewApplicant, NewAuthUser,
-    NewAuthUserLogin, NewJournalClubMeeting, NewJournalClubPaper,
-    NewMailingListSubscriber, NewRFD, NewRepo, RFD,
-};
-use crate::schema::{
-    applicants, auth_user_logins, auth_users, buildings, conference_rooms,
-    github_labels, github_repos, groups, journal_club_meetings,
-    journal_club_papers, links, mailing_list_subscribers, rfds, users,
-};
-
-pub struct Database {
-    conn: PgConnection,
+use diesel::r2d2::{ConnectionManager, Pool, PoolError, PooledConnection};
+use dropshot::HttpError;
+
+use crate::configs::User;
+use crate::models::{Applicant, RFD};
+use crate::schema::{applicants, rfds, users};
+
+/// The configuration for our database connection pool.
+///
+/// All the values can be set from the environment, see `DatabaseConfig::from_env`.
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    /// The URL for the Postgres database.
+    pub url: String,
+    /// The maximum number of connections managed by the pool.
+    pub pool_size: u32,
+    /// The minimum number of idle connections the pool will try to maintain.
+    /// If unset, this will be the same as the `pool_size`.
+    pub min_idle: Option<u32>,
+    /// How long to wait when checking out a connection from the pool before
+    /// giving up.
+    pub connection_timeout: Duration,
+    /// How long a connection can sit idle in the pool before it is closed.
+    pub idle_timeout: Option<Duration>,
 }
 
-impl Default for Database {
+impl Default for DatabaseConfig {
     fn default() -> Self {
-        let database_url =
-            env::var("CIO_DATABASE_URL").expect("CIO_DATABASE_URL must be set");
-
-        Database {
-            conn: PgConnection::establish(&database_url).unwrap_or_else(|e| {
-                panic!("error connecting to {}: {}", database_url, e)
-            }),
+        DatabaseConfig {
+            url: String::new(),
+            pool_size: 10,
+            min_idle: None,
+            connection_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(600)),
         }
     }
 }
 
-// TODO: more gracefully handle errors
-// TODO: possibly generate all this boilerplate as well.
-impl Database {
-    /// Establish a connection to the database.
-    pub fn new() -> Database {
-        Default::default()
-    }
-
-    pub fn get_applicants(&self) -> Vec<Applicant> {
-        applicants::dsl::applicants
-            .order_by(applicants::dsl::id.desc())
-            .load::<Applicant>(&self.conn)
-            .unwrap()
-    }
-
-    pub fn upsert_applicant(&self, applicant: &NewApplicant) -> Applicant {
-        // See if we already have the applicant in the database.
-        match applicants::dsl::applicants
-            .filter(applicants::dsl::email.eq(applicant.email.to_string()))
-            .filter(
-                applicants::dsl::sheet_id.eq(applicant.sheet_id.to_string()),
-            )
-            .limit(1)
-            .load::<Applicant>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the applicant in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let a = r.get(0).unwrap();
-
-                    // Update the applicant.
-                    return diesel::update(a)
-                        .set(applicant)
-                        .get_result::<Applicant>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!("unable to update applicant {}: {}", a.id, e)
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the applicant in the database, adding it", e);
-            }
+impl DatabaseConfig {
+    /// Read the database configuration from the environment.
+    ///
+    /// `CIO_DATABASE_URL` is required. `CIO_DATABASE_POOL_SIZE`,
+    /// `CIO_DATABASE_POOL_MIN_IDLE`, `CIO_DATABASE_CONNECTION_TIMEOUT` and
+    /// `CIO_DATABASE_IDLE_TIMEOUT` are optional, the timeouts are in seconds.
+    pub fn from_env() -> Result<Self, DbError> {
+        let url = env::var("CIO_DATABASE_URL").map_err(|_| {
+            DbError::Config("CIO_DATABASE_URL must be set".to_string())
+        })?;
+
+        let mut config = DatabaseConfig {
+            url,
+            ..Default::default()
+        };
+
+        if let Some(pool_size) = env_u64("CIO_DATABASE_POOL_SIZE")? {
+            config.pool_size = pool_size as u32;
+        }
+        if let Some(min_idle) = env_u64("CIO_DATABASE_POOL_MIN_IDLE")? {
+            config.min_idle = Some(min_idle as u32);
+        }
+        if let Some(timeout) = env_u64("CIO_DATABASE_CONNECTION_TIMEOUT")? {
+            config.connection_timeout = Duration::from_secs(timeout);
+        }
+        if let Some(timeout) = env_u64("CIO_DATABASE_IDLE_TIMEOUT")? {
+            // An idle timeout of 0 means connections are never closed for
+            // being idle.
+            config.idle_timeout = if timeout == 0 {
+                None
+            } else {
+                Some(Duration::from_secs(timeout))
+            };
         }
 
-        diesel::insert_into(applicants::table)
-            .values(applicant)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| panic!("creating applicant failed: {}", e))
+        Ok(config)
     }
+}
 
-    pub fn get_buildings(&self) -> Vec<Building> {
-        buildings::dsl::buildings
-            .order_by(buildings::dsl::id.desc())
-            .load::<Building>(&self.conn)
-            .unwrap()
+/// Parse an optional numeric environment variable.
+fn env_u64(key: &str) -> Result<Option<u64>, DbError> {
+    match env::var(key) {
+        Ok(v) => match v.trim().parse::<u64>() {
+            Ok(n) => Ok(Some(n)),
+            Err(e) => Err(DbError::Config(format!(
+                "{} must be a number, got {}: {}",
+                key, v, e
+            ))),
+        },
+        Err(_) => Ok(None),
     }
+}
 
-    pub fn upsert_building(&self, building: &BuildingConfig) -> Building {
-        // See if we already have the building in the database.
-        match buildings::dsl::buildings
-            .filter(buildings::dsl::name.eq(building.name.to_string()))
-            .limit(1)
-            .load::<Building>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the building in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let b = r.get(0).unwrap();
-
-                    // Update the building.
-                    return diesel::update(b)
-                        .set(building)
-                        .get_result::<Building>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!("unable to update building {}: {}", b.id, e)
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the building in the database, adding it", e);
-            }
-        }
+/// Error type returned by our database functions.
+#[derive(Debug)]
+pub enum DbError {
+    /// The database configuration is missing or invalid.
+    Config(String),
+    /// We could not get a connection to the database, either when creating
+    /// the pool or when checking out a connection from it.
+    Connection(PoolError),
+    /// A query failed.
+    Query(diesel::result::Error),
+}
 
-        diesel::insert_into(buildings::table)
-            .values(building)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| panic!("creating building failed: {}", e))
+impl DbError {
+    /// Returns true if the database is unavailable, as opposed to a
+    /// single query failing. Callers can use this to retry later.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(
+            self,
+            DbError::Connection(_)
+                | DbError::Query(diesel::result::Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                    _,
+                ))
+        )
     }
+}
 
-    pub fn get_conference_rooms(&self) -> Vec<ConferenceRoom> {
-        conference_rooms::dsl::conference_rooms
-            .order_by(conference_rooms::dsl::id.desc())
-            .load::<ConferenceRoom>(&self.conn)
-            .unwrap()
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbError::Config(e) => write!(f, "DbError: config -> {}", e),
+            DbError::Connection(e) => write!(f, "DbError: connection -> {}", e),
+            DbError::Query(e) => write!(f, "DbError: query -> {}", e),
+        }
     }
+}
 
-    pub fn upsert_conference_room(
-        &self,
-        conference_room: &ResourceConfig,
-    ) -> ConferenceRoom {
-        // See if we already have the conference_room in the database.
-        match conference_rooms::dsl::conference_rooms
-            .filter(
-                conference_rooms::dsl::name
-                    .eq(conference_room.name.to_string()),
-            )
-            .limit(1)
-            .load::<ConferenceRoom>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the conference_room in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let c = r.get(0).unwrap();
-
-                    // Update the conference_room.
-                    return diesel::update(c)
-                        .set(conference_room)
-                        .get_result::<ConferenceRoom>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!(
-                                "unable to update conference_room {}: {}",
-                                c.id, e
-                            )
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the conference_room in the database, adding it", e);
-            }
+impl error::Error for DbError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DbError::Config(_) => None,
+            DbError::Connection(e) => Some(e),
+            DbError::Query(e) => Some(e),
         }
-
-        diesel::insert_into(conference_rooms::table)
-            .values(conference_room)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| {
-                panic!("creating conference_room failed: {}", e)
-            })
     }
+}
 
-    pub fn get_auth_users(&self) -> Vec<AuthUser> {
-        auth_users::dsl::auth_users
-            .order_by(auth_users::dsl::id.desc())
-            .load::<AuthUser>(&self.conn)
-            .unwrap()
+impl From<PoolError> for DbError {
+    fn from(e: PoolError) -> Self {
+        DbError::Connection(e)
     }
+}
 
-    pub fn upsert_auth_user(&self, auth_user: &NewAuthUser) -> AuthUser {
-        // See if we already have the auth_user in the database.
-        match auth_users::dsl::auth_users
-            .filter(auth_users::dsl::user_id.eq(auth_user.user_id.to_string()))
-            .limit(1)
-            .load::<AuthUser>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the auth_user in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let a = r.get(0).unwrap();
-
-                    // Update the auth_user.
-                    return diesel::update(a)
-                        .set(auth_user)
-                        .get_result::<AuthUser>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!("unable to update auth_user {}: {}", a.id, e)
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the auth_user in the database, adding it", e);
-            }
-        }
-
-        diesel::insert_into(auth_users::table)
-            .values(auth_user)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| panic!("creating auth_user failed: {}", e))
+impl From<diesel::result::Error> for DbError {
+    fn from(e: diesel::result::Error) -> Self {
+        DbError::Query(e)
     }
+}
 
-    pub fn get_auth_user_logins(&self) -> Vec<AuthUserLogin> {
-        auth_user_logins::dsl::auth_user_logins
-            .order_by(auth_user_logins::dsl::id.desc())
-            .load::<AuthUserLogin>(&self.conn)
-            .unwrap()
+/// Database errors surface in our API as a 503 when we cannot reach the
+/// database and a 500 for everything else.
+impl From<DbError> for HttpError {
+    fn from(e: DbError) -> Self {
+        if e.is_unavailable() {
+            HttpError::for_unavail(None, e.to_string())
+        } else {
+            HttpError::for_internal_error(e.to_string())
+        }
     }
+}
 
-    pub fn upsert_auth_user_login(
-        &self,
-        auth_user_login: &NewAuthUserLogin,
-    ) -> AuthUserLogin {
-        // See if we already have the auth_user_login in the database.
-        match auth_user_logins::dsl::auth_user_logins
-            .filter(
-                auth_user_logins::dsl::user_id
-                    .eq(auth_user_login.user_id.to_string()),
-            )
-            .filter(auth_user_logins::dsl::date.eq(auth_user_login.date))
-            .limit(1)
-            .load::<AuthUserLogin>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the auth_user_login in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let a = r.get(0).unwrap();
-
-                    // Update the auth_user_login.
-                    return diesel::update(a)
-                        .set(auth_user_login)
-                        .get_result::<AuthUserLogin>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!(
-                                "unable to update auth_user_login {}: {}",
-                                a.id, e
-                            )
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the auth_user_login in the database, adding it", e);
-            }
+/// Log a failed write of a single row so a sync can carry on with the rest,
+/// unless we cannot reach the database, in which case the error is returned
+/// so the sync stops.
+pub fn log_or_fail<T>(
+    res: Result<T, DbError>,
+    what: fmt::Arguments,
+) -> Result<(), DbError> {
+    if let Err(e) = res {
+        if e.is_unavailable() {
+            return Err(e);
         }
-
-        diesel::insert_into(auth_user_logins::table)
-            .values(auth_user_login)
-            .get_result::<AuthUserLogin>(&self.conn)
-            .unwrap_or_else(|e| {
-                panic!("creating auth_user_login failed: {}", e)
-            })
+        println!("[db] {} failed: {}", what, e);
     }
 
-    pub fn get_github_labels(&self) -> Vec<GithubLabel> {
-        github_labels::dsl::github_labels
-            .order_by(github_labels::dsl::id.desc())
-            .load::<GithubLabel>(&self.conn)
-            .unwrap()
-    }
+    Ok(())
+}
 
-    pub fn upsert_github_label(
-        &self,
-        github_label: &LabelConfig,
-    ) -> GithubLabel {
-        // See if we already have the github_label in the database.
-        match github_labels::dsl::github_labels
-            .filter(github_labels::dsl::name.eq(github_label.name.to_string()))
-            .limit(1)
-            .load::<GithubLabel>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the github_label in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let label = r.get(0).unwrap();
-
-                    // Update the github_label.
-                    return diesel::update(label)
-                        .set(github_label)
-                        .get_result::<GithubLabel>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!(
-                                "unable to update github_label {}: {}",
-                                label.id, e
-                            )
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the github_label in the database, adding it", e);
-            }
-        }
+/// A handle to our database.
+///
+/// This is backed by a connection pool, so it is cheap to clone and can be
+/// shared between threads. Every query checks out a connection from the pool
+/// and returns it when the query is done.
+#[derive(Clone)]
+pub struct Database {
+    pool: Pool<ConnectionManager<PgConnection>>,
+}
 
-        diesel::insert_into(github_labels::table)
-            .values(github_label)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| panic!("creating github_label failed: {}", e))
+// The functions to get, upsert and delete rows for each of our tables are
+// generated by the `db_struct` macro alongside the models.
+impl Database {
+    /// Establish a connection pool to the database, configured from the
+    /// environment.
+    pub fn new() -> Result<Database, DbError> {
+        Database::new_with_config(&DatabaseConfig::from_env()?)
     }
 
-    pub fn get_github_repos(&self) -> Vec<GithubRepo> {
-        github_repos::dsl::github_repos
-            .order_by(github_repos::dsl::id.desc())
-            .load::<GithubRepo>(&self.conn)
-            .unwrap()
+    /// Establish a connection pool to the database with the given
+    /// configuration.
+    pub fn new_with_config(
+        config: &DatabaseConfig,
+    ) -> Result<Database, DbError> {
+        let manager = ConnectionManager::<PgConnection>::new(&config.url);
+        let pool = Pool::builder()
+            .max_size(config.pool_size)
+            .min_idle(config.min_idle)
+            .connection_timeout(config.connection_timeout)
+            .idle_timeout(config.idle_timeout)
+            .build(manager)?;
+
+        Ok(Database { pool })
     }
 
-    pub fn upsert_github_repo(&self, github_repo: &NewRepo) -> GithubRepo {
-        // See if we already have the github_repo in the database.
-        match github_repos::dsl::github_repos
-            .filter(
-                github_repos::dsl::full_name
-                    .eq(github_repo.full_name.to_string()),
-            )
-            .limit(1)
-            .load::<GithubRepo>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the github_repo in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let a = r.get(0).unwrap();
-
-                    // Update the github_repo.
-                    return diesel::update(a)
-                        .set(github_repo)
-                        .get_result::<GithubRepo>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!(
-                                "unable to update github_repo {}: {}",
-                                a.id, e
-                            )
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the github_repo in the database, adding it", e);
-            }
-        }
-
-        diesel::insert_into(github_repos::table)
-            .values(github_repo)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| panic!("creating github_repo failed: {}", e))
+    /// Check out a connection from the pool.
+    pub(crate) fn conn(
+        &self,
+    ) -> Result<PooledConnection<ConnectionManager<PgConnection>>, DbError>
+    {
+        Ok(self.pool.get()?)
     }
 
-    pub fn get_groups(&self) -> Vec<Group> {
-        groups::dsl::groups
-            .order_by(groups::dsl::id.desc())
-            .load::<Group>(&self.conn)
-            .unwrap()
+    /// Get a page of applicants, optionally filtered by their status and the
+    /// role they applied for. See the generated `get_applicants_page` for how
+    /// `before` and `limit` work.
+    pub fn get_applicants_filtered(
+        &self,
+        status: Option<&str>,
+        role: Option<&str>,
+        before: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<Applicant>, DbError> {
+        Ok(applicants_filtered_query(status, role, before, limit)
+            .load::<Applicant>(&self.conn()?)?)
     }
 
-    pub fn upsert_group(&self, group: &GroupConfig) -> Group {
-        // See if we already have the group in the database.
-        match groups::dsl::groups
-            .filter(groups::dsl::name.eq(group.name.to_string()))
-            .limit(1)
-            .load::<Group>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the group in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let g = r.get(0).unwrap();
-
-                    // Update the group.
-                    return diesel::update(g)
-                        .set(group)
-                        .get_result::<Group>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!("unable to update group {}: {}", g.id, e)
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the group in the database, adding it", e);
-            }
+    /// Get a page of RFDs, optionally filtered by their state.
+    pub fn get_rfds_filtered(
+        &self,
+        state: Option<&str>,
+        before: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<RFD>, DbError> {
+        let mut query = Database::rfds_page_query(before, limit);
+        if let Some(state) = state {
+            query = query.filter(rfds::dsl::state.eq(state.to_string()));
         }
 
-        diesel::insert_into(groups::table)
-            .values(group)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| panic!("creating group failed: {}", e))
-    }
-
-    pub fn get_journal_club_meetings(&self) -> Vec<JournalClubMeeting> {
-        journal_club_meetings::dsl::journal_club_meetings
-            .order_by(journal_club_meetings::dsl::id.desc())
-            .load::<JournalClubMeeting>(&self.conn)
-            .unwrap()
+        Ok(query.load::<RFD>(&self.conn()?)?)
     }
 
-    pub fn upsert_journal_club_meeting(
+    /// Search the applicants by name, the search is case insensitive.
+    pub fn search_applicants(
         &self,
-        journal_club_meeting: &NewJournalClubMeeting,
-    ) -> JournalClubMeeting {
-        // See if we already have the journal_club_meeting in the database.
-        match journal_club_meetings::dsl::journal_club_meetings
-            .filter(
-                journal_club_meetings::dsl::issue
-                    .eq(journal_club_meeting.issue.to_string()),
-            )
-            .limit(1)
-            .load::<JournalClubMeeting>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the journal_club_meeting in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let a = r.get(0).unwrap();
-
-                    // Update the journal_club_meeting.
-                    return diesel::update(a)
-                        .set(journal_club_meeting)
-                        .get_result::<JournalClubMeeting>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!(
-                                "unable to update journal_club_meeting {}: {}",
-                                a.id, e
-                            )
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the journal_club_meeting in the database, adding it", e);
-            }
-        }
-
-        diesel::insert_into(journal_club_meetings::table)
-            .values(journal_club_meeting)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| {
-                panic!("creating journal_club_meeting failed: {}", e)
-            })
+        name: &str,
+        limit: i64,
+    ) -> Result<Vec<Applicant>, DbError> {
+        Ok(applicants::dsl::applicants
+            .filter(applicants::dsl::name.ilike(like_pattern(name)))
+            .order_by(applicants::dsl::id.desc())
+            .limit(limit)
+            .load::<Applicant>(&self.conn()?)?)
     }
 
-    pub fn get_journal_club_papers(&self) -> Vec<JournalClubPaper> {
-        journal_club_papers::dsl::journal_club_papers
-            .order_by(journal_club_papers::dsl::id.desc())
-            .load::<JournalClubPaper>(&self.conn)
-            .unwrap()
+    /// Search the RFDs by title, the search is case insensitive.
+    pub fn search_rfds(
+        &self,
+        title: &str,
+        limit: i64,
+    ) -> Result<Vec<RFD>, DbError> {
+        Ok(rfds::dsl::rfds
+            .filter(rfds::dsl::title.ilike(like_pattern(title)))
+            .order_by(rfds::dsl::number.desc())
+            .limit(limit)
+            .load::<RFD>(&self.conn()?)?)
     }
 
-    pub fn upsert_journal_club_paper(
+    /// Get a page of users, optionally filtered by the building they work
+    /// from.
+    pub fn get_users_filtered(
         &self,
-        journal_club_paper: &NewJournalClubPaper,
-    ) -> JournalClubPaper {
-        // See if we already have the journal_club_paper in the database.
-        match journal_club_papers::dsl::journal_club_papers
-            .filter(
-                journal_club_papers::dsl::link
-                    .eq(journal_club_paper.link.to_string()),
-            )
-            .limit(1)
-            .load::<JournalClubPaper>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the journal_club_paper in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let a = r.get(0).unwrap();
-
-                    // Update the journal_club_paper.
-                    return diesel::update(a)
-                        .set(journal_club_paper)
-                        .get_result::<JournalClubPaper>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!(
-                                "unable to update journal_club_paper {}: {}",
-                                a.id, e
-                            )
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the journal_club_paper in the database, adding it", e);
-            }
+        building: Option<&str>,
+        before: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<User>, DbError> {
+        let mut query = Database::users_page_query(before, limit);
+        if let Some(building) = building {
+            query = query.filter(users::dsl::building.eq(building.to_string()));
         }
 
-        diesel::insert_into(journal_club_papers::table)
-            .values(journal_club_paper)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| {
-                panic!("creating journal_club_paper failed: {}", e)
-            })
+        Ok(query.load::<User>(&self.conn()?)?)
     }
+}
 
-    pub fn get_links(&self) -> Vec<Link> {
-        links::dsl::links
-            .order_by(links::dsl::id.desc())
-            .load::<Link>(&self.conn)
-            .unwrap()
+/// Build the query for a page of applicants with the filters that are set.
+fn applicants_filtered_query(
+    status: Option<&str>,
+    role: Option<&str>,
+    before: Option<i32>,
+    limit: i64,
+) -> applicants::BoxedQuery<'static, Pg> {
+    let mut query = Database::applicants_page_query(before, limit);
+    if let Some(status) = status {
+        query = query.filter(applicants::dsl::status.eq(status.to_string()));
     }
-
-    pub fn upsert_link(&self, link: &LinkConfig) -> Link {
-        // See if we already have the link in the database.
-        match links::dsl::links
-            .filter(links::dsl::name.eq(link.name.to_string()))
-            .limit(1)
-            .load::<Link>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the link in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let l = r.get(0).unwrap();
-
-                    // Update the link.
-                    return diesel::update(l)
-                        .set(link)
-                        .get_result::<Link>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!("unable to update link {}: {}", l.id, e)
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the link in the database, adding it", e);
-            }
-        }
-
-        diesel::insert_into(links::table)
-            .values(link)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| panic!("creating link failed: {}", e))
+    if let Some(role) = role {
+        query = query.filter(applicants::dsl::role.eq(role.to_string()));
     }
 
-    pub fn get_mailing_list_subscribers(&self) -> Vec<MailingListSubscriber> {
-        mailing_list_subscribers::dsl::mailing_list_subscribers
-            .order_by(mailing_list_subscribers::dsl::id.desc())
-            .load::<MailingListSubscriber>(&self.conn)
-            .unwrap()
-    }
+    query
+}
 
-    pub fn upsert_mailing_list_subscriber(
-        &self,
-        mailing_list_subscriber: &NewMailingListSubscriber,
-    ) -> MailingListSubscriber {
-        // See if we already have the mailing_list_subscriber in the database.
-        match mailing_list_subscribers::dsl::mailing_list_subscribers
-            .filter(
-                mailing_list_subscribers::dsl::email
-                    .eq(mailing_list_subscriber.email.to_string()),
-            )
-            .limit(1)
-            .load::<MailingListSubscriber>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the mailing_list_subscriber in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let m = r.get(0).unwrap();
-
-                    // Update the mailing_list_subscriber.
-                    return diesel::update(m)
-                        .set(mailing_list_subscriber)
-                        .get_result::<MailingListSubscriber>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!("unable to update mailing_list_subscriber {}: {}", m.id, e)
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the mailing_list_subscriber in the database, adding it", e);
-            }
-        }
+/// Turn the text into a pattern for `LIKE` that matches it anywhere.
+fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .trim()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
 
-        diesel::insert_into(mailing_list_subscribers::table)
-            .values(mailing_list_subscriber)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| {
-                panic!("creating mailing_list_subscriber failed: {}", e)
-            })
-    }
+    format!("%{}%", escaped)
+}
 
-    pub fn get_rfds(&self) -> Vec<RFD> {
-        rfds::dsl::rfds
-            .order_by(rfds::dsl::id.desc())
-            .load::<RFD>(&self.conn)
-            .unwrap()
+#[cfg(test)]
+mod tests {
+    use diesel::debug_query;
+    use diesel::pg::Pg;
+    use diesel::result::{DatabaseErrorKind, Error};
+    use dropshot::HttpError;
+    use hyper::StatusCode;
+
+    use crate::db::{
+        applicants_filtered_query, like_pattern, log_or_fail, Database, DbError,
+    };
+
+    #[test]
+    fn test_like_pattern() {
+        assert_eq!(like_pattern(" single sign-on "), "%single sign-on%");
+        assert_eq!(like_pattern("100%_done"), "%100\\%\\_done%");
     }
 
-    pub fn upsert_rfd(&self, rfd: &NewRFD) -> RFD {
-        // See if we already have the rfd in the database.
-        match rfds::dsl::rfds
-            .filter(rfds::dsl::number.eq(rfd.number))
-            .limit(1)
-            .load::<RFD>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the rfd in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let a = r.get(0).unwrap();
-
-                    // Update the rfd.
-                    return diesel::update(a)
-                        .set(rfd)
-                        .get_result::<RFD>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!("unable to update rfd {}: {}", a.id, e)
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the rfd in the database, adding it", e);
-            }
-        }
-
-        diesel::insert_into(rfds::table)
-            .values(rfd)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| panic!("creating rfd failed: {}", e))
+    #[test]
+    fn test_db_error_to_http_error() {
+        let e = DbError::Query(Error::NotFound);
+        assert!(!e.is_unavailable());
+        let http: HttpError = e.into();
+        assert_eq!(http.status_code, StatusCode::INTERNAL_SERVER_ERROR);
+
+        let e = DbError::Query(Error::DatabaseError(
+            DatabaseErrorKind::UnableToSendCommand,
+            Box::new("connection closed".to_string()),
+        ));
+        assert!(e.is_unavailable());
+        let http: HttpError = e.into();
+        assert_eq!(http.status_code, StatusCode::SERVICE_UNAVAILABLE);
     }
 
-    pub fn get_users(&self) -> Vec<User> {
-        users::dsl::users
-            .order_by(users::dsl::id.desc())
-            .load::<User>(&self.conn)
-            .unwrap()
+    #[test]
+    fn test_log_or_fail() {
+        // A row that fails to write is logged and the sync carries on.
+        let res: Result<(), DbError> = Err(DbError::Query(Error::NotFound));
+        assert!(log_or_fail(res, format_args!("upserting rfd {}", 1)).is_ok());
+
+        // Losing the database stops the sync.
+        let res: Result<(), DbError> =
+            Err(DbError::Query(Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new("connection closed".to_string()),
+            )));
+        let e =
+            log_or_fail(res, format_args!("upserting rfd {}", 1)).unwrap_err();
+        assert!(e.is_unavailable());
     }
 
-    pub fn upsert_user(&self, user: &UserConfig) -> User {
-        // See if we already have the user in the database.
-        match users::dsl::users
-            .filter(users::dsl::username.eq(user.username.to_string()))
-            .limit(1)
-            .load::<User>(&self.conn)
-        {
-            Ok(r) => {
-                if r.is_empty() {
-                    // We don't have the user in the database so we need to add it.
-                    // That will happen below.
-                } else {
-                    let u = r.get(0).unwrap();
-
-                    // Update the user.
-                    return diesel::update(u)
-                        .set(user)
-                        .get_result::<User>(&self.conn)
-                        .unwrap_or_else(|e| {
-                            panic!("unable to update user {}: {}", u.id, e)
-                        });
-                }
-            }
-            Err(e) => {
-                println!("[db] on err: {:?}; we don't have the user in the database, adding it", e);
-            }
-        }
+    #[test]
+    fn test_page_query() {
+        // The first page has no lower bound on the id.
+        let sql = debug_query::<Pg, _>(&Database::rfds_page_query(None, 25))
+            .to_string();
+        assert!(!sql.contains("WHERE"), "{}", sql);
+        assert!(
+            sql.ends_with(
+                r#"ORDER BY "rfds"."id" DESC LIMIT $1 -- binds: [25]"#
+            ),
+            "{}",
+            sql
+        );
+
+        // The next page starts below the last id we saw.
+        let sql =
+            debug_query::<Pg, _>(&Database::rfds_page_query(Some(40), 25))
+                .to_string();
+        assert!(
+            sql.ends_with(
+                r#"WHERE "rfds"."id" < $1 ORDER BY "rfds"."id" DESC LIMIT $2 -- binds: [40, 25]"#
+            ),
+            "{}",
+            sql
+        );
+    }
 
-        diesel::insert_into(users::table)
-            .values(user)
-            .get_result(&self.conn)
-            .unwrap_or_else(|e| panic!("creating user failed: {}", e))
+    #[test]
+    fn test_applicants_filtered_query() {
+        let sql = debug_query::<Pg, _>(&applicants_filtered_query(
+            Some("Onsite"),
+            Some("Engineering"),
+            Some(40),
+            25,
+        ))
+        .to_string();
+        assert!(
+            sql.ends_with(
+                r#"WHERE "applicants"."id" < $1 AND "applicants"."status" = $2 AND "applicants"."role" = $3 ORDER BY "applicants"."id" DESC LIMIT $4 -- binds: [40, "Onsite", "Engineering", 25]"#
+            ),
+            "{}",
+            sql
+        );
+
+        // Filters that are not set are left out.
+        let sql = debug_query::<Pg, _>(&applicants_filtered_query(
+            None,
+            Some("Engineering"),
+            None,
+            25,
+        ))
+        .to_string();
+        assert!(
+            sql.ends_with(
+                r#"WHERE "applicants"."role" = $1 ORDER BY "applicants"."id" DESC LIMIT $2 -- binds: ["Engineering", 25]"#
+            ),
+            "{}",
+            sql
+        );
     }
 }
@@ -0,0 +1,721 @@
+use std::env;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::offset::Utc;
+use chrono::{DateTime, Datelike, TimeZone, Timelike};
+use dropshot::HttpError;
+use hyper::StatusCode;
+use rand::Rng;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::airtable::{refresh_airtable_webhooks, SyncError};
+use crate::applicants::{refresh_airtable_applicants, refresh_db_applicants};
+use crate::auth_logins::{
+    refresh_airtable_auth_user_logins, refresh_airtable_auth_users,
+    refresh_db_auth,
+};
+use crate::configs::refresh_db_configs;
+use crate::db::{Database, DbError};
+use crate::journal_clubs::{
+    refresh_airtable_journal_club_meetings,
+    refresh_airtable_journal_club_papers, refresh_db_journal_club_meetings,
+};
+use crate::mailing_list::refresh_db_mailing_list_subscribers;
+use crate::rfds::{refresh_airtable_rfds, refresh_db_rfds};
+use crate::utils::{authenticate_github, refresh_db_github_repos};
+
+/// Error type returned by the job scheduler.
+#[derive(Debug)]
+pub enum JobError {
+    /// A cron expression could not be parsed.
+    Schedule(String),
+    /// There is no job with the given name.
+    NotFound(String),
+    /// The job is already running, we never run the same job twice at once.
+    AlreadyRunning(String),
+    /// The job failed talking to the database.
+    Db(DbError),
+    /// The job failed syncing to Airtable.
+    Sync(SyncError),
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JobError::Schedule(e) => write!(f, "JobError: schedule -> {}", e),
+            JobError::NotFound(e) => write!(f, "JobError: not found -> {}", e),
+            JobError::AlreadyRunning(e) => {
+                write!(f, "JobError: already running -> {}", e)
+            }
+            JobError::Db(e) => write!(f, "JobError: db -> {}", e),
+            JobError::Sync(e) => write!(f, "JobError: sync -> {}", e),
+        }
+    }
+}
+
+impl error::Error for JobError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            JobError::Db(e) => Some(e),
+            JobError::Sync(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<DbError> for JobError {
+    fn from(e: DbError) -> Self {
+        JobError::Db(e)
+    }
+}
+
+impl From<SyncError> for JobError {
+    fn from(e: SyncError) -> Self {
+        JobError::Sync(e)
+    }
+}
+
+impl From<JobError> for HttpError {
+    fn from(e: JobError) -> Self {
+        match e {
+            JobError::NotFound(_) => {
+                HttpError::for_not_found(None, e.to_string())
+            }
+            JobError::AlreadyRunning(_) => HttpError::for_client_error(
+                None,
+                StatusCode::CONFLICT,
+                e.to_string(),
+            ),
+            JobError::Db(e) => e.into(),
+            JobError::Sync(e) => e.into(),
+            JobError::Schedule(_) => {
+                HttpError::for_internal_error(e.to_string())
+            }
+        }
+    }
+}
+
+/// A cron schedule, in the usual five field format:
+/// `minute hour day-of-month month day-of-week`.
+///
+/// Each field is `*`, a number, a range like `1-5` or a list like `1,15`,
+/// optionally followed by a step like `*/15`. Times are in UTC.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    // Like cron, if both day fields are restricted a day matches either.
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl Schedule {
+    /// Returns the cron expression for the schedule.
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Returns the first time the schedule fires strictly after `after`, or
+    /// `None` if it never does, for example `0 0 30 2 *`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut t = after.with_second(0)?.with_nanosecond(0)?
+            + chrono::Duration::minutes(1);
+        // Every schedule repeats at least once every four years.
+        let limit = t + chrono::Duration::days(4 * 366 + 1);
+
+        while t < limit {
+            if !is_set(self.months, t.month()) {
+                let (year, month) = if t.month() == 12 {
+                    (t.year() + 1, 1)
+                } else {
+                    (t.year(), t.month() + 1)
+                };
+                t = Utc.ymd(year, month, 1).and_hms(0, 0, 0);
+            } else if !self.day_matches(t) {
+                t = (t.date() + chrono::Duration::days(1)).and_hms(0, 0, 0);
+            } else if !is_set(self.hours, t.hour()) {
+                t = t.with_minute(0)? + chrono::Duration::hours(1);
+            } else if !is_set(self.minutes, t.minute()) {
+                t = t + chrono::Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+
+        None
+    }
+
+    fn day_matches(&self, t: DateTime<Utc>) -> bool {
+        let dom = is_set(self.days_of_month, t.day());
+        let dow = is_set(self.days_of_week, t.weekday().num_days_from_sunday());
+
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (true, true) => true,
+            (false, true) => dom,
+            (true, false) => dow,
+            (false, false) => dom || dow,
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = JobError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            e => e,
+        };
+
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(JobError::Schedule(format!(
+                "`{}` must have five fields",
+                s
+            )));
+        }
+
+        let mut days_of_week = parse_field(fields[4], 0, 7)?;
+        // Both 0 and 7 are Sunday.
+        if is_set(days_of_week, 7) {
+            days_of_week |= 1;
+        }
+
+        Ok(Schedule {
+            expression: s.trim().to_string(),
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days_of_month: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            days_of_week,
+            any_day_of_month: fields[2] == "*",
+            any_day_of_week: fields[4] == "*",
+        })
+    }
+}
+
+fn is_set(bits: u64, n: u32) -> bool {
+    bits & (1 << n) != 0
+}
+
+/// Parse one field of a cron expression into a bit set of the values it
+/// matches.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, JobError> {
+    let invalid = || {
+        JobError::Schedule(format!(
+            "invalid field `{}`, values must be between {} and {}",
+            field, min, max
+        ))
+    };
+    let number = |n: &str| -> Result<u32, JobError> {
+        match n.parse::<u32>() {
+            Ok(n) if n >= min && n <= max => Ok(n),
+            _ => Err(invalid()),
+        }
+    };
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(i) => match part[i + 1..].parse::<u32>() {
+                Ok(step) if step > 0 => (&part[..i], step),
+                _ => return Err(invalid()),
+            },
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(i) = range.find('-') {
+            (number(&range[..i])?, number(&range[i + 1..])?)
+        } else {
+            let n = number(range)?;
+            // `5/15` means every 15 starting at 5.
+            if part.contains('/') {
+                (n, max)
+            } else {
+                (n, n)
+            }
+        };
+        if start > end {
+            return Err(invalid());
+        }
+
+        for n in (start..=end).step_by(step as usize) {
+            bits |= 1 << n;
+        }
+    }
+
+    Ok(bits)
+}
+
+/// The jobs we know how to run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JobKind {
+    RefreshAirtableApplicants,
+    RefreshAirtableAuthUserLogins,
+    RefreshAirtableAuthUsers,
+    RefreshAirtableJournalClubMeetings,
+    RefreshAirtableJournalClubPapers,
+    RefreshAirtableRFDs,
+    RefreshAirtableWebhooks,
+    RefreshApplicants,
+    RefreshAuth,
+    RefreshConfigs,
+    RefreshGithubRepos,
+    RefreshJournalClubMeetings,
+    RefreshMailingListSubscribers,
+    RefreshRFDs,
+}
+
+impl JobKind {
+    /// Run the job.
+    pub async fn run(self, db: &Database) -> Result<(), JobError> {
+        match self {
+            JobKind::RefreshAirtableApplicants => {
+                refresh_airtable_applicants(db).await?
+            }
+            JobKind::RefreshAirtableAuthUserLogins => {
+                refresh_airtable_auth_user_logins(db).await?
+            }
+            JobKind::RefreshAirtableAuthUsers => {
+                refresh_airtable_auth_users(db).await?
+            }
+            JobKind::RefreshAirtableJournalClubMeetings => {
+                refresh_airtable_journal_club_meetings(db).await?
+            }
+            JobKind::RefreshAirtableJournalClubPapers => {
+                refresh_airtable_journal_club_papers(db).await?
+            }
+            JobKind::RefreshAirtableRFDs => refresh_airtable_rfds(db).await?,
+            JobKind::RefreshAirtableWebhooks => {
+                refresh_airtable_webhooks().await?
+            }
+            JobKind::RefreshApplicants => refresh_db_applicants(db).await?,
+            JobKind::RefreshAuth => refresh_db_auth(db).await?,
+            JobKind::RefreshConfigs => {
+                refresh_db_configs(db, &authenticate_github()).await?
+            }
+            JobKind::RefreshGithubRepos => {
+                refresh_db_github_repos(db, &authenticate_github()).await?
+            }
+            JobKind::RefreshJournalClubMeetings => {
+                refresh_db_journal_club_meetings(db, &authenticate_github())
+                    .await?
+            }
+            JobKind::RefreshMailingListSubscribers => {
+                refresh_db_mailing_list_subscribers(db).await?
+            }
+            JobKind::RefreshRFDs => {
+                refresh_db_rfds(db, &authenticate_github()).await?
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The jobs the server runs by default, with their name, cron expression
+/// and the maximum jitter in seconds.
+///
+/// The cron expression for a job can be overridden with the
+/// `CIO_JOB_SCHEDULE_<NAME>` environment variable, where the name is in
+/// uppercase, for example `CIO_JOB_SCHEDULE_REFRESH_RFDS`.
+const DEFAULT_JOBS: &[(&str, JobKind, &str, u64)] = &[
+    (
+        "refresh_applicants",
+        JobKind::RefreshApplicants,
+        "0 * * * *",
+        300,
+    ),
+    ("refresh_auth", JobKind::RefreshAuth, "10 * * * *", 300),
+    (
+        "refresh_configs",
+        JobKind::RefreshConfigs,
+        "*/15 * * * *",
+        60,
+    ),
+    (
+        "refresh_github_repos",
+        JobKind::RefreshGithubRepos,
+        "20 * * * *",
+        300,
+    ),
+    (
+        "refresh_journal_club_meetings",
+        JobKind::RefreshJournalClubMeetings,
+        "30 */6 * * *",
+        300,
+    ),
+    (
+        "refresh_mailing_list_subscribers",
+        JobKind::RefreshMailingListSubscribers,
+        "40 * * * *",
+        300,
+    ),
+    ("refresh_rfds", JobKind::RefreshRFDs, "*/30 * * * *", 120),
+    (
+        "refresh_airtable_applicants",
+        JobKind::RefreshAirtableApplicants,
+        "15 */2 * * *",
+        300,
+    ),
+    (
+        "refresh_airtable_auth_user_logins",
+        JobKind::RefreshAirtableAuthUserLogins,
+        "25 */2 * * *",
+        300,
+    ),
+    (
+        "refresh_airtable_auth_users",
+        JobKind::RefreshAirtableAuthUsers,
+        "35 */2 * * *",
+        300,
+    ),
+    (
+        "refresh_airtable_journal_club_meetings",
+        JobKind::RefreshAirtableJournalClubMeetings,
+        "45 */6 * * *",
+        300,
+    ),
+    (
+        "refresh_airtable_journal_club_papers",
+        JobKind::RefreshAirtableJournalClubPapers,
+        "50 */6 * * *",
+        300,
+    ),
+    (
+        "refresh_airtable_rfds",
+        JobKind::RefreshAirtableRFDs,
+        "55 */2 * * *",
+        300,
+    ),
+    (
+        "refresh_airtable_webhooks",
+        JobKind::RefreshAirtableWebhooks,
+        "5 3 * * *",
+        300,
+    ),
+];
+
+/// A single run of a job.
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+pub struct JobRun {
+    pub started_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Set if the job failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The status of a job, as returned by the API.
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub schedule: String,
+    pub jitter_seconds: u64,
+    pub running: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<JobRun>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+/// A job and when it runs.
+pub struct Job {
+    pub name: String,
+    pub kind: JobKind,
+    pub schedule: Schedule,
+    /// Each scheduled run is delayed by a random amount up to this, so jobs
+    /// on the same schedule don't all hit the same APIs at once.
+    pub jitter: Duration,
+    running: AtomicBool,
+    last_run: Mutex<Option<JobRun>>,
+    next_run: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl Job {
+    /// Create a new job.
+    pub fn new(
+        name: &str,
+        kind: JobKind,
+        schedule: Schedule,
+        jitter: Duration,
+    ) -> Self {
+        Job {
+            name: name.to_string(),
+            kind,
+            schedule,
+            jitter,
+            running: AtomicBool::new(false),
+            last_run: Mutex::new(None),
+            next_run: Mutex::new(None),
+        }
+    }
+
+    /// Return the current status of the job.
+    pub fn status(&self) -> JobStatus {
+        JobStatus {
+            name: self.name.to_string(),
+            schedule: self.schedule.expression().to_string(),
+            jitter_seconds: self.jitter.as_secs(),
+            running: self.running.load(Ordering::SeqCst),
+            last_run: self.last_run.lock().unwrap().clone(),
+            next_run: *self.next_run.lock().unwrap(),
+        }
+    }
+
+    /// Mark the job as running, this fails if it already is.
+    fn start(self: &Arc<Self>) -> Result<RunningJob, JobError> {
+        if self
+            .running
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Err(JobError::AlreadyRunning(self.name.to_string()));
+        }
+
+        Ok(RunningJob {
+            job: Arc::clone(self),
+        })
+    }
+
+    /// Schedule the next run of the job, returning how long to wait for it.
+    fn schedule_next(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let next = self.schedule.next_after(now)?;
+        let jitter = if self.jitter.as_secs() > 0 {
+            rand::thread_rng().gen_range(0, self.jitter.as_secs())
+        } else {
+            0
+        };
+        let next = next + chrono::Duration::seconds(jitter as i64);
+
+        *self.next_run.lock().unwrap() = Some(next);
+
+        Some((next - now).to_std().unwrap_or_default())
+    }
+}
+
+/// A running job. The job is marked as no longer running when this is
+/// dropped, even if the job panicked.
+struct RunningJob {
+    job: Arc<Job>,
+}
+
+impl RunningJob {
+    async fn run(self, db: &Database) {
+        let started_at = Utc::now();
+        *self.job.last_run.lock().unwrap() = Some(JobRun {
+            started_at,
+            finished_at: None,
+            error: None,
+        });
+        println!("[jobs] {}: started", self.job.name);
+
+        let result = self.job.kind.run(db).await;
+
+        let error = match result {
+            Ok(_) => {
+                println!("[jobs] {}: finished", self.job.name);
+                None
+            }
+            Err(e) => {
+                println!("[jobs] {}: failed: {}", self.job.name, e);
+                Some(e.to_string())
+            }
+        };
+        *self.job.last_run.lock().unwrap() = Some(JobRun {
+            started_at,
+            finished_at: Some(Utc::now()),
+            error,
+        });
+    }
+
+    /// Run the job in its own task. Some jobs still panic when they fail, this
+    /// records the panic as a failed run instead of taking down the caller.
+    async fn run_in_task(self, db: Database) {
+        let job = Arc::clone(&self.job);
+
+        if let Err(e) = tokio::spawn(async move { self.run(&db).await }).await {
+            println!("[jobs] {}: panicked: {}", job.name, e);
+
+            let mut last_run = job.last_run.lock().unwrap();
+            let started_at = last_run
+                .as_ref()
+                .map(|r| r.started_at)
+                .unwrap_or_else(Utc::now);
+            *last_run = Some(JobRun {
+                started_at,
+                finished_at: Some(Utc::now()),
+                error: Some(format!("panicked: {}", e)),
+            });
+        }
+    }
+}
+
+impl Drop for RunningJob {
+    fn drop(&mut self) {
+        self.job.running.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Runs our jobs on their schedules in the background.
+pub struct Scheduler {
+    db: Database,
+    jobs: Vec<Arc<Job>>,
+}
+
+impl Scheduler {
+    /// Create a scheduler with our default jobs.
+    pub fn new(db: Database) -> Result<Self, JobError> {
+        let mut jobs: Vec<Job> = Default::default();
+        for (name, kind, schedule, jitter) in DEFAULT_JOBS {
+            let schedule =
+                env::var(format!("CIO_JOB_SCHEDULE_{}", name.to_uppercase()))
+                    .unwrap_or_else(|_| schedule.to_string());
+
+            jobs.push(Job::new(
+                name,
+                *kind,
+                schedule.parse()?,
+                Duration::from_secs(*jitter),
+            ));
+        }
+
+        Ok(Scheduler::with_jobs(db, jobs))
+    }
+
+    /// Create a scheduler with the given jobs.
+    pub fn with_jobs(db: Database, jobs: Vec<Job>) -> Self {
+        Scheduler {
+            db,
+            jobs: jobs.into_iter().map(Arc::new).collect(),
+        }
+    }
+
+    /// Start running the jobs on their schedules. This spawns a task for
+    /// each job and returns immediately.
+    pub fn start(&self) {
+        for job in &self.jobs {
+            let job = Arc::clone(job);
+            let db = self.db.clone();
+
+            tokio::spawn(async move {
+                while let Some(wait) = job.schedule_next(Utc::now()) {
+                    tokio::time::delay_for(wait).await;
+
+                    // If the last run is still going, skip this one.
+                    match job.start() {
+                        Ok(running) => running.run_in_task(db.clone()).await,
+                        Err(e) => println!("[jobs] skipping run: {}", e),
+                    }
+                }
+
+                println!("[jobs] {}: will never run again", job.name);
+            });
+        }
+    }
+
+    /// Return the status of all the jobs.
+    pub fn jobs(&self) -> Vec<JobStatus> {
+        self.jobs.iter().map(|job| job.status()).collect()
+    }
+
+    /// Run a job now, outside of its schedule. This returns once the job has
+    /// started.
+    pub fn run(&self, name: &str) -> Result<JobStatus, JobError> {
+        let job = self
+            .jobs
+            .iter()
+            .find(|job| job.name == name)
+            .ok_or_else(|| JobError::NotFound(name.to_string()))?;
+
+        let running = job.start()?;
+        let db = self.db.clone();
+        tokio::spawn(running.run_in_task(db));
+
+        Ok(job.status())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::jobs::Schedule;
+
+    #[test]
+    fn test_schedule_parse() {
+        assert!("* * * * *".parse::<Schedule>().is_ok());
+        assert!("*/15 0-6,22 1 * 1-5".parse::<Schedule>().is_ok());
+        assert!("@hourly".parse::<Schedule>().is_ok());
+
+        assert!("* * * *".parse::<Schedule>().is_err());
+        assert!("60 * * * *".parse::<Schedule>().is_err());
+        assert!("* * 0 * *".parse::<Schedule>().is_err());
+        assert!("*/0 * * * *".parse::<Schedule>().is_err());
+        assert!("5-1 * * * *".parse::<Schedule>().is_err());
+        assert!("a * * * *".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn test_schedule_next_after() {
+        let now = Utc.ymd(2020, 9, 30).and_hms(23, 52, 10);
+
+        let s: Schedule = "*/15 * * * *".parse().unwrap();
+        assert_eq!(
+            s.next_after(now),
+            Some(Utc.ymd(2020, 10, 1).and_hms(0, 0, 0))
+        );
+
+        let s: Schedule = "30 */6 * * *".parse().unwrap();
+        assert_eq!(
+            s.next_after(now),
+            Some(Utc.ymd(2020, 10, 1).and_hms(0, 30, 0))
+        );
+
+        // Strictly after, even if we are on the minute.
+        let s: Schedule = "52 23 * * *".parse().unwrap();
+        assert_eq!(
+            s.next_after(Utc.ymd(2020, 9, 30).and_hms(23, 52, 0)),
+            Some(Utc.ymd(2020, 10, 1).and_hms(23, 52, 0))
+        );
+
+        // Sundays, as 0 or 7.
+        let s: Schedule = "0 9 * * 7".parse().unwrap();
+        assert_eq!(
+            s.next_after(now),
+            Some(Utc.ymd(2020, 10, 4).and_hms(9, 0, 0))
+        );
+
+        // Either the 15th or a Monday.
+        let s: Schedule = "0 0 15 * 1".parse().unwrap();
+        assert_eq!(
+            s.next_after(now),
+            Some(Utc.ymd(2020, 10, 5).and_hms(0, 0, 0))
+        );
+
+        // Leap days.
+        let s: Schedule = "0 0 29 2 *".parse().unwrap();
+        assert_eq!(
+            s.next_after(now),
+            Some(Utc.ymd(2024, 2, 29).and_hms(0, 0, 0))
+        );
+
+        let s: Schedule = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(s.next_after(now), None);
+    }
+}
@@ -1,19 +1,14 @@
 use std::collections::BTreeMap;
 use std::str::from_utf8;
 
-use airtable_api::{Airtable, Record};
 use chrono::NaiveDate;
 use hubcaps::Github;
 use serde::{Deserialize, Serialize};
 
-use crate::airtable::{
-    airtable_api_key, AIRTABLE_BASE_ID_MISC, AIRTABLE_GRID_VIEW,
-    AIRTABLE_JOURNAL_CLUB_MEETINGS_TABLE, AIRTABLE_JOURNAL_CLUB_PAPERS_TABLE,
-};
-use crate::db::Database;
+use crate::airtable::{airtable_table, sync_airtable, SyncError};
+use crate::db::{log_or_fail, Database, DbError};
 use crate::models::{
-    JournalClubMeeting, JournalClubPaper, NewJournalClubMeeting,
-    NewJournalClubPaper,
+    JournalClubMeeting, NewJournalClubMeeting, NewJournalClubPaper,
 };
 use crate::utils::github_org;
 
@@ -129,177 +124,102 @@ pub async fn get_meetings_from_repo(github: &Github) -> Vec<Meeting> {
 }
 
 // Sync the journal_club_meetings with our database.
-pub async fn refresh_db_journal_club_meetings(github: &Github) {
+pub async fn refresh_db_journal_club_meetings(
+    db: &Database,
+    github: &Github,
+) -> Result<(), DbError> {
     let journal_club_meetings = get_meetings_from_repo(github).await;
 
-    // Initialize our database.
-    let db = Database::new();
-
     // Sync journal_club_meetings.
     for journal_club_meeting in journal_club_meetings {
-        db.upsert_journal_club_meeting(&journal_club_meeting.to_model());
+        log_or_fail(
+            db.upsert_journal_club_meeting(&journal_club_meeting.to_model()),
+            format_args!(
+                "upserting journal club meeting {}",
+                journal_club_meeting.issue
+            ),
+        )?;
 
         // Upsert the papers.
         for mut journal_club_paper in journal_club_meeting.papers {
             journal_club_paper.meeting = journal_club_meeting.issue.to_string();
-            db.upsert_journal_club_paper(&journal_club_paper);
+            log_or_fail(
+                db.upsert_journal_club_paper(&journal_club_paper),
+                format_args!(
+                    "upserting journal club paper {}",
+                    journal_club_paper.link
+                ),
+            )?;
         }
     }
-}
-
-pub async fn refresh_airtable_journal_club_meetings() {
-    // Initialize the Airtable client.
-    let airtable = Airtable::new(airtable_api_key(), AIRTABLE_BASE_ID_MISC);
-
-    let records = airtable
-        .list_records(
-            AIRTABLE_JOURNAL_CLUB_MEETINGS_TABLE,
-            AIRTABLE_GRID_VIEW,
-            vec![],
-        )
-        .await
-        .unwrap();
-
-    let mut airtable_journal_club_meetings: BTreeMap<
-        i32,
-        (Record, JournalClubMeeting),
-    > = Default::default();
-    for record in records {
-        let fields: JournalClubMeeting =
-            serde_json::from_value(record.fields.clone()).unwrap();
-
-        airtable_journal_club_meetings.insert(fields.id, (record, fields));
-    }
 
-    // Initialize our database.
-    let db = Database::new();
-    let journal_club_meetings = db.get_journal_club_meetings();
+    Ok(())
+}
 
-    let mut updated: i32 = 0;
-    for mut journal_club_meeting in journal_club_meetings {
-        // Reset the papers field.
+pub async fn refresh_airtable_journal_club_meetings(
+    db: &Database,
+) -> Result<(), SyncError> {
+    let mut journal_club_meetings = db.get_journal_club_meetings()?;
+    for journal_club_meeting in journal_club_meetings.iter_mut() {
+        // We keep the papers themselves in the database, but in Airtable
+        // they are links to the paper records, so new meetings start without
+        // them.
         journal_club_meeting.papers = Default::default();
-
-        // See if we have it in our fields.
-        match airtable_journal_club_meetings.get(&journal_club_meeting.id) {
-            Some((r, in_airtable_fields)) => {
-                let mut record = r.clone();
-
-                // Set the papers fileds.
-                journal_club_meeting.papers = in_airtable_fields.papers.clone();
-
-                record.fields = json!(journal_club_meeting);
-
-                airtable
-                    .update_records(
-                        AIRTABLE_JOURNAL_CLUB_MEETINGS_TABLE,
-                        vec![record.clone()],
-                    )
-                    .await
-                    .unwrap();
-
-                updated += 1;
-            }
-            None => {
-                // Create the record.
-                journal_club_meeting.push_to_airtable().await;
-            }
-        }
     }
 
-    println!("updated {} journal_club_meetings", updated);
-}
+    let report = sync_airtable(journal_club_meetings).await?;
+    println!("synced journal_club_meetings to airtable: {}", report);
 
-pub async fn refresh_airtable_journal_club_papers() {
-    // Initialize the Airtable client.
-    let airtable = Airtable::new(airtable_api_key(), AIRTABLE_BASE_ID_MISC);
-
-    let records = airtable
-        .list_records(
-            AIRTABLE_JOURNAL_CLUB_PAPERS_TABLE,
-            AIRTABLE_GRID_VIEW,
-            vec![],
-        )
-        .await
-        .unwrap();
+    Ok(())
+}
 
-    let mut airtable_journal_club_papers: BTreeMap<
-        i32,
-        (Record, JournalClubPaper),
-    > = Default::default();
-    for record in records {
-        let fields: JournalClubPaper =
-            serde_json::from_value(record.fields.clone()).unwrap();
+pub async fn refresh_airtable_journal_club_papers(
+    db: &Database,
+) -> Result<(), SyncError> {
+    let meetings = airtable_table("journal_club_meetings")?;
 
-        airtable_journal_club_papers.insert(fields.id, (record, fields));
-    }
+    // Initialize the Airtable client.
+    let airtable = meetings.client();
 
     let meeting_records = airtable
-        .list_records(
-            AIRTABLE_JOURNAL_CLUB_MEETINGS_TABLE,
-            AIRTABLE_GRID_VIEW,
+        .list_records::<JournalClubMeeting>(
+            &meetings.table,
+            &meetings.view,
             vec![],
         )
-        .await
-        .unwrap();
+        .await?;
 
     let mut airtable_journal_club_meetings: BTreeMap<String, String> =
         Default::default();
     for meeting_record in meeting_records {
-        let fields: JournalClubMeeting =
-            serde_json::from_value(meeting_record.fields.clone()).unwrap();
-
-        airtable_journal_club_meetings
-            .insert(fields.issue, meeting_record.id.unwrap());
+        match meeting_record {
+            Ok(r) => {
+                airtable_journal_club_meetings
+                    .insert(r.fields.issue, r.id.unwrap());
+            }
+            Err(e) => println!("skipping journal_club_meeting record: {}", e),
+        }
     }
 
-    // Initialize our database.
-    let db = Database::new();
-    let journal_club_papers = db.get_journal_club_papers();
-
-    let mut updated: i32 = 0;
-    for mut journal_club_paper in journal_club_papers {
+    let mut journal_club_papers = db.get_journal_club_papers()?;
+    for journal_club_paper in journal_club_papers.iter_mut() {
         // Set the link_to_meeting to the right meeting.
-        let meeting_record_id = if let Some(m) =
+        if let Some(m) =
             airtable_journal_club_meetings.get(&journal_club_paper.meeting)
         {
-            m.to_string()
-        } else {
-            "".to_string()
-        };
-        if !meeting_record_id.is_empty() {
-            journal_club_paper.link_to_meeting = vec![meeting_record_id];
-        }
-
-        // See if we have it in our fields.
-        match airtable_journal_club_papers.get(&journal_club_paper.id) {
-            Some((r, _in_airtable_fields)) => {
-                let mut record = r.clone();
-
-                record.fields = json!(journal_club_paper);
-
-                airtable
-                    .update_records(
-                        AIRTABLE_JOURNAL_CLUB_PAPERS_TABLE,
-                        vec![record.clone()],
-                    )
-                    .await
-                    .unwrap();
-
-                updated += 1;
-            }
-            None => {
-                // Create the record.
-                journal_club_paper.push_to_airtable().await;
-            }
+            journal_club_paper.link_to_meeting = vec![m.to_string()];
         }
     }
 
-    println!("updated {} journal_club_papers", updated);
+    let report = sync_airtable(journal_club_papers).await?;
+    println!("synced journal_club_papers to airtable: {}", report);
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::db::Database;
     use crate::journal_clubs::{
         refresh_airtable_journal_club_meetings,
         refresh_airtable_journal_club_papers, refresh_db_journal_club_meetings,
@@ -308,17 +228,22 @@ mod tests {
 
     #[tokio::test(threaded_scheduler)]
     async fn test_journal_club_meetings() {
+        let db = Database::new().unwrap();
         let github = authenticate_github();
-        refresh_db_journal_club_meetings(&github).await;
+        refresh_db_journal_club_meetings(&db, &github)
+            .await
+            .unwrap();
     }
 
     #[tokio::test(threaded_scheduler)]
     async fn test_journal_club_meetings_airtable() {
-        refresh_airtable_journal_club_meetings().await;
+        let db = Database::new().unwrap();
+        refresh_airtable_journal_club_meetings(&db).await.unwrap();
     }
 
     #[tokio::test(threaded_scheduler)]
     async fn test_journal_club_papers_airtable() {
-        refresh_airtable_journal_club_papers().await;
+        let db = Database::new().unwrap();
+        refresh_airtable_journal_club_papers(&db).await.unwrap();
     }
 }
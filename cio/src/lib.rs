@@ -2,10 +2,12 @@
 
 pub mod airtable;
 pub mod applicants;
+pub mod auth;
 pub mod auth_logins;
 pub mod configs;
 pub mod core;
 pub mod db;
+pub mod jobs;
 pub mod journal_clubs;
 pub mod mailing_list;
 pub mod models;
@@ -1,38 +1,40 @@
-use airtable_api::Airtable;
+use std::env;
+
 use chrono::offset::Utc;
 use chrono::DateTime;
+use ring::constant_time;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-use crate::airtable::{
-    airtable_api_key, AIRTABLE_BASE_ID_CUSTOMER_LEADS, AIRTABLE_GRID_VIEW,
-    AIRTABLE_MAILING_LIST_SIGNUPS_TABLE,
-};
-use crate::db::Database;
-use crate::models::NewMailingListSubscriber;
+use crate::airtable::{airtable_table, SyncError};
+use crate::db::{log_or_fail, Database, DbError};
+use crate::models::{MailingListSubscriber, NewMailingListSubscriber};
+use crate::slack::{get_public_relations_channel_post_url, post_to_channel};
 
 /// Get all the mailing list subscribers from Airtable.
-pub async fn get_all_subscribers() -> Vec<NewMailingListSubscriber> {
+pub async fn get_all_subscribers(
+) -> Result<Vec<NewMailingListSubscriber>, SyncError> {
+    let table = airtable_table("mailing_list")?;
+
     // Initialize the Airtable client.
-    let airtable =
-        Airtable::new(airtable_api_key(), AIRTABLE_BASE_ID_CUSTOMER_LEADS);
+    let airtable = table.client();
 
     let records = airtable
-        .list_records(
-            AIRTABLE_MAILING_LIST_SIGNUPS_TABLE,
-            AIRTABLE_GRID_VIEW,
+        .list_records::<NewMailingListSubscriber>(
+            &table.table,
+            &table.view,
             vec![],
         )
-        .await
-        .unwrap();
+        .await?;
 
     let mut subscribers: Vec<NewMailingListSubscriber> = Default::default();
     for record in records {
-        let fields: NewMailingListSubscriber =
-            serde_json::from_value(record.fields.clone()).unwrap();
-
-        subscribers.push(fields);
+        match record {
+            Ok(r) => subscribers.push(r.fields),
+            Err(e) => println!("skipping mailing list record: {}", e),
+        }
     }
-    subscribers
+    Ok(subscribers)
 }
 
 /// The data type for the webhook from Mailchimp.
@@ -92,6 +94,32 @@ mod mailchimp_date_format {
 }
 
 impl MailchimpWebhook {
+    /// Parse the form encoded body Mailchimp posts to the webhook.
+    ///
+    /// The keys are nested with brackets, for example
+    /// `data[merges][GROUPINGS][0][name]`, so we turn the pairs into a JSON
+    /// object before deserializing it.
+    pub fn from_form(body: &[u8]) -> Result<Self, serde_json::Error> {
+        let pairs: Vec<(String, String)> = serde_urlencoded::from_bytes(body)
+            .map_err(serde::de::Error::custom)?;
+
+        let mut root = Map::new();
+        for (key, value) in pairs {
+            // Mailchimp sends empty values for the fields that aren't set.
+            if value.is_empty() {
+                continue;
+            }
+
+            let path: Vec<&str> = key
+                .split(|c| c == '[' || c == ']')
+                .filter(|k| !k.is_empty())
+                .collect();
+            insert_form_value(&mut root, &path, value);
+        }
+
+        serde_json::from_value(form_arrays(Value::Object(root)))
+    }
+
     /// Convert to a signup data type.
     pub fn as_signup(&self) -> NewMailingListSubscriber {
         let mut signup: NewMailingListSubscriber = Default::default();
@@ -115,12 +143,16 @@ impl MailchimpWebhook {
                 signup.interest = i.trim().to_string();
             }
 
-            if merges.groupings.is_some() {
-                let groupings = merges.groupings.as_ref().unwrap();
+            if let Some(groupings) = &merges.groupings {
+                // The groupings come from the webhook body, so we cannot
+                // count on all of them being there.
+                let wants = |n: usize| {
+                    groupings.get(n).map_or(false, |g| g.groups.is_some())
+                };
 
-                signup.wants_podcast_updates = groupings[0].groups.is_some();
-                signup.wants_newsletter = groupings[1].groups.is_some();
-                signup.wants_product_updates = groupings[2].groups.is_some();
+                signup.wants_podcast_updates = wants(0);
+                signup.wants_newsletter = wants(1);
+                signup.wants_product_updates = wants(2);
             }
         }
 
@@ -133,6 +165,54 @@ impl MailchimpWebhook {
     }
 }
 
+/// Insert a value from a form into a JSON object at the nested path.
+fn insert_form_value(
+    map: &mut Map<String, Value>,
+    path: &[&str],
+    value: String,
+) {
+    match path {
+        [] => {}
+        [key] => {
+            map.insert(key.to_string(), Value::String(value));
+        }
+        [key, rest @ ..] => {
+            let child = map
+                .entry(key.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(child) = child {
+                insert_form_value(child, rest, value);
+            }
+        }
+    }
+}
+
+/// Turn the objects that are keyed by index into arrays.
+fn form_arrays(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            if !map.is_empty()
+                && map.keys().all(|k| k.chars().all(|c| c.is_ascii_digit()))
+            {
+                let mut items: Vec<(usize, Value)> = map
+                    .into_iter()
+                    .map(|(k, v)| {
+                        (k.parse().unwrap_or_default(), form_arrays(v))
+                    })
+                    .collect();
+                items.sort_by_key(|(i, _)| *i);
+
+                Value::Array(items.into_iter().map(|(_, v)| v).collect())
+            } else {
+                Value::Object(
+                    map.into_iter().map(|(k, v)| (k, form_arrays(v))).collect(),
+                )
+            }
+        }
+        v => v,
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MailchimpWebhookData {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -188,25 +268,196 @@ pub struct MailchimpWebhookGrouping {
     pub groups: Option<String>,
 }
 
-// Sync the mailing list subscribers with our database.
-pub async fn refresh_db_mailing_list_subscribers() {
-    let mailing_list_subscribers = get_all_subscribers().await;
+/// Check the shared secret Mailchimp sends as a query parameter on the
+/// webhook URL.
+pub fn verify_mailchimp_webhook_secret(secret: &str) -> bool {
+    let expected = match env::var("MAILCHIMP_WEBHOOK_SECRET") {
+        Ok(s) if !s.is_empty() => s,
+        _ => return false,
+    };
 
-    // Initialize our database.
-    let db = Database::new();
+    // Compare in constant time so we don't leak how much of the secret
+    // matched.
+    constant_time::verify_slices_are_equal(
+        expected.as_bytes(),
+        secret.as_bytes(),
+    )
+    .is_ok()
+}
+
+/// Handle a webhook event from Mailchimp and return the subscriber it
+/// changed.
+///
+/// Docs:
+/// https://mailchimp.com/developer/guides/sync-audience-data-with-webhooks/#webhook-events
+pub async fn handle_mailchimp_webhook(
+    db: &Database,
+    event: &MailchimpWebhook,
+) -> Result<Option<MailingListSubscriber>, DbError> {
+    let email = match &event.data.email {
+        Some(e) => e.trim().to_string(),
+        None => event.as_signup().email,
+    };
+    let existing = db.get_mailing_list_subscriber_by_email(&email)?;
+
+    match event.webhook_type.as_str() {
+        "subscribe" => {
+            let mut subscriber = event.as_signup();
+            subscriber.email = email;
+            if let Some(e) = existing {
+                // Keep what we know about people that subscribe again.
+                subscriber.date_added = e.date_added;
+                subscriber.notes = e.notes;
+                subscriber.link_to_people = e.link_to_people;
+                subscriber.tags = e
+                    .tags
+                    .into_iter()
+                    .filter(|t| t != "unsubscribed" && t != "cleaned")
+                    .collect();
+            }
+
+            let subscriber = db.upsert_mailing_list_subscriber(&subscriber)?;
+
+            let signup: NewMailingListSubscriber = subscriber.clone().into();
+            post_to_channel(
+                get_public_relations_channel_post_url(),
+                signup.as_slack_msg(),
+            )
+            .await;
+
+            Ok(Some(subscriber))
+        }
+        "profile" => {
+            let mut subscriber = event.as_signup();
+            subscriber.email = email;
+            if let Some(e) = existing {
+                subscriber.date_added = e.date_added;
+                subscriber.date_optin = e.date_optin;
+                subscriber.notes = e.notes;
+                subscriber.tags = e.tags;
+                subscriber.link_to_people = e.link_to_people;
+            }
+
+            Ok(Some(db.upsert_mailing_list_subscriber(&subscriber)?))
+        }
+        "unsubscribe" | "cleaned" => {
+            // We don't know about them, so there is nothing to update.
+            let mut subscriber: NewMailingListSubscriber = match existing {
+                Some(e) => e.into(),
+                None => return Ok(None),
+            };
+
+            subscriber.wants_podcast_updates = false;
+            subscriber.wants_newsletter = false;
+            subscriber.wants_product_updates = false;
+            subscriber.date_last_changed = event.fired_at;
+            let tag = if event.webhook_type == "cleaned" {
+                "cleaned"
+            } else {
+                "unsubscribed"
+            };
+            if !subscriber.tags.iter().any(|t| t == tag) {
+                subscriber.tags.push(tag.to_string());
+            }
+            if let Some(reason) = &event.data.reason {
+                if !subscriber.notes.is_empty() {
+                    subscriber.notes.push('\n');
+                }
+                subscriber.notes +=
+                    &format!("{} on {}: {}", tag, event.fired_at, reason);
+            }
+
+            Ok(Some(db.upsert_mailing_list_subscriber(&subscriber)?))
+        }
+        t => {
+            println!("[mailchimp] ignoring webhook event of type {}", t);
+            Ok(None)
+        }
+    }
+}
+
+// Sync the mailing list subscribers with our database.
+pub async fn refresh_db_mailing_list_subscribers(
+    db: &Database,
+) -> Result<(), SyncError> {
+    let mailing_list_subscribers = get_all_subscribers().await?;
 
     // Sync mailing_list_subscribers.
     for mailing_list_subscriber in mailing_list_subscribers {
-        db.upsert_mailing_list_subscriber(&mailing_list_subscriber);
+        log_or_fail(
+            db.upsert_mailing_list_subscriber(&mailing_list_subscriber),
+            format_args!(
+                "upserting mailing list subscriber {}",
+                mailing_list_subscriber.email
+            ),
+        )?;
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::mailing_list::refresh_db_mailing_list_subscribers;
+    use crate::db::Database;
+    use crate::mailing_list::{
+        refresh_db_mailing_list_subscribers, MailchimpWebhook,
+    };
+
+    #[test]
+    fn test_mailchimp_webhook_from_form() {
+        let body = "type=subscribe&fired_at=2020-09-07+21%3A31%3A09\
+            &data%5Bid%5D=8a25ff1d98&data%5Blist_id%5D=a6b5da1054\
+            &data%5Bemail%5D=jess%40example.com\
+            &data%5Bmerges%5D%5BEMAIL%5D=jess%40example.com\
+            &data%5Bmerges%5D%5BFNAME%5D=Jess\
+            &data%5Bmerges%5D%5BLNAME%5D=Frazelle\
+            &data%5Bmerges%5D%5BINTEREST%5D=Computers\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B0%5D%5Bid%5D=1\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B0%5D%5Bunique_id%5D=a\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B0%5D%5Bname%5D=Podcast\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B0%5D%5Bgroups%5D=Yes\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B1%5D%5Bid%5D=2\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B1%5D%5Bunique_id%5D=b\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B1%5D%5Bname%5D=Newsletter\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B1%5D%5Bgroups%5D=\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B2%5D%5Bid%5D=3\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B2%5D%5Bunique_id%5D=c\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B2%5D%5Bname%5D=Product\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B2%5D%5Bgroups%5D=Yes";
+
+        let event = MailchimpWebhook::from_form(body.as_bytes()).unwrap();
+        assert_eq!(event.webhook_type, "subscribe");
+
+        let signup = event.as_signup();
+        assert_eq!(signup.email, "jess@example.com");
+        assert_eq!(signup.name, "Jess Frazelle");
+        assert_eq!(signup.interest, "Computers");
+        assert!(signup.wants_podcast_updates);
+        assert!(!signup.wants_newsletter);
+        assert!(signup.wants_product_updates);
+    }
+
+    #[test]
+    fn test_mailchimp_webhook_from_form_one_grouping() {
+        let body = "type=subscribe&fired_at=2020-09-07+21%3A31%3A09\
+            &data%5Bemail%5D=jess%40example.com\
+            &data%5Bmerges%5D%5BEMAIL%5D=jess%40example.com\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B0%5D%5Bid%5D=1\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B0%5D%5Bunique_id%5D=a\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B0%5D%5Bname%5D=Podcast\
+            &data%5Bmerges%5D%5BGROUPINGS%5D%5B0%5D%5Bgroups%5D=Yes";
+
+        let event = MailchimpWebhook::from_form(body.as_bytes()).unwrap();
+        let signup = event.as_signup();
+        assert_eq!(signup.email, "jess@example.com");
+        assert!(signup.wants_podcast_updates);
+        assert!(!signup.wants_newsletter);
+        assert!(!signup.wants_product_updates);
+    }
 
     #[tokio::test(threaded_scheduler)]
     async fn test_mailing_list_subscribers() {
-        refresh_db_mailing_list_subscribers().await;
+        let db = Database::new().unwrap();
+        refresh_db_mailing_list_subscribers(&db).await.unwrap();
     }
 }
@@ -1,27 +1,57 @@
 use std::any::Any;
 use std::fs::File;
 use std::io::Read;
+use std::marker::PhantomData;
 use std::sync::Arc;
 
+use airtable_api::WebhookNotification;
+use chrono::offset::Utc;
 use dropshot::endpoint;
 use dropshot::ApiDescription;
 use dropshot::ConfigDropshot;
 use dropshot::ConfigLogging;
 use dropshot::ConfigLoggingLevel;
 use dropshot::HttpError;
+use dropshot::HttpResponseCreated;
+use dropshot::HttpResponseDeleted;
 use dropshot::HttpResponseOk;
 use dropshot::HttpServer;
+use dropshot::PaginationParams;
+use dropshot::Path;
+use dropshot::Query;
 use dropshot::RequestContext;
+use dropshot::ResultsPage;
+use dropshot::TypedBody;
+use dropshot::WhichPage;
 use hyper::{Body, Response, StatusCode};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, Serializer};
 
+use cio_api::airtable::{
+    airtable_webhook_secret, check_airtable_config, handle_airtable_webhook,
+    verify_airtable_webhook,
+};
+use cio_api::auth::{AuthConfig, Authenticator, Claims};
 use cio_api::configs::{
-    Building, ConferenceRoom, GithubLabel, Group, Link, User,
+    record_api_change, Building, BuildingConfig, ConferenceRoom, ConfigRecord,
+    GithubLabel, Group, GroupConfig, LabelConfig, Link, LinkConfig,
+    ResourceConfig, User, UserConfig,
 };
 use cio_api::db::Database;
+use cio_api::jobs::{JobStatus, Scheduler};
+use cio_api::mailing_list::{
+    handle_mailchimp_webhook, verify_mailchimp_webhook_secret, MailchimpWebhook,
+};
 use cio_api::models::{
     Applicant, AuthUser, GithubRepo, JournalClubMeeting, MailingListSubscriber,
     RFD,
 };
+use cio_api::slack::{
+    get_slack_signing_secret, handle_bot_command, verify_slack_signature,
+    BotCommand,
+};
 
 #[macro_use]
 extern crate serde_json;
@@ -54,19 +84,55 @@ async fn main() -> Result<(), String> {
      * Build a description of the API.
      */
     let mut api = ApiDescription::new();
+    api.register(api_create_building).unwrap();
+    api.register(api_create_conference_room).unwrap();
+    api.register(api_create_github_label).unwrap();
+    api.register(api_create_group).unwrap();
+    api.register(api_create_link).unwrap();
+    api.register(api_create_user).unwrap();
+    api.register(api_delete_building).unwrap();
+    api.register(api_delete_conference_room).unwrap();
+    api.register(api_delete_github_label).unwrap();
+    api.register(api_delete_group).unwrap();
+    api.register(api_delete_link).unwrap();
+    api.register(api_delete_user).unwrap();
+    api.register(api_get_applicant).unwrap();
     api.register(api_get_applicants).unwrap();
+    api.register(api_get_auth_user).unwrap();
     api.register(api_get_auth_users).unwrap();
+    api.register(api_get_building).unwrap();
     api.register(api_get_buildings).unwrap();
+    api.register(api_get_conference_room).unwrap();
     api.register(api_get_conference_rooms).unwrap();
+    api.register(api_get_github_label).unwrap();
     api.register(api_get_github_labels).unwrap();
+    api.register(api_get_github_repo).unwrap();
     api.register(api_get_github_repos).unwrap();
+    api.register(api_get_group).unwrap();
     api.register(api_get_groups).unwrap();
+    api.register(api_get_jobs).unwrap();
+    api.register(api_get_journal_club_meeting).unwrap();
     api.register(api_get_journal_club_meetings).unwrap();
+    api.register(api_get_link).unwrap();
     api.register(api_get_links).unwrap();
+    api.register(api_get_mailing_list_subscriber).unwrap();
     api.register(api_get_mailing_list_subscribers).unwrap();
+    api.register(api_get_rfd).unwrap();
     api.register(api_get_rfds).unwrap();
     api.register(api_get_schema).unwrap();
+    api.register(api_get_user).unwrap();
     api.register(api_get_users).unwrap();
+    api.register(api_run_job).unwrap();
+    api.register(api_update_building).unwrap();
+    api.register(api_update_conference_room).unwrap();
+    api.register(api_update_github_label).unwrap();
+    api.register(api_update_group).unwrap();
+    api.register(api_update_link).unwrap();
+    api.register(api_update_user).unwrap();
+    api.register(listen_airtable_webhooks).unwrap();
+    api.register(listen_mailchimp_webhooks).unwrap();
+    api.register(listen_slack_commands).unwrap();
+    api.register(ping_mailchimp_webhooks).unwrap();
 
     // Print the OpenAPI Spec to stdout.
     let api_file = "openapi-cio.json";
@@ -90,10 +156,38 @@ async fn main() -> Result<(), String> {
     f.read_to_string(&mut api_schema).unwrap();
     let schema: openapiv3::OpenAPI = serde_json::from_str(&api_schema).unwrap();
 
+    /*
+     * Create the database connection pool, this is shared between all the
+     * handlers.
+     */
+    let db = Database::new()
+        .map_err(|error| format!("failed to connect to database: {}", error))?;
+
+    /*
+     * Read our Airtable config now, so a bad one stops us here instead of
+     * failing the first job that syncs to Airtable.
+     */
+    check_airtable_config()
+        .map_err(|error| format!("invalid airtable config: {}", error))?;
+
+    /*
+     * Requests are authenticated with the JWTs Auth0 issues for our API.
+     */
+    let auth_config = AuthConfig::from_env()
+        .map_err(|error| format!("failed to configure auth: {}", error))?;
+    let auth = Authenticator::new(auth_config);
+
+    /*
+     * Run our refresh jobs in the background, on their schedules.
+     */
+    let scheduler = Scheduler::new(db.clone())
+        .map_err(|error| format!("failed to schedule jobs: {}", error))?;
+    scheduler.start();
+
     /*
      * The functions that implement our API endpoints will share this context.
      */
-    let api_context = Context::new(schema).await;
+    let api_context = Context::new(schema, db, auth, scheduler).await;
 
     /*
      * Set up the server.
@@ -113,7 +207,9 @@ async fn main() -> Result<(), String> {
  * Application-specific context (state shared by handler functions)
  */
 struct Context {
-    // TODO: share a database connection here.
+    auth: Authenticator,
+    db: Database,
+    scheduler: Scheduler,
     schema: openapiv3::OpenAPI,
 }
 
@@ -121,8 +217,18 @@ impl Context {
     /**
      * Return a new Context.
      */
-    pub async fn new(schema: openapiv3::OpenAPI) -> Arc<Context> {
-        let api_context = Context { schema: schema };
+    pub async fn new(
+        schema: openapiv3::OpenAPI,
+        db: Database,
+        auth: Authenticator,
+        scheduler: Scheduler,
+    ) -> Arc<Context> {
+        let api_context = Context {
+            auth,
+            db,
+            scheduler,
+            schema,
+        };
 
         Arc::new(api_context)
     }
@@ -137,6 +243,349 @@ impl Context {
         ctx.downcast::<Context>()
             .expect("wrong type for private data")
     }
+
+    /**
+     * Authenticate the request and make sure its token was granted the
+     * scopes the route requires.
+     */
+    pub async fn authorize(
+        &self,
+        rqctx: &Arc<RequestContext>,
+        scopes: &[&str],
+    ) -> Result<Claims, HttpError> {
+        let request = rqctx.request.lock().await;
+
+        Ok(self.auth.authorize(request.headers(), scopes).await?)
+    }
+}
+
+/**
+ * Path parameters for looking up a record by its id.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct IdPathParams {
+    id: i32,
+}
+
+/**
+ * Path parameters for looking up a record by its name.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct NamePathParams {
+    name: String,
+}
+
+/**
+ * Path parameters for looking up a GitHub repository.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct RepoPathParams {
+    owner: String,
+    name: String,
+}
+
+/**
+ * Path parameters for looking up an RFD by its number.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct RFDPathParams {
+    number: i32,
+}
+
+/**
+ * Path parameters for looking up a user by their username.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct UserPathParams {
+    username: String,
+}
+
+/**
+ * Scan parameters for the list endpoints that can only be projected.
+ */
+#[derive(Clone, Deserialize, JsonSchema, Serialize)]
+struct ListScanParams {
+    /**
+     * Comma separated list of the fields to return for each record. This
+     * only trims the response, the full records are still read from the
+     * database.
+     */
+    fields: Option<String>,
+}
+
+/**
+ * Scan parameters for filtering the list of applicants.
+ */
+#[derive(Clone, Deserialize, JsonSchema, Serialize)]
+struct ApplicantsScanParams {
+    status: Option<String>,
+    role: Option<String>,
+    /**
+     * Comma separated list of the fields to return for each record. This
+     * only trims the response, the full records are still read from the
+     * database.
+     */
+    fields: Option<String>,
+}
+
+/**
+ * Scan parameters for filtering the list of RFDs.
+ */
+#[derive(Clone, Deserialize, JsonSchema, Serialize)]
+struct RFDsScanParams {
+    state: Option<String>,
+    /**
+     * Comma separated list of the fields to return for each record. This
+     * only trims the response, the full records are still read from the
+     * database.
+     */
+    fields: Option<String>,
+}
+
+/**
+ * Scan parameters for filtering the list of users.
+ */
+#[derive(Clone, Deserialize, JsonSchema, Serialize)]
+struct UsersScanParams {
+    building: Option<String>,
+    /**
+     * Comma separated list of the fields to return for each record. This
+     * only trims the response, the full records are still read from the
+     * database.
+     */
+    fields: Option<String>,
+}
+
+/**
+ * The page selector for our list endpoints. The records are listed newest
+ * first, so the next page starts at the record before the last one we saw.
+ * The scan parameters are carried along so the filters and projection stay
+ * the same for every page.
+ */
+#[derive(Deserialize, Serialize)]
+struct ListPageSelector<S> {
+    scan: S,
+    last_seen: i32,
+}
+
+/**
+ * Return the scan parameters and the id the page starts before.
+ */
+fn page_start<S: Clone>(
+    page: &WhichPage<S, ListPageSelector<S>>,
+) -> (S, Option<i32>) {
+    match page {
+        WhichPage::First(scan) => (scan.clone(), None),
+        WhichPage::Next(selector) => {
+            (selector.scan.clone(), Some(selector.last_seen))
+        }
+    }
+}
+
+/**
+ * A record projected down to the fields that were asked for. It is
+ * documented in the OpenAPI spec with the schema of the full record.
+ *
+ * The projection only shapes the response, the full rows are still loaded
+ * from the database. Our rows are small and selecting columns by name at
+ * runtime does not fit diesel's typed queries.
+ */
+struct Projection<T> {
+    id: i32,
+    value: serde_json::Value,
+    record: PhantomData<T>,
+}
+
+impl<T: Serialize> Projection<T> {
+    /**
+     * Project the record down to the comma separated `fields`, or keep all
+     * of them if `fields` is not set. The id is always kept since we page on
+     * it.
+     */
+    fn new(record: &T, fields: Option<&str>) -> Result<Self, HttpError> {
+        let value = serde_json::to_value(record)
+            .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
+        let id = value["id"].as_i64().unwrap_or_default() as i32;
+
+        let value = match (fields, value) {
+            (Some(fields), serde_json::Value::Object(map)) => {
+                let keep: Vec<&str> = fields
+                    .split(',')
+                    .map(|f| f.trim())
+                    .filter(|f| !f.is_empty())
+                    .collect();
+
+                serde_json::Value::Object(
+                    map.into_iter()
+                        .filter(|(k, _)| {
+                            k == "id" || keep.contains(&k.as_str())
+                        })
+                        .collect(),
+                )
+            }
+            (_, value) => value,
+        };
+
+        Ok(Projection {
+            id,
+            value,
+            record: PhantomData,
+        })
+    }
+}
+
+impl<T> Serialize for Projection<T> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.value.serialize(serializer)
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Projection<T> {
+    fn schema_name() -> String {
+        T::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        T::json_schema(gen)
+    }
+}
+
+/**
+ * Build a page of projected records.
+ */
+fn results_page<T: Serialize, S: Clone + Serialize>(
+    records: &[T],
+    fields: Option<&str>,
+    scan: &S,
+) -> Result<HttpResponseOk<ResultsPage<Projection<T>>>, HttpError> {
+    let items = records
+        .iter()
+        .map(|r| Projection::new(r, fields))
+        .collect::<Result<Vec<_>, HttpError>>()?;
+
+    Ok(HttpResponseOk(ResultsPage::new(
+        items,
+        scan,
+        |p: &Projection<T>, scan: &S| ListPageSelector {
+            scan: scan.clone(),
+            last_seen: p.id,
+        },
+    )?))
+}
+
+/**
+ * Return a 404 for the record we could not find.
+ */
+fn not_found(what: String) -> HttpError {
+    HttpError::for_not_found(None, format!("{} not found", what))
+}
+
+/**
+ * Return a 409 for the record that already exists.
+ */
+fn conflict(what: String) -> HttpError {
+    HttpError::for_client_error(
+        None,
+        StatusCode::CONFLICT,
+        format!("{} already exists", what),
+    )
+}
+
+/**
+ * Query parameters for the routes that change configs.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct ConfigChangeParams {
+    /** Return the change as a patch for the TOML in the configs repo. */
+    #[serde(default)]
+    toml: bool,
+}
+
+/**
+ * The result of changing a config.
+ */
+#[derive(JsonSchema, Serialize)]
+struct ConfigChange<T> {
+    record: T,
+    /** The TOML to add to, or replace in, the configs repo. */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    toml_patch: Option<String>,
+}
+
+impl<T> ConfigChange<T> {
+    /**
+     * Build the result of a change, exporting the TOML patch if it was asked
+     * for.
+     */
+    fn new<C: ConfigRecord>(
+        record: T,
+        config: &C,
+        params: &ConfigChangeParams,
+    ) -> Result<Self, HttpError> {
+        let toml_patch =
+            if params.toml {
+                Some(config.to_toml_patch().map_err(|e| {
+                    HttpError::for_internal_error(e.to_string())
+                })?)
+            } else {
+                None
+            };
+
+        Ok(ConfigChange { record, toml_patch })
+    }
+}
+
+/**
+ * Make sure the key in the body of a request matches the one in the path.
+ * The key can be left out of the body, renames are not supported.
+ */
+fn check_config_key<C: ConfigRecord>(
+    config: &mut C,
+    key: &str,
+) -> Result<(), HttpError> {
+    if config.key().is_empty() {
+        config.set_key(key);
+    } else if config.key() != key {
+        return Err(HttpError::for_bad_request(
+            None,
+            format!(
+                "key {} in the body does not match {} in the path",
+                config.key(),
+                key
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/**
+ * Query parameters for the webhooks that authenticate with a shared secret.
+ */
+#[derive(Deserialize, JsonSchema)]
+struct WebhookSecretParams {
+    secret: String,
+}
+
+/**
+ * Read the whole body of the request.
+ */
+async fn read_body(
+    rqctx: &Arc<RequestContext>,
+) -> Result<hyper::body::Bytes, HttpError> {
+    let mut request = rqctx.request.lock().await;
+
+    hyper::body::to_bytes(request.body_mut())
+        .await
+        .map_err(|e| {
+            HttpError::for_bad_request(
+                None,
+                format!("reading body failed: {}", e),
+            )
+        })
 }
 
 /*
@@ -169,27 +618,98 @@ async fn api_get_schema(
     path = "/auth/users",
 }]
 async fn api_get_auth_users(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<AuthUser>>, HttpError> {
-    // TODO: figure out how to share this between threads.
-    let db = Database::new();
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<ListScanParams, ListPageSelector<ListScanParams>>,
+    >,
+) -> Result<HttpResponseOk<ResultsPage<Projection<AuthUser>>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["auth:read"]).await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
+
+    let records = db.get_auth_users_page(before, limit.into())?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
+}
+
+/**
+ * Fetch an auth user by their id.
+ */
+#[endpoint {
+    method = GET,
+    path = "/auth/users/{id}",
+}]
+async fn api_get_auth_user(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<IdPathParams>,
+) -> Result<HttpResponseOk<AuthUser>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["auth:read"]).await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
 
-    Ok(HttpResponseOk(db.get_auth_users()))
+    match db.get_auth_user_by_id(params.id)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => Err(not_found(format!("auth user {}", params.id))),
+    }
 }
 
 /**
- * Fetch all applicants.
+ * Fetch all applicants, optionally filtered by their status or role.
  */
 #[endpoint {
     method = GET,
     path = "/applicants",
 }]
 async fn api_get_applicants(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<Applicant>>, HttpError> {
-    let db = Database::new();
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<
+            ApplicantsScanParams,
+            ListPageSelector<ApplicantsScanParams>,
+        >,
+    >,
+) -> Result<HttpResponseOk<ResultsPage<Projection<Applicant>>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["hiring:read"]).await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
+
+    let records = db.get_applicants_filtered(
+        scan.status.as_deref(),
+        scan.role.as_deref(),
+        before,
+        limit.into(),
+    )?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
+}
+
+/**
+ * Fetch an applicant by their id.
+ */
+#[endpoint {
+    method = GET,
+    path = "/applicants/{id}",
+}]
+async fn api_get_applicant(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<IdPathParams>,
+) -> Result<HttpResponseOk<Applicant>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["hiring:read"]).await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
 
-    Ok(HttpResponseOk(db.get_applicants()))
+    match db.get_applicant_by_id(params.id)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => Err(not_found(format!("applicant {}", params.id))),
+    }
 }
 
 /**
@@ -200,144 +720,1234 @@ async fn api_get_applicants(
     path = "/buildings",
 }]
 async fn api_get_buildings(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<Building>>, HttpError> {
-    let db = Database::new();
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<ListScanParams, ListPageSelector<ListScanParams>>,
+    >,
+) -> Result<HttpResponseOk<ResultsPage<Projection<Building>>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["facilities:read"]).await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
 
-    Ok(HttpResponseOk(db.get_buildings()))
+    let records = db.get_buildings_page(before, limit.into())?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
 }
 
 /**
- * Fetch a list of conference rooms.
+ * Fetch an office building by its name.
  */
 #[endpoint {
     method = GET,
-    path = "/conferenceRooms",
+    path = "/buildings/{name}",
 }]
-async fn api_get_conference_rooms(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<ConferenceRoom>>, HttpError> {
-    let db = Database::new();
+async fn api_get_building(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseOk<Building>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["facilities:read"]).await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
 
-    Ok(HttpResponseOk(db.get_conference_rooms()))
+    match db.get_building_by_name(&params.name)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => Err(not_found(format!("building {}", params.name))),
+    }
 }
 
 /**
- * Fetch a list of our GitHub labels that get added to all repositories.
+ * Create an office building.
  */
 #[endpoint {
-    method = GET,
-    path = "/github/labels",
+    method = POST,
+    path = "/buildings",
 }]
-async fn api_get_github_labels(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<GithubLabel>>, HttpError> {
-    let db = Database::new();
+async fn api_create_building(
+    rqctx: Arc<RequestContext>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<BuildingConfig>,
+) -> Result<HttpResponseCreated<ConfigChange<Building>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["facilities:write"]).await?;
+    let db = &api_context.db;
+    let params = query_params.into_inner();
+    let building = body.into_inner();
+
+    building.validate()?;
+    if db.get_building_by_name(&building.name)?.is_some() {
+        return Err(conflict(format!("building {}", building.name)));
+    }
 
-    Ok(HttpResponseOk(db.get_github_labels()))
+    let record = db.upsert_building(&building)?;
+    record_api_change::<BuildingConfig>(db, building.key(), false)?;
+
+    Ok(HttpResponseCreated(ConfigChange::new(
+        record, &building, &params,
+    )?))
 }
 
 /**
- * Fetch a list of our GitHub repositories.
+ * Update an office building.
  */
 #[endpoint {
-    method = GET,
-    path = "/github/repos",
+    method = PUT,
+    path = "/buildings/{name}",
 }]
-async fn api_get_github_repos(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<GithubRepo>>, HttpError> {
-    let db = Database::new();
+async fn api_update_building(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<BuildingConfig>,
+) -> Result<HttpResponseOk<ConfigChange<Building>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["facilities:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
+    let params = query_params.into_inner();
+    let mut building = body.into_inner();
 
-    Ok(HttpResponseOk(db.get_github_repos()))
+    check_config_key(&mut building, &path.name)?;
+    building.validate()?;
+    if db.get_building_by_name(&path.name)?.is_none() {
+        return Err(not_found(format!("building {}", path.name)));
+    }
+
+    let record = db.upsert_building(&building)?;
+    record_api_change::<BuildingConfig>(db, building.key(), false)?;
+
+    Ok(HttpResponseOk(ConfigChange::new(
+        record, &building, &params,
+    )?))
 }
 
 /**
- * Fetch a list of Google groups.
+ * Delete an office building.
  */
 #[endpoint {
-    method = GET,
-    path = "/groups",
+    method = DELETE,
+    path = "/buildings/{name}",
 }]
-async fn api_get_groups(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<Group>>, HttpError> {
-    let db = Database::new();
+async fn api_delete_building(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["facilities:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
 
-    Ok(HttpResponseOk(db.get_groups()))
+    match db.get_building_by_name(&path.name)? {
+        Some(r) => {
+            db.delete_building(r.id)?;
+            record_api_change::<BuildingConfig>(db, &r.name, true)?;
+            Ok(HttpResponseDeleted())
+        }
+        None => Err(not_found(format!("building {}", path.name))),
+    }
 }
 
 /**
- * Fetch a list of journal club meetings.
+ * Fetch a list of conference rooms.
  */
 #[endpoint {
     method = GET,
-    path = "/journalClubMeetings",
+    path = "/conferenceRooms",
 }]
-async fn api_get_journal_club_meetings(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<JournalClubMeeting>>, HttpError> {
-    let db = Database::new();
+async fn api_get_conference_rooms(
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<ListScanParams, ListPageSelector<ListScanParams>>,
+    >,
+) -> Result<HttpResponseOk<ResultsPage<Projection<ConferenceRoom>>>, HttpError>
+{
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["facilities:read"]).await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
 
-    Ok(HttpResponseOk(db.get_journal_club_meetings()))
+    let records = db.get_conference_rooms_page(before, limit.into())?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
 }
 
 /**
- * Fetch a list of internal links.
+ * Fetch a conference room by its name.
  */
 #[endpoint {
     method = GET,
-    path = "/links",
+    path = "/conferenceRooms/{name}",
 }]
-async fn api_get_links(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<Link>>, HttpError> {
-    let db = Database::new();
+async fn api_get_conference_room(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseOk<ConferenceRoom>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["facilities:read"]).await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
 
-    Ok(HttpResponseOk(db.get_links()))
+    match db.get_conference_room_by_name(&params.name)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => Err(not_found(format!("conference room {}", params.name))),
+    }
 }
 
 /**
- * Fetch a list of mailing list subscribers.
+ * Create a conference room.
  */
 #[endpoint {
-    method = GET,
-    path = "/mailingListSubscribers",
+    method = POST,
+    path = "/conferenceRooms",
 }]
-async fn api_get_mailing_list_subscribers(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<MailingListSubscriber>>, HttpError> {
-    let db = Database::new();
+async fn api_create_conference_room(
+    rqctx: Arc<RequestContext>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<ResourceConfig>,
+) -> Result<HttpResponseCreated<ConfigChange<ConferenceRoom>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["facilities:write"]).await?;
+    let db = &api_context.db;
+    let params = query_params.into_inner();
+    let room = body.into_inner();
 
-    Ok(HttpResponseOk(db.get_mailing_list_subscribers()))
+    room.validate()?;
+    if db.get_conference_room_by_name(&room.name)?.is_some() {
+        return Err(conflict(format!("conference room {}", room.name)));
+    }
+
+    let record = db.upsert_conference_room(&room)?;
+    record_api_change::<ResourceConfig>(db, room.key(), false)?;
+
+    Ok(HttpResponseCreated(ConfigChange::new(
+        record, &room, &params,
+    )?))
 }
 
 /**
- * Fetch all RFDs.
+ * Update a conference room.
  */
 #[endpoint {
-    method = GET,
-    path = "/rfds",
+    method = PUT,
+    path = "/conferenceRooms/{name}",
 }]
-async fn api_get_rfds(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<RFD>>, HttpError> {
-    let db = Database::new();
+async fn api_update_conference_room(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<ResourceConfig>,
+) -> Result<HttpResponseOk<ConfigChange<ConferenceRoom>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["facilities:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
+    let params = query_params.into_inner();
+    let mut room = body.into_inner();
+
+    check_config_key(&mut room, &path.name)?;
+    room.validate()?;
+    if db.get_conference_room_by_name(&path.name)?.is_none() {
+        return Err(not_found(format!("conference room {}", path.name)));
+    }
+
+    let record = db.upsert_conference_room(&room)?;
+    record_api_change::<ResourceConfig>(db, room.key(), false)?;
 
-    Ok(HttpResponseOk(db.get_rfds()))
+    Ok(HttpResponseOk(ConfigChange::new(record, &room, &params)?))
 }
 
 /**
- * Fetch a list of employees.
+ * Delete a conference room.
  */
 #[endpoint {
-    method = GET,
-    path = "/users",
+    method = DELETE,
+    path = "/conferenceRooms/{name}",
 }]
-async fn api_get_users(
-    _rqctx: Arc<RequestContext>,
-) -> Result<HttpResponseOk<Vec<User>>, HttpError> {
-    let db = Database::new();
+async fn api_delete_conference_room(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["facilities:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
+
+    match db.get_conference_room_by_name(&path.name)? {
+        Some(r) => {
+            db.delete_conference_room(r.id)?;
+            record_api_change::<ResourceConfig>(db, &r.name, true)?;
+            Ok(HttpResponseDeleted())
+        }
+        None => Err(not_found(format!("conference room {}", path.name))),
+    }
+}
 
-    Ok(HttpResponseOk(db.get_users()))
+/**
+ * Fetch a list of our GitHub labels that get added to all repositories.
+ */
+#[endpoint {
+    method = GET,
+    path = "/github/labels",
+}]
+async fn api_get_github_labels(
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<ListScanParams, ListPageSelector<ListScanParams>>,
+    >,
+) -> Result<HttpResponseOk<ResultsPage<Projection<GithubLabel>>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["github:read"]).await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
+
+    let records = db.get_github_labels_page(before, limit.into())?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
+}
+
+/**
+ * Fetch a GitHub label by its name.
+ */
+#[endpoint {
+    method = GET,
+    path = "/github/labels/{name}",
+}]
+async fn api_get_github_label(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseOk<GithubLabel>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["github:read"]).await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
+
+    match db.get_github_label_by_name(&params.name)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => Err(not_found(format!("github label {}", params.name))),
+    }
+}
+
+/**
+ * Create a GitHub label.
+ */
+#[endpoint {
+    method = POST,
+    path = "/github/labels",
+}]
+async fn api_create_github_label(
+    rqctx: Arc<RequestContext>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<LabelConfig>,
+) -> Result<HttpResponseCreated<ConfigChange<GithubLabel>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["github:write"]).await?;
+    let db = &api_context.db;
+    let params = query_params.into_inner();
+    let label = body.into_inner();
+
+    label.validate()?;
+    if db.get_github_label_by_name(&label.name)?.is_some() {
+        return Err(conflict(format!("github label {}", label.name)));
+    }
+
+    let record = db.upsert_github_label(&label)?;
+    record_api_change::<LabelConfig>(db, label.key(), false)?;
+
+    Ok(HttpResponseCreated(ConfigChange::new(
+        record, &label, &params,
+    )?))
+}
+
+/**
+ * Update a GitHub label.
+ */
+#[endpoint {
+    method = PUT,
+    path = "/github/labels/{name}",
+}]
+async fn api_update_github_label(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<LabelConfig>,
+) -> Result<HttpResponseOk<ConfigChange<GithubLabel>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["github:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
+    let params = query_params.into_inner();
+    let mut label = body.into_inner();
+
+    check_config_key(&mut label, &path.name)?;
+    label.validate()?;
+    if db.get_github_label_by_name(&path.name)?.is_none() {
+        return Err(not_found(format!("github label {}", path.name)));
+    }
+
+    let record = db.upsert_github_label(&label)?;
+    record_api_change::<LabelConfig>(db, label.key(), false)?;
+
+    Ok(HttpResponseOk(ConfigChange::new(record, &label, &params)?))
+}
+
+/**
+ * Delete a GitHub label.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/github/labels/{name}",
+}]
+async fn api_delete_github_label(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["github:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
+
+    match db.get_github_label_by_name(&path.name)? {
+        Some(r) => {
+            db.delete_github_label(r.id)?;
+            record_api_change::<LabelConfig>(db, &r.name, true)?;
+            Ok(HttpResponseDeleted())
+        }
+        None => Err(not_found(format!("github label {}", path.name))),
+    }
+}
+
+/**
+ * Fetch a list of our GitHub repositories.
+ */
+#[endpoint {
+    method = GET,
+    path = "/github/repos",
+}]
+async fn api_get_github_repos(
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<ListScanParams, ListPageSelector<ListScanParams>>,
+    >,
+) -> Result<HttpResponseOk<ResultsPage<Projection<GithubRepo>>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["github:read"]).await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
+
+    let records = db.get_github_repos_page(before, limit.into())?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
+}
+
+/**
+ * Fetch a GitHub repository by its owner and name.
+ */
+#[endpoint {
+    method = GET,
+    path = "/github/repos/{owner}/{name}",
+}]
+async fn api_get_github_repo(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<RepoPathParams>,
+) -> Result<HttpResponseOk<GithubRepo>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["github:read"]).await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
+
+    let full_name = format!("{}/{}", params.owner, params.name);
+
+    match db.get_github_repo_by_full_name(&full_name)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => Err(not_found(format!("github repo {}", full_name))),
+    }
+}
+
+/**
+ * Fetch a list of Google groups.
+ */
+#[endpoint {
+    method = GET,
+    path = "/groups",
+}]
+async fn api_get_groups(
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<ListScanParams, ListPageSelector<ListScanParams>>,
+    >,
+) -> Result<HttpResponseOk<ResultsPage<Projection<Group>>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["people:read"]).await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
+
+    let records = db.get_groups_page(before, limit.into())?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
+}
+
+/**
+ * Fetch a Google group by its name.
+ */
+#[endpoint {
+    method = GET,
+    path = "/groups/{name}",
+}]
+async fn api_get_group(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseOk<Group>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["people:read"]).await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
+
+    match db.get_group_by_name(&params.name)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => Err(not_found(format!("group {}", params.name))),
+    }
+}
+
+/**
+ * Create a Google group.
+ */
+#[endpoint {
+    method = POST,
+    path = "/groups",
+}]
+async fn api_create_group(
+    rqctx: Arc<RequestContext>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<GroupConfig>,
+) -> Result<HttpResponseCreated<ConfigChange<Group>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["people:write"]).await?;
+    let db = &api_context.db;
+    let params = query_params.into_inner();
+    let group = body.into_inner();
+
+    group.validate()?;
+    if db.get_group_by_name(&group.name)?.is_some() {
+        return Err(conflict(format!("group {}", group.name)));
+    }
+
+    let record = db.upsert_group(&group)?;
+    record_api_change::<GroupConfig>(db, group.key(), false)?;
+
+    Ok(HttpResponseCreated(ConfigChange::new(
+        record, &group, &params,
+    )?))
+}
+
+/**
+ * Update a Google group.
+ */
+#[endpoint {
+    method = PUT,
+    path = "/groups/{name}",
+}]
+async fn api_update_group(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<GroupConfig>,
+) -> Result<HttpResponseOk<ConfigChange<Group>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["people:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
+    let params = query_params.into_inner();
+    let mut group = body.into_inner();
+
+    check_config_key(&mut group, &path.name)?;
+    group.validate()?;
+    if db.get_group_by_name(&path.name)?.is_none() {
+        return Err(not_found(format!("group {}", path.name)));
+    }
+
+    let record = db.upsert_group(&group)?;
+    record_api_change::<GroupConfig>(db, group.key(), false)?;
+
+    Ok(HttpResponseOk(ConfigChange::new(record, &group, &params)?))
+}
+
+/**
+ * Delete a Google group.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/groups/{name}",
+}]
+async fn api_delete_group(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["people:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
+
+    match db.get_group_by_name(&path.name)? {
+        Some(r) => {
+            db.delete_group(r.id)?;
+            record_api_change::<GroupConfig>(db, &r.name, true)?;
+            Ok(HttpResponseDeleted())
+        }
+        None => Err(not_found(format!("group {}", path.name))),
+    }
+}
+
+/**
+ * Fetch a list of journal club meetings.
+ */
+#[endpoint {
+    method = GET,
+    path = "/journalClubMeetings",
+}]
+async fn api_get_journal_club_meetings(
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<ListScanParams, ListPageSelector<ListScanParams>>,
+    >,
+) -> Result<
+    HttpResponseOk<ResultsPage<Projection<JournalClubMeeting>>>,
+    HttpError,
+> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context
+        .authorize(&rqctx, &["journal_club:read"])
+        .await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
+
+    let records = db.get_journal_club_meetings_page(before, limit.into())?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
+}
+
+/**
+ * Fetch a journal club meeting by its id.
+ */
+#[endpoint {
+    method = GET,
+    path = "/journalClubMeetings/{id}",
+}]
+async fn api_get_journal_club_meeting(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<IdPathParams>,
+) -> Result<HttpResponseOk<JournalClubMeeting>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context
+        .authorize(&rqctx, &["journal_club:read"])
+        .await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
+
+    match db.get_journal_club_meeting_by_id(params.id)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => Err(not_found(format!("journal club meeting {}", params.id))),
+    }
+}
+
+/**
+ * Fetch a list of internal links.
+ */
+#[endpoint {
+    method = GET,
+    path = "/links",
+}]
+async fn api_get_links(
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<ListScanParams, ListPageSelector<ListScanParams>>,
+    >,
+) -> Result<HttpResponseOk<ResultsPage<Projection<Link>>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["links:read"]).await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
+
+    let records = db.get_links_page(before, limit.into())?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
+}
+
+/**
+ * Fetch an internal link by its name.
+ */
+#[endpoint {
+    method = GET,
+    path = "/links/{name}",
+}]
+async fn api_get_link(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseOk<Link>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["links:read"]).await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
+
+    match db.get_link_by_name(&params.name)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => Err(not_found(format!("link {}", params.name))),
+    }
+}
+
+/**
+ * Create an internal link.
+ */
+#[endpoint {
+    method = POST,
+    path = "/links",
+}]
+async fn api_create_link(
+    rqctx: Arc<RequestContext>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<LinkConfig>,
+) -> Result<HttpResponseCreated<ConfigChange<Link>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["links:write"]).await?;
+    let db = &api_context.db;
+    let params = query_params.into_inner();
+    let link = body.into_inner();
+
+    link.validate()?;
+    if db.get_link_by_name(&link.name)?.is_some() {
+        return Err(conflict(format!("link {}", link.name)));
+    }
+
+    let record = db.upsert_link(&link)?;
+    record_api_change::<LinkConfig>(db, link.key(), false)?;
+
+    Ok(HttpResponseCreated(ConfigChange::new(
+        record, &link, &params,
+    )?))
+}
+
+/**
+ * Update an internal link.
+ */
+#[endpoint {
+    method = PUT,
+    path = "/links/{name}",
+}]
+async fn api_update_link(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<LinkConfig>,
+) -> Result<HttpResponseOk<ConfigChange<Link>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["links:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
+    let params = query_params.into_inner();
+    let mut link = body.into_inner();
+
+    check_config_key(&mut link, &path.name)?;
+    link.validate()?;
+    if db.get_link_by_name(&path.name)?.is_none() {
+        return Err(not_found(format!("link {}", path.name)));
+    }
+
+    let record = db.upsert_link(&link)?;
+    record_api_change::<LinkConfig>(db, link.key(), false)?;
+
+    Ok(HttpResponseOk(ConfigChange::new(record, &link, &params)?))
+}
+
+/**
+ * Delete an internal link.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/links/{name}",
+}]
+async fn api_delete_link(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["links:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
+
+    match db.get_link_by_name(&path.name)? {
+        Some(r) => {
+            db.delete_link(r.id)?;
+            record_api_change::<LinkConfig>(db, &r.name, true)?;
+            Ok(HttpResponseDeleted())
+        }
+        None => Err(not_found(format!("link {}", path.name))),
+    }
+}
+
+/**
+ * Fetch a list of mailing list subscribers.
+ */
+#[endpoint {
+    method = GET,
+    path = "/mailingListSubscribers",
+}]
+async fn api_get_mailing_list_subscribers(
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<ListScanParams, ListPageSelector<ListScanParams>>,
+    >,
+) -> Result<
+    HttpResponseOk<ResultsPage<Projection<MailingListSubscriber>>>,
+    HttpError,
+> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context
+        .authorize(&rqctx, &["mailing_list:read"])
+        .await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
+
+    let records = db.get_mailing_list_subscribers_page(before, limit.into())?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
+}
+
+/**
+ * Fetch a mailing list subscriber by their id.
+ */
+#[endpoint {
+    method = GET,
+    path = "/mailingListSubscribers/{id}",
+}]
+async fn api_get_mailing_list_subscriber(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<IdPathParams>,
+) -> Result<HttpResponseOk<MailingListSubscriber>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context
+        .authorize(&rqctx, &["mailing_list:read"])
+        .await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
+
+    match db.get_mailing_list_subscriber_by_id(params.id)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => {
+            Err(not_found(format!("mailing list subscriber {}", params.id)))
+        }
+    }
+}
+
+/**
+ * Fetch all RFDs, optionally filtered by their state.
+ */
+#[endpoint {
+    method = GET,
+    path = "/rfds",
+}]
+async fn api_get_rfds(
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<RFDsScanParams, ListPageSelector<RFDsScanParams>>,
+    >,
+) -> Result<HttpResponseOk<ResultsPage<Projection<RFD>>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["rfds:read"]).await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
+
+    let records =
+        db.get_rfds_filtered(scan.state.as_deref(), before, limit.into())?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
+}
+
+/**
+ * Fetch an RFD by its number.
+ */
+#[endpoint {
+    method = GET,
+    path = "/rfds/{number}",
+}]
+async fn api_get_rfd(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<RFDPathParams>,
+) -> Result<HttpResponseOk<RFD>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["rfds:read"]).await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
+
+    match db.get_rfd_by_number(params.number)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => Err(not_found(format!("rfd {}", params.number))),
+    }
+}
+
+/**
+ * Fetch a list of employees, optionally filtered by their building.
+ */
+#[endpoint {
+    method = GET,
+    path = "/users",
+}]
+async fn api_get_users(
+    rqctx: Arc<RequestContext>,
+    query: Query<
+        PaginationParams<UsersScanParams, ListPageSelector<UsersScanParams>>,
+    >,
+) -> Result<HttpResponseOk<ResultsPage<Projection<User>>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["people:read"]).await?;
+    let db = &api_context.db;
+    let pag_params = query.into_inner();
+    let limit = rqctx.page_limit(&pag_params)?.get();
+    let (scan, before) = page_start(&pag_params.page);
+
+    let records =
+        db.get_users_filtered(scan.building.as_deref(), before, limit.into())?;
+
+    results_page(&records, scan.fields.as_deref(), &scan)
+}
+
+/**
+ * Fetch an employee by their username.
+ */
+#[endpoint {
+    method = GET,
+    path = "/users/{username}",
+}]
+async fn api_get_user(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<UserPathParams>,
+) -> Result<HttpResponseOk<User>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["people:read"]).await?;
+    let db = &api_context.db;
+    let params = path_params.into_inner();
+
+    match db.get_user_by_username(&params.username)? {
+        Some(r) => Ok(HttpResponseOk(r)),
+        None => Err(not_found(format!("user {}", params.username))),
+    }
+}
+
+/**
+ * Create an employee.
+ */
+#[endpoint {
+    method = POST,
+    path = "/users",
+}]
+async fn api_create_user(
+    rqctx: Arc<RequestContext>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<UserConfig>,
+) -> Result<HttpResponseCreated<ConfigChange<User>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["people:write"]).await?;
+    let db = &api_context.db;
+    let params = query_params.into_inner();
+    let user = body.into_inner();
+
+    user.validate()?;
+    if db.get_user_by_username(&user.username)?.is_some() {
+        return Err(conflict(format!("user {}", user.username)));
+    }
+
+    let record = db.upsert_user(&user)?;
+    record_api_change::<UserConfig>(db, user.key(), false)?;
+
+    Ok(HttpResponseCreated(ConfigChange::new(
+        record, &user, &params,
+    )?))
+}
+
+/**
+ * Update an employee.
+ */
+#[endpoint {
+    method = PUT,
+    path = "/users/{username}",
+}]
+async fn api_update_user(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<UserPathParams>,
+    query_params: Query<ConfigChangeParams>,
+    body: TypedBody<UserConfig>,
+) -> Result<HttpResponseOk<ConfigChange<User>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["people:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
+    let params = query_params.into_inner();
+    let mut user = body.into_inner();
+
+    check_config_key(&mut user, &path.username)?;
+    user.validate()?;
+    if db.get_user_by_username(&path.username)?.is_none() {
+        return Err(not_found(format!("user {}", path.username)));
+    }
+
+    let record = db.upsert_user(&user)?;
+    record_api_change::<UserConfig>(db, user.key(), false)?;
+
+    Ok(HttpResponseOk(ConfigChange::new(record, &user, &params)?))
+}
+
+/**
+ * Delete an employee.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/users/{username}",
+}]
+async fn api_delete_user(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<UserPathParams>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["people:write"]).await?;
+    let db = &api_context.db;
+    let path = path_params.into_inner();
+
+    match db.get_user_by_username(&path.username)? {
+        Some(r) => {
+            db.delete_user(r.id)?;
+            record_api_change::<UserConfig>(db, &r.username, true)?;
+            Ok(HttpResponseDeleted())
+        }
+        None => Err(not_found(format!("user {}", path.username))),
+    }
+}
+
+/**
+ * Fetch the status of our background jobs.
+ */
+#[endpoint {
+    method = GET,
+    path = "/jobs",
+}]
+async fn api_get_jobs(
+    rqctx: Arc<RequestContext>,
+) -> Result<HttpResponseOk<Vec<JobStatus>>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["jobs:read"]).await?;
+
+    Ok(HttpResponseOk(api_context.scheduler.jobs()))
+}
+
+/**
+ * Run a background job now, outside of its schedule. This returns once the
+ * job has started, with a 409 if it is already running.
+ */
+#[endpoint {
+    method = POST,
+    path = "/jobs/{name}/run",
+}]
+async fn api_run_job(
+    rqctx: Arc<RequestContext>,
+    path_params: Path<NamePathParams>,
+) -> Result<HttpResponseOk<JobStatus>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    api_context.authorize(&rqctx, &["jobs:write"]).await?;
+    let params = path_params.into_inner();
+
+    Ok(HttpResponseOk(api_context.scheduler.run(&params.name)?))
+}
+
+/**
+ * Listen for changes made in Airtable. Airtable only tells us something
+ * changed, we fetch the changes and copy the fields people edit in Airtable
+ * back into our database. The request must be signed with the secret of the
+ * webhook.
+ */
+#[endpoint {
+    method = POST,
+    path = "/airtable/webhook",
+}]
+async fn listen_airtable_webhooks(
+    rqctx: Arc<RequestContext>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+
+    let mac = {
+        let request = rqctx.request.lock().await;
+        request
+            .headers()
+            .get("X-Airtable-Content-MAC")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let body = read_body(&rqctx).await?;
+    let notification: WebhookNotification = serde_json::from_slice(&body)
+        .map_err(|e| {
+            HttpError::for_bad_request(None, format!("invalid webhook: {}", e))
+        })?;
+
+    let verified = match airtable_webhook_secret(&notification.webhook.id) {
+        Some(secret) => verify_airtable_webhook(&secret, &body, &mac),
+        None => false,
+    };
+    if !verified {
+        return Err(HttpError::for_client_error(
+            None,
+            StatusCode::UNAUTHORIZED,
+            "invalid airtable signature".to_string(),
+        ));
+    }
+
+    let updated =
+        handle_airtable_webhook(&api_context.db, &notification).await?;
+    println!(
+        "applied airtable webhook {}, updated {} rows",
+        notification.webhook.id, updated
+    );
+
+    Ok(HttpResponseOk(()))
+}
+
+/**
+ * Respond to the request Mailchimp makes to validate the webhook URL.
+ */
+#[endpoint {
+    method = GET,
+    path = "/mailchimp",
+}]
+async fn ping_mailchimp_webhooks(
+    _rqctx: Arc<RequestContext>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    Ok(HttpResponseOk(()))
+}
+
+/**
+ * Listen for changes to our mailing list from Mailchimp.
+ */
+#[endpoint {
+    method = POST,
+    path = "/mailchimp",
+}]
+async fn listen_mailchimp_webhooks(
+    rqctx: Arc<RequestContext>,
+    query_params: Query<WebhookSecretParams>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+    let params = query_params.into_inner();
+
+    if !verify_mailchimp_webhook_secret(&params.secret) {
+        return Err(HttpError::for_client_error(
+            None,
+            StatusCode::UNAUTHORIZED,
+            "invalid webhook secret".to_string(),
+        ));
+    }
+
+    let body = read_body(&rqctx).await?;
+    let event = MailchimpWebhook::from_form(&body).map_err(|e| {
+        HttpError::for_bad_request(None, format!("invalid webhook: {}", e))
+    })?;
+
+    handle_mailchimp_webhook(&api_context.db, &event).await?;
+
+    Ok(HttpResponseOk(()))
+}
+
+/**
+ * Answer a slash command from Slack. The request must be signed with our
+ * Slack app's signing secret.
+ */
+#[endpoint {
+    method = POST,
+    path = "/slack/commands",
+}]
+async fn listen_slack_commands(
+    rqctx: Arc<RequestContext>,
+) -> Result<HttpResponseOk<serde_json::Value>, HttpError> {
+    let api_context = Context::from_rqctx(&rqctx);
+
+    let (timestamp, signature) = {
+        let request = rqctx.request.lock().await;
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        (
+            header("X-Slack-Request-Timestamp"),
+            header("X-Slack-Signature"),
+        )
+    };
+
+    let body = read_body(&rqctx).await?;
+    if !verify_slack_signature(
+        &get_slack_signing_secret(),
+        &timestamp,
+        &signature,
+        &body,
+        Utc::now(),
+    ) {
+        return Err(HttpError::for_client_error(
+            None,
+            StatusCode::UNAUTHORIZED,
+            "invalid slack signature".to_string(),
+        ));
+    }
+
+    let command: BotCommand =
+        serde_urlencoded::from_bytes(&body).map_err(|e| {
+            HttpError::for_bad_request(None, format!("invalid command: {}", e))
+        })?;
+
+    Ok(HttpResponseOk(handle_bot_command(
+        &api_context.db,
+        &command,
+    )?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Projection;
+
+    #[test]
+    fn test_projection() {
+        let record = json!({
+            "id": 42,
+            "name": "Single Sign-On",
+            "state": "published",
+            "content": "a very long document",
+        });
+
+        let p = Projection::new(&record, Some("name, state")).unwrap();
+        assert_eq!(p.id, 42);
+        assert_eq!(
+            p.value,
+            json!({"id": 42, "name": "Single Sign-On", "state": "published"})
+        );
+
+        let p = Projection::new(&record, None).unwrap();
+        assert_eq!(p.value, record);
+    }
 }
@@ -1,4 +1,4 @@
-use airtable_api::{Airtable, Record};
+use airtable_api::Record;
 use chrono::offset::Utc;
 use chrono::{DateTime, NaiveDate};
 use chrono_humanize::HumanTime;
@@ -21,25 +21,18 @@ use std::io::Write;
 
 use crate::utils::{check_if_github_issue_exists, github_org};
 
-use crate::airtable::{
-    airtable_api_key, AIRTABLE_APPLICATIONS_TABLE, AIRTABLE_AUTH_USERS_TABLE,
-    AIRTABLE_AUTH_USER_LOGINS_TABLE, AIRTABLE_BASE_ID_CUSTOMER_LEADS,
-    AIRTABLE_BASE_ID_MISC, AIRTABLE_BASE_ID_RACK_ROADMAP,
-    AIRTABLE_BASE_ID_RECURITING_APPLICATIONS,
-    AIRTABLE_JOURNAL_CLUB_MEETINGS_TABLE, AIRTABLE_JOURNAL_CLUB_PAPERS_TABLE,
-    AIRTABLE_MAILING_LIST_SIGNUPS_TABLE, AIRTABLE_RFD_TABLE,
-};
+use crate::airtable::{airtable_table, SyncError};
 use crate::applicants::{
-    email_send_received_application, get_file_contents, ApplicantSheetColumns,
+    email_send_received_application, get_file_contents, ApplicantRow,
 };
 use crate::rfds::{
     clean_rfd_html_links, get_authors, get_rfd_contents_from_repo,
     parse_asciidoc, parse_markdown,
 };
 use crate::schema::{
-    applicants, auth_user_logins, auth_users, github_repos,
-    journal_club_meetings, journal_club_papers, mailing_list_subscribers,
-    rfds as r_f_ds, rfds,
+    airtable_webhook_cursors, applicants, auth_user_logins, auth_users,
+    github_repos, journal_club_meetings, journal_club_papers,
+    mailing_list_subscribers, rfds as r_f_ds, rfds,
 };
 use crate::slack::{
     FormattedMessage, MessageBlock, MessageBlockText, MessageBlockType,
@@ -59,8 +52,9 @@ static QUESTION_WHY_OXIDE: &str = r"W(?s:.*)y do you want to work for Oxide\?";
 /// The data type for a NewApplicant.
 #[db_struct {
     new_name = "Applicant",
-    base_id = "AIRTABLE_BASE_ID_RECURITING_APPLICATIONS",
-    table = "AIRTABLE_APPLICATIONS_TABLE",
+    match_on = ["email", "sheet_id"],
+    airtable_table = "applicants",
+    airtable_fields = ["status"],
 }]
 #[derive(
     Debug,
@@ -140,123 +134,60 @@ pub struct NewApplicant {
 }
 
 impl NewApplicant {
-    /// Parse the applicant from a Google Sheets row.
+    /// Parse the applicant from a Google Sheets row. If we have not sent
+    /// them an email that we received their application, we send it and set
+    /// the cell to `TRUE`.
     pub async fn parse(
         drive_client: &GoogleDrive,
         sheets_client: &Sheets,
         sheet_name: &str,
         sheet_id: &str,
-        columns: &ApplicantSheetColumns,
-        row: &[String],
-        row_index: usize,
+        row: &ApplicantRow,
+        sent_email_received_cell: Option<String>,
     ) -> (Self, bool) {
         // Parse the time.
-        let time_str = row[columns.timestamp].to_string() + " -08:00";
+        let time_str = row.timestamp.to_string() + " -08:00";
         let time =
             DateTime::parse_from_str(&time_str, "%m/%d/%Y %H:%M:%S  %:z")
                 .unwrap()
                 .with_timezone(&Utc);
 
-        // If the length of the row is greater than the status column
-        // then we have a status.
-        let status = if row.len() > columns.status {
-            let mut s = row[columns.status].trim().to_lowercase();
-
-            if s.contains("next steps") {
-                s = "Next steps".to_string();
-            } else if s.contains("deferred") {
-                s = "Deferred".to_string();
-            } else if s.contains("declined") {
-                s = "Declined".to_string();
-            } else if s.contains("hired") {
-                s = "Hired".to_string();
-            } else if s.contains("contractor") || s.contains("consulting") {
-                s = "Consulting".to_string();
-            } else if s.contains("keeping warm") {
-                s = "Keeping warm".to_string();
-            } else {
-                s = "Needs to be triaged".to_string();
-            }
-
-            s
+        // Parse the status, anything else still needs to be triaged.
+        let s = row.status.to_lowercase();
+        let status = if s.contains("next steps") {
+            "Next steps"
+        } else if s.contains("deferred") {
+            "Deferred"
+        } else if s.contains("declined") {
+            "Declined"
+        } else if s.contains("hired") {
+            "Hired"
+        } else if s.contains("contractor") || s.contains("consulting") {
+            "Consulting"
+        } else if s.contains("keeping warm") {
+            "Keeping warm"
         } else {
-            "Needs to be triaged".to_string()
-        };
-
-        // If the length of the row is greater than the linkedin column
-        // then we have a linkedin.
-        let linkedin = if row.len() > columns.linkedin && columns.linkedin != 0
-        {
-            row[columns.linkedin].trim().to_lowercase()
-        } else {
-            "".to_string()
-        };
-
-        // If the length of the row is greater than the portfolio column
-        // then we have a portfolio.
-        let portfolio =
-            if row.len() > columns.portfolio && columns.portfolio != 0 {
-                row[columns.portfolio].trim().to_lowercase()
-            } else {
-                "".to_lowercase()
-            };
+            "Needs to be triaged"
+        }
+        .to_string();
 
-        // If the length of the row is greater than the website column
-        // then we have a website.
-        let website = if row.len() > columns.website && columns.website != 0 {
-            row[columns.website].trim().to_lowercase()
-        } else {
-            "".to_lowercase()
-        };
+        let linkedin = row.linkedin.to_lowercase();
+        let portfolio = row.portfolio.to_lowercase();
+        let website = row.website.to_lowercase();
+        let value_reflected = row.value_reflected.to_lowercase();
+        let value_violated = row.value_violated.to_lowercase();
 
-        // If the length of the row is greater than the value_reflected column
-        // then we have a value_reflected.
-        let value_reflected = if row.len() > columns.value_reflected
-            && columns.value_reflected != 0
-        {
-            row[columns.value_reflected].trim().to_lowercase()
-        } else {
-            "".to_lowercase()
-        };
-
-        // If the length of the row is greater than the value_violated column
-        // then we have a value_violated.
-        let value_violated = if row.len() > columns.value_violated
-            && columns.value_violated != 0
-        {
-            row[columns.value_violated].trim().to_lowercase()
-        } else {
-            "".to_lowercase()
-        };
-
-        let mut values_in_tension: Vec<String> = Default::default();
-        // If the length of the row is greater than the value_in_tension1 column
-        // then we have a value_in_tension1.
-        if row.len() > columns.value_in_tension_1
-            && columns.value_in_tension_1 != 0
-        {
-            values_in_tension
-                .push(row[columns.value_in_tension_1].trim().to_lowercase());
-        }
-        // If the length of the row is greater than the value_in_tension2 column
-        // then we have a value_in_tension2.
-        if row.len() > columns.value_in_tension_2
-            && columns.value_in_tension_2 != 0
-        {
-            values_in_tension
-                .push(row[columns.value_in_tension_2].trim().to_lowercase());
-        }
+        let values_in_tension: Vec<String> =
+            vec![&row.value_in_tension_1, &row.value_in_tension_2]
+                .into_iter()
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_lowercase())
+                .collect();
 
         // Check if we sent them an email that we received their application.
-        let mut sent_email_received = true;
-        if row[columns.sent_email_received]
-            .to_lowercase()
-            .contains("false")
-        {
-            sent_email_received = false;
-        }
+        let sent_email_received = row.sent_email_received;
 
-        let email = row[columns.email].to_string();
+        let email = row.email.to_string();
 
         let mut is_new_applicant = false;
 
@@ -276,17 +207,12 @@ impl NewApplicant {
             .await;
 
             // Mark the column as true not false.
-            let mut colmn = "ABCDEFGHIJKLMNOPQRSTUVWXYZ".chars();
-            let rng = format!(
-                "{}{}",
-                colmn.nth(columns.sent_email_received).unwrap().to_string(),
-                row_index + 1
-            );
-
-            sheets_client
-                .update_values(sheet_id, &rng, "TRUE".to_string())
-                .await
-                .unwrap();
+            if let Some(rng) = sent_email_received_cell {
+                sheets_client
+                    .update_values(sheet_id, &rng, "TRUE".to_string())
+                    .await
+                    .unwrap();
+            }
 
             println!(
             "[applicant] sent email to {} that we received their application",
@@ -296,10 +222,10 @@ impl NewApplicant {
 
         let mut github = "".to_string();
         let mut gitlab = "".to_string();
-        if !row[columns.github].trim().is_empty() {
+        if !row.github.trim().is_empty() {
             github = format!(
                 "@{}",
-                row[columns.github]
+                row.github
                     .trim()
                     .to_lowercase()
                     .trim_start_matches("https://github.com/")
@@ -315,7 +241,7 @@ impl NewApplicant {
 
                 gitlab = format!(
                     "@{}",
-                    row[columns.github]
+                    row.github
                         .trim()
                         .to_lowercase()
                         .trim_start_matches("https://gitlab.com/")
@@ -325,9 +251,10 @@ impl NewApplicant {
             }
         }
 
-        let location = row[columns.location].trim().to_string();
+        let location = row.location.trim().to_string();
 
-        let mut phone = row[columns.phone]
+        let mut phone = row
+            .phone
             .trim()
             .replace(" ", "")
             .replace("-", "")
@@ -446,8 +373,8 @@ impl NewApplicant {
         }
 
         // Read the file contents.
-        let resume = row[columns.resume].to_string();
-        let materials = row[columns.materials].to_string();
+        let resume = row.resume.to_string();
+        let materials = row.materials.to_string();
         let resume_contents = get_file_contents(drive_client, &resume).await;
         let materials_contents =
             get_file_contents(drive_client, &materials).await;
@@ -619,7 +546,7 @@ impl NewApplicant {
         (
             NewApplicant {
                 submitted_time: time,
-                name: row[columns.name].to_string(),
+                name: row.name.to_string(),
                 email,
                 location,
                 phone,
@@ -1122,8 +1049,9 @@ fn parse_question(q1: &str, q2: &str, materials_contents: &str) -> String {
 /// The data type for an NewAuthUser.
 #[db_struct {
     new_name = "AuthUser",
-    base_id = "AIRTABLE_BASE_ID_CUSTOMER_LEADS",
-    table = "AIRTABLE_AUTH_USERS_TABLE",
+    match_on = ["user_id"],
+    airtable_table = "auth_users",
+    airtable_fields = ["link_to_people", "link_to_auth_user_logins"],
 }]
 #[serde(rename_all = "camelCase")]
 #[derive(
@@ -1181,8 +1109,8 @@ pub struct NewAuthUser {
 /// The data type for a NewAuthUserLogin.
 #[db_struct {
     new_name = "AuthUserLogin",
-    base_id = "AIRTABLE_BASE_ID_CUSTOMER_LEADS",
-    table = "AIRTABLE_AUTH_USER_LOGINS_TABLE",
+    match_on = ["user_id", "date"],
+    airtable_table = "auth_user_logins",
 }]
 #[derive(
     Debug, Insertable, AsChangeset, PartialEq, Clone, Deserialize, Serialize,
@@ -1239,8 +1167,9 @@ pub struct NewAuthUserLogin {
 /// The data type for a NewJournalClubMeeting.
 #[db_struct {
     new_name = "JournalClubMeeting",
-    base_id = "AIRTABLE_BASE_ID_MISC",
-    table = "AIRTABLE_JOURNAL_CLUB_MEETINGS_TABLE",
+    match_on = ["issue"],
+    airtable_table = "journal_club_meetings",
+    airtable_fields = ["papers"],
 }]
 #[derive(
     Debug, Insertable, AsChangeset, PartialEq, Clone, Deserialize, Serialize,
@@ -1346,8 +1275,8 @@ impl JournalClubMeeting {
 /// The data type for a NewJournalClubPaper.
 #[db_struct {
     new_name = "JournalClubPaper",
-    base_id = "AIRTABLE_BASE_ID_MISC",
-    table = "AIRTABLE_JOURNAL_CLUB_PAPERS_TABLE",
+    match_on = ["link"],
+    airtable_table = "journal_club_papers",
 }]
 #[derive(
     Debug, Insertable, AsChangeset, PartialEq, Clone, Deserialize, Serialize,
@@ -1367,8 +1296,9 @@ pub struct NewJournalClubPaper {
 /// The data type for a MailingListSubscriber.
 #[db_struct {
     new_name = "MailingListSubscriber",
-    base_id = "AIRTABLE_BASE_ID_CUSTOMER_LEADS",
-    table = "AIRTABLE_MAILING_LIST_SIGNUPS_TABLE",
+    match_on = ["email"],
+    airtable_table = "mailing_list",
+    airtable_fields = ["link_to_people"],
 }]
 #[serde(rename_all = "camelCase")]
 #[derive(
@@ -1414,27 +1344,30 @@ pub struct NewMailingListSubscriber {
 }
 
 impl NewMailingListSubscriber {
-    /// Push the mailing list signup to our Airtable workspace.
-    pub async fn push_to_airtable(&self) {
+    /// Push the mailing list signup to our Airtable workspace, updating the
+    /// existing row if they already signed up.
+    pub async fn push_to_airtable(&self) -> Result<(), SyncError> {
+        let table = airtable_table("mailing_list")?;
+
         // Initialize the Airtable client.
-        let airtable =
-            Airtable::new(airtable_api_key(), AIRTABLE_BASE_ID_CUSTOMER_LEADS);
+        let airtable = table.client();
 
         // Create the record.
         let record = Record {
             id: None,
             created_time: None,
-            fields: serde_json::to_value(self).unwrap(),
+            fields: self.clone(),
         };
 
-        // Send the new record to the Airtable client.
-        // Batch can only handle 10 at a time.
+        // Send the record to the Airtable client, matching on the email so
+        // people who sign up twice don't get two rows.
         airtable
-            .create_records(AIRTABLE_MAILING_LIST_SIGNUPS_TABLE, vec![record])
+            .upsert_records(&table.table, "email", vec![record])
             .await
-            .unwrap();
+            .map_err(|e| e.error)?;
 
-        println!("created mailing list record in Airtable: {:?}", self);
+        println!("upserted mailing list record in Airtable: {:?}", self);
+        Ok(())
     }
 
     /// Get the human duration of time since the signup was fired.
@@ -1597,6 +1530,7 @@ impl ToSql<Jsonb, Pg> for GitHubUser {
 /// The data type for a GitHub repository.
 #[db_struct {
     new_name = "GithubRepo",
+    match_on = ["full_name"],
 }]
 #[serde(rename_all = "camelCase")]
 #[derive(
@@ -1817,8 +1751,9 @@ impl NewRepo {
 /// The data type for an RFD.
 #[db_struct {
     new_name = "RFD",
-    base_id = "AIRTABLE_BASE_ID_RACK_ROADMAP",
-    table = "AIRTABLE_RFD_TABLE",
+    match_on = ["number"],
+    airtable_table = "rfds",
+    airtable_fields = ["milestones", "relevant_components"],
 }]
 #[serde(rename_all = "camelCase")]
 #[derive(
@@ -1962,3 +1897,18 @@ impl RFD {
         msg
     }
 }
+
+/// The data type for an AirtableWebhookCursor, the cursor of the next
+/// payload of a webhook we have not applied yet.
+#[db_struct {
+    new_name = "AirtableWebhookCursor",
+    match_on = ["webhook_id"],
+}]
+#[derive(
+    Debug, Insertable, AsChangeset, PartialEq, Clone, Deserialize, Serialize,
+)]
+#[table_name = "airtable_webhook_cursors"]
+pub struct NewAirtableWebhookCursor {
+    pub webhook_id: String,
+    pub next_cursor: i64,
+}
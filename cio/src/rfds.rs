@@ -1,4 +1,3 @@
-use airtable_api::{Airtable, Record};
 use std::collections::BTreeMap;
 use std::env;
 use std::fs;
@@ -11,12 +10,9 @@ use csv::ReaderBuilder;
 use hubcaps::Github;
 use regex::Regex;
 
-use crate::airtable::{
-    airtable_api_key, AIRTABLE_BASE_ID_RACK_ROADMAP, AIRTABLE_GRID_VIEW,
-    AIRTABLE_RFD_TABLE,
-};
-use crate::db::Database;
-use crate::models::{NewRFD, RFD};
+use crate::airtable::{sync_airtable, SyncError};
+use crate::db::{log_or_fail, Database, DbError};
+use crate::models::NewRFD;
 use crate::utils::github_org;
 
 /// Get the RFDs from the rfd GitHub repo.
@@ -187,61 +183,19 @@ pub fn get_authors(content: &str, is_markdown: bool) -> String {
     }
 }
 
-pub async fn refresh_airtable_rfds() {
-    // Initialize the Airtable client.
-    let airtable =
-        Airtable::new(airtable_api_key(), AIRTABLE_BASE_ID_RACK_ROADMAP);
-
-    let records = airtable
-        .list_records(AIRTABLE_RFD_TABLE, AIRTABLE_GRID_VIEW, vec![])
-        .await
-        .unwrap();
-
-    let mut airtable_rfds: BTreeMap<i32, (Record, RFD)> = Default::default();
-    for record in records {
-        let fields: RFD =
-            serde_json::from_value(record.fields.clone()).unwrap();
-
-        airtable_rfds.insert(fields.id, (record, fields));
+pub async fn refresh_airtable_rfds(db: &Database) -> Result<(), SyncError> {
+    let mut rfds = db.get_rfds()?;
+    for rfd in rfds.iter_mut() {
+        // Airtable can only hold 100,000 chars. IDK which one is that long but LOL
+        // https://community.airtable.com/t/what-is-the-long-text-character-limit/1780
+        rfd.content = truncate(&rfd.content, 100000);
+        rfd.html = truncate(&rfd.html, 100000);
     }
 
-    // Initialize our database.
-    let db = Database::new();
-    let rfds = db.get_rfds();
-
-    let mut updated: i32 = 0;
-    for mut rfd in rfds {
-        // See if we have it in our fields.
-        match airtable_rfds.get(&rfd.id) {
-            Some((r, in_airtable_fields)) => {
-                let mut record = r.clone();
-
-                // Set the Link to People from the original so it stays intact.
-                rfd.milestones = in_airtable_fields.milestones.clone();
-                rfd.relevant_components =
-                    in_airtable_fields.relevant_components.clone();
-                // Airtable can only hold 100,000 chars. IDK which one is that long but LOL
-                // https://community.airtable.com/t/what-is-the-long-text-character-limit/1780
-                rfd.content = truncate(&rfd.content, 100000);
-                rfd.html = truncate(&rfd.html, 100000);
-
-                record.fields = json!(rfd);
-
-                airtable
-                    .update_records(AIRTABLE_RFD_TABLE, vec![record.clone()])
-                    .await
-                    .unwrap();
-
-                updated += 1;
-            }
-            None => {
-                // Create the record.
-                rfd.push_to_airtable().await;
-            }
-        }
-    }
+    let report = sync_airtable(rfds).await?;
+    println!("synced rfds to airtable: {}", report);
 
-    println!("updated {} rfds", updated);
+    Ok(())
 }
 
 fn truncate(s: &str, max_chars: usize) -> String {
@@ -252,20 +206,31 @@ fn truncate(s: &str, max_chars: usize) -> String {
 }
 
 // Sync the rfds with our database.
-pub async fn refresh_db_rfds(github: &Github) {
+pub async fn refresh_db_rfds(
+    db: &Database,
+    github: &Github,
+) -> Result<(), DbError> {
     let rfds = get_rfds_from_repo(github).await;
 
-    // Initialize our database.
-    let db = Database::new();
-
     // Sync rfds.
-    for (_, rfd) in rfds {
-        db.upsert_rfd(&rfd);
+    for (_, mut rfd) in rfds {
+        // The milestones and components are edited in Airtable, keep the
+        // ones we have instead of resetting them.
+        let upserted = db.get_rfd_by_number(rfd.number).and_then(|existing| {
+            if let Some(existing) = existing {
+                rfd.keep_airtable_fields(&existing);
+            }
+            db.upsert_rfd(&rfd)
+        });
+        log_or_fail(upserted, format_args!("upserting rfd {}", rfd.number))?;
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::db::Database;
     use crate::rfds::{
         clean_rfd_html_links, get_authors, refresh_airtable_rfds,
         refresh_db_rfds,
@@ -274,8 +239,9 @@ mod tests {
 
     #[tokio::test(threaded_scheduler)]
     async fn test_rfds() {
+        let db = Database::new().unwrap();
         let github = authenticate_github();
-        refresh_db_rfds(&github).await;
+        refresh_db_rfds(&db, &github).await.unwrap();
     }
 
     #[test]
@@ -339,6 +305,7 @@ authors: nope"#;
 
     #[tokio::test(threaded_scheduler)]
     async fn test_rfds_airtable() {
-        refresh_airtable_rfds().await;
+        let db = Database::new().unwrap();
+        refresh_airtable_rfds(&db).await.unwrap();
     }
 }
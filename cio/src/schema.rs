@@ -39,6 +39,14 @@ table! {
     }
 }
 
+table! {
+    airtable_webhook_cursors (id) {
+        id -> Int4,
+        webhook_id -> Varchar,
+        next_cursor -> Int8,
+    }
+}
+
 table! {
     auth_users (id) {
         id -> Int4,
@@ -119,6 +127,16 @@ table! {
     }
 }
 
+table! {
+    config_api_changes (id) {
+        id -> Int4,
+        section -> Varchar,
+        name -> Varchar,
+        deleted -> Bool,
+        changed_at -> Timestamptz,
+    }
+}
+
 table! {
     github_labels (id) {
         id -> Int4,
@@ -315,11 +333,13 @@ table! {
 }
 
 allow_tables_to_appear_in_same_query!(
+    airtable_webhook_cursors,
     applicants,
     auth_user_logins,
     auth_users,
     buildings,
     conference_rooms,
+    config_api_changes,
     github_labels,
     github_repos,
     groups,
@@ -4,9 +4,12 @@ use chrono::offset::Utc;
 use chrono::serde::ts_seconds;
 use chrono::DateTime;
 use reqwest::{Body, Client, StatusCode};
+use ring::{constant_time, hmac};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::db::{Database, DbError};
+
 /// The Slack app webhook URL for our app to post to the #hiring channel.
 pub fn get_hiring_channel_post_url() -> String {
     env::var("SLACK_HIRING_CHANNEL_POST_URL").unwrap()
@@ -17,6 +20,58 @@ pub fn get_public_relations_channel_post_url() -> String {
     env::var("SLACK_PUBLIC_RELATIONS_CHANNEL_POST_URL").unwrap()
 }
 
+/// The secret Slack signs the requests to our app with.
+pub fn get_slack_signing_secret() -> String {
+    env::var("SLACK_SIGNING_SECRET").unwrap_or_default()
+}
+
+/// How old a signed request from Slack can be before we reject it, this
+/// protects against replay attacks.
+const SLACK_SIGNATURE_MAX_AGE_SECONDS: i64 = 60 * 5;
+
+/// Verify the signature Slack sends in the `X-Slack-Signature` header with
+/// the timestamp from the `X-Slack-Request-Timestamp` header.
+///
+/// Docs: https://api.slack.com/authentication/verifying-requests-from-slack
+pub fn verify_slack_signature(
+    signing_secret: &str,
+    timestamp: &str,
+    signature: &str,
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> bool {
+    if signing_secret.is_empty() {
+        return false;
+    }
+
+    let ts: i64 = match timestamp.parse() {
+        Ok(ts) => ts,
+        Err(_) => return false,
+    };
+    if (now.timestamp() - ts).abs() > SLACK_SIGNATURE_MAX_AGE_SECONDS {
+        return false;
+    }
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, signing_secret.as_bytes());
+    let mut ctx = hmac::Context::with_key(&key);
+    ctx.update(format!("v0:{}:", timestamp).as_bytes());
+    ctx.update(body);
+    let expected = format!(
+        "v0={}",
+        ctx.sign()
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    );
+
+    constant_time::verify_slices_are_equal(
+        expected.as_bytes(),
+        signature.as_bytes(),
+    )
+    .is_ok()
+}
+
 /// Post text to a channel.
 pub async fn post_to_channel(url: String, v: Value) {
     let client = Client::new();
@@ -73,6 +128,7 @@ impl Default for MessageResponseType {
 ///
 /// Docs: https://api.slack.com/interactivity/slash-commands#app_command_handling
 #[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct BotCommand {
     pub user_name: String,
     pub command: String,
@@ -88,6 +144,193 @@ pub struct BotCommand {
     pub user_id: String,
 }
 
+/// The slash commands our bot knows how to answer.
+#[derive(Debug, PartialEq)]
+pub enum SlashCommand {
+    /// `/rfd 123` looks up an RFD by its number.
+    RFDNumber(i32),
+    /// `/rfd <search>` searches the RFDs by title.
+    RFDSearch(String),
+    /// `/applicants <name>` searches the applicants by name.
+    Applicants(String),
+    /// `/meet <room>` looks up a conference room.
+    Meet(String),
+    /// `/link <name>` looks up one of our internal links.
+    Link(String),
+    /// A command we don't know about, or one without any text.
+    Unknown(String),
+}
+
+impl BotCommand {
+    /// Parse the command and its text.
+    pub fn parse(&self) -> SlashCommand {
+        let text = self.text.trim().to_string();
+        if text.is_empty() {
+            return SlashCommand::Unknown(self.command.to_string());
+        }
+
+        match self.command.as_str() {
+            "/rfd" => match text.trim_start_matches('#').parse::<i32>() {
+                Ok(n) => SlashCommand::RFDNumber(n),
+                Err(_) => SlashCommand::RFDSearch(text),
+            },
+            "/applicants" => SlashCommand::Applicants(text),
+            "/meet" => SlashCommand::Meet(text),
+            "/link" => SlashCommand::Link(text),
+            c => SlashCommand::Unknown(c.to_string()),
+        }
+    }
+}
+
+/// The maximum number of results we answer a search with.
+const MAX_SEARCH_RESULTS: i64 = 5;
+
+/// Run a bot command and return the message to respond to Slack with.
+pub fn handle_bot_command(
+    db: &Database,
+    command: &BotCommand,
+) -> Result<Value, DbError> {
+    let response = match command.parse() {
+        SlashCommand::RFDNumber(n) => match db.get_rfd_by_number(n)? {
+            Some(rfd) => text_response(
+                MessageResponseType::InChannel,
+                rfd.as_slack_msg(),
+            ),
+            None => text_response(
+                MessageResponseType::Ephemeral,
+                format!("RFD {} does not exist", n),
+            ),
+        },
+        SlashCommand::RFDSearch(search) => {
+            let rfds = db.search_rfds(&search, MAX_SEARCH_RESULTS)?;
+            if rfds.is_empty() {
+                text_response(
+                    MessageResponseType::Ephemeral,
+                    format!("no RFDs match `{}`", search),
+                )
+            } else {
+                text_response(
+                    MessageResponseType::InChannel,
+                    rfds.iter()
+                        .map(|rfd| rfd.as_slack_msg())
+                        .collect::<Vec<String>>()
+                        .join("\n"),
+                )
+            }
+        }
+        SlashCommand::Applicants(name) => {
+            let applicants = db.search_applicants(&name, MAX_SEARCH_RESULTS)?;
+            if applicants.is_empty() {
+                text_response(
+                    MessageResponseType::Ephemeral,
+                    format!("no applicants match `{}`", name),
+                )
+            } else {
+                // Applicants have personal information in them, so only the
+                // person that ran the command gets to see them.
+                let mut blocks: Vec<Value> = Default::default();
+                for (i, applicant) in applicants.iter().enumerate() {
+                    if i > 0 {
+                        blocks.push(json!(MessageBlock {
+                            block_type: MessageBlockType::Divider,
+                            ..Default::default()
+                        }));
+                    }
+                    if let Value::Array(b) =
+                        applicant.as_slack_msg()["blocks"].take()
+                    {
+                        blocks.extend(b);
+                    }
+                }
+
+                json!({
+                    "response_type": MessageResponseType::Ephemeral,
+                    "blocks": blocks,
+                })
+            }
+        }
+        SlashCommand::Meet(name) => {
+            let room = match db.get_conference_room_by_name(&name)? {
+                Some(room) => Some(room),
+                None => db.get_conference_rooms()?.into_iter().find(|r| {
+                    r.name.to_lowercase().contains(&name.to_lowercase())
+                }),
+            };
+
+            match room {
+                Some(room) => text_response(
+                    MessageResponseType::InChannel,
+                    format!(
+                        "*{}* {} | {}, floor {} {} | seats {}",
+                        room.name,
+                        room.description,
+                        room.building,
+                        room.floor,
+                        room.section,
+                        room.capacity
+                    ),
+                ),
+                None => text_response(
+                    MessageResponseType::Ephemeral,
+                    format!("no conference rooms match `{}`", name),
+                ),
+            }
+        }
+        SlashCommand::Link(name) => {
+            let link = match db.get_link_by_name(&name)? {
+                Some(link) => Some(link),
+                None => db
+                    .get_links()?
+                    .into_iter()
+                    .find(|l| l.aliases.iter().any(|a| *a == name)),
+            };
+
+            match link {
+                Some(link) => text_response(
+                    MessageResponseType::InChannel,
+                    format!(
+                        "<{}|{}> {}",
+                        link.link, link.name, link.description
+                    ),
+                ),
+                None => text_response(
+                    MessageResponseType::Ephemeral,
+                    format!("link `{}` does not exist", name),
+                ),
+            }
+        }
+        SlashCommand::Unknown(c) => text_response(
+            MessageResponseType::Ephemeral,
+            format!(
+                "sorry, I don't know how to answer `{}`, {}",
+                format!("{} {}", c, command.text.trim()).trim(),
+                command_usage(&c)
+            ),
+        ),
+    };
+
+    Ok(response)
+}
+
+/// A plain text response to a command.
+fn text_response(response_type: MessageResponseType, text: String) -> Value {
+    json!(MessageResponse {
+        response_type,
+        text,
+    })
+}
+
+/// Help text for a command.
+fn command_usage(command: &str) -> &'static str {
+    match command {
+        "/rfd" => "try `/rfd 123` or `/rfd <search>`",
+        "/applicants" => "try `/applicants <name>`",
+        "/meet" => "try `/meet <room>`",
+        "/link" => "try `/link <name>`",
+        _ => "try `/rfd`, `/applicants`, `/meet` or `/link`",
+    }
+}
+
 /// A formatted message to send to Slack.
 ///
 /// Docs: https://api.slack.com/messaging/composing/layouts
@@ -215,3 +458,108 @@ pub struct MessageAttachmentField {
     pub title: String,
     pub value: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::slack::{verify_slack_signature, BotCommand, SlashCommand};
+
+    /// The signed request from the Slack docs.
+    const SLACK_DOCS_SECRET: &str = "8f742231b10e8888abcd99yyyzzz85a5";
+    const SLACK_DOCS_TIMESTAMP: &str = "1531420618";
+    const SLACK_DOCS_SIGNATURE: &str =
+        "v0=a2114d57b48eac39b9ad189dd8316235a7b4a8d21a10bd27519666489c69b503";
+    const SLACK_DOCS_BODY: &str = "token=xyzz0WbapA4vBCDEFasx0q6G\
+        &team_id=T1DC2JH3J&team_domain=testteamnow&channel_id=G8PSS9T3V\
+        &channel_name=foobar&user_id=U2CERLKJA&user_name=roadrunner\
+        &command=%2Fwebhook-collect&text=\
+        &response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2FT1DC2JH3J\
+        %2F397700885554%2F96rGlfmibIGlgcZRskXaIFfN\
+        &trigger_id=398738663015.47445629121.803a0bc887a14d10d2c447fce8b6703c";
+
+    /// A `/rfd 123` command, signed with a test secret.
+    const RFD_SECRET: &str = "test-signing-secret";
+    const RFD_TIMESTAMP: &str = "1600000000";
+    const RFD_SIGNATURE: &str =
+        "v0=5cf214384c4d0a8d70c2d997dcfca6055b05d24951dc2635eb14a9b370014d7d";
+    const RFD_BODY: &str = "token=test&team_id=T0001&team_domain=oxide\
+        &channel_id=C0001&channel_name=general&user_id=U0001\
+        &user_name=jess&command=%2Frfd&text=123\
+        &response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2F1234\
+        &trigger_id=1234.5678";
+
+    #[test]
+    fn test_verify_slack_signature() {
+        let now = Utc.timestamp(1531420618 + 60, 0);
+        assert!(verify_slack_signature(
+            SLACK_DOCS_SECRET,
+            SLACK_DOCS_TIMESTAMP,
+            SLACK_DOCS_SIGNATURE,
+            SLACK_DOCS_BODY.as_bytes(),
+            now,
+        ));
+
+        // The wrong secret.
+        assert!(!verify_slack_signature(
+            RFD_SECRET,
+            SLACK_DOCS_TIMESTAMP,
+            SLACK_DOCS_SIGNATURE,
+            SLACK_DOCS_BODY.as_bytes(),
+            now,
+        ));
+
+        // A tampered body.
+        let body = SLACK_DOCS_BODY.replace("roadrunner", "coyote");
+        assert!(!verify_slack_signature(
+            SLACK_DOCS_SECRET,
+            SLACK_DOCS_TIMESTAMP,
+            SLACK_DOCS_SIGNATURE,
+            body.as_bytes(),
+            now,
+        ));
+
+        // A replayed request.
+        assert!(!verify_slack_signature(
+            SLACK_DOCS_SECRET,
+            SLACK_DOCS_TIMESTAMP,
+            SLACK_DOCS_SIGNATURE,
+            SLACK_DOCS_BODY.as_bytes(),
+            Utc.timestamp(1531420618 + 60 * 10, 0),
+        ));
+    }
+
+    #[test]
+    fn test_bot_command_parse() {
+        let now = Utc.timestamp(1600000000, 0);
+        assert!(verify_slack_signature(
+            RFD_SECRET,
+            RFD_TIMESTAMP,
+            RFD_SIGNATURE,
+            RFD_BODY.as_bytes(),
+            now,
+        ));
+
+        let command: BotCommand =
+            serde_urlencoded::from_bytes(RFD_BODY.as_bytes()).unwrap();
+        assert_eq!(command.user_name, "jess");
+        assert_eq!(command.parse(), SlashCommand::RFDNumber(123));
+
+        let command: BotCommand =
+            serde_urlencoded::from_bytes(SLACK_DOCS_BODY.as_bytes()).unwrap();
+        assert_eq!(
+            command.parse(),
+            SlashCommand::Unknown("/webhook-collect".to_string())
+        );
+
+        let command = BotCommand {
+            command: "/rfd".to_string(),
+            text: "single sign-on".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            command.parse(),
+            SlashCommand::RFDSearch("single sign-on".to_string())
+        );
+    }
+}
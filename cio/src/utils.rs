@@ -3,6 +3,7 @@ use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
 
+use gauth::TokenProvider;
 use hubcaps::http_cache::FileBasedCache;
 use hubcaps::issues::Issue;
 use hubcaps::repositories::OrgRepoType;
@@ -10,11 +11,9 @@ use hubcaps::repositories::OrganizationRepoListOptions;
 use hubcaps::{Credentials, Github};
 use reqwest::get;
 use reqwest::Client;
-use yup_oauth2::{
-    read_service_account_key, AccessToken, ServiceAccountAuthenticator,
-};
+use yup_oauth2::{read_service_account_key, ServiceAccountAuthenticator};
 
-use crate::db::Database;
+use crate::db::{log_or_fail, Database, DbError};
 use crate::models::NewRepo;
 
 /// Write a file.
@@ -29,8 +28,9 @@ pub fn write_file(file: PathBuf, contents: String) {
     println!("wrote file: {}", file.to_str().unwrap());
 }
 
-/// Get a GSuite token.
-pub async fn get_gsuite_token() -> AccessToken {
+/// Get a provider of GSuite tokens. It gets a new token before the current
+/// one expires, so clients using it keep working for as long as they run.
+pub async fn get_gsuite_token() -> TokenProvider {
     // Get the GSuite credentials file.
     let gsuite_credential_file = env::var("GADMIN_CREDENTIAL_FILE").unwrap();
     let gsuite_subject = env::var("GADMIN_SUBJECT").unwrap();
@@ -43,22 +43,22 @@ pub async fn get_gsuite_token() -> AccessToken {
         .await
         .expect("failed to create authenticator");
 
-    // Add the scopes to the secret and get the token.
-    let token = auth
-        .token(&[
+    // Add the scopes to the secret.
+    let token = TokenProvider::new(
+        auth,
+        &[
             "https://www.googleapis.com/auth/admin.directory.group",
             "https://www.googleapis.com/auth/admin.directory.resource.calendar",
             "https://www.googleapis.com/auth/admin.directory.user",
             "https://www.googleapis.com/auth/apps.groups.settings",
             "https://www.googleapis.com/auth/spreadsheets",
             "https://www.googleapis.com/auth/drive",
-        ])
-        .await
-        .expect("failed to get token");
+        ],
+    );
 
-    if token.as_str().is_empty() {
-        panic!("empty token is not valid");
-    }
+    // Get the first token now so bad credentials fail here and not in the
+    // middle of a sync.
+    token.token().await.expect("failed to get token");
 
     token
 }
@@ -134,26 +134,33 @@ pub async fn list_all_github_repos(github: &Github) -> Vec<NewRepo> {
 }
 
 // Sync the repos with our database.
-pub async fn refresh_db_github_repos(github: &Github) {
+pub async fn refresh_db_github_repos(
+    db: &Database,
+    github: &Github,
+) -> Result<(), DbError> {
     let github_repos = list_all_github_repos(github).await;
 
-    // Initialize our database.
-    let db = Database::new();
-
     // Sync github_repos.
     for github_repo in github_repos {
-        db.upsert_github_repo(&github_repo);
+        log_or_fail(
+            db.upsert_github_repo(&github_repo),
+            format_args!("upserting github repo {}", github_repo.full_name),
+        )?;
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::db::Database;
     use crate::utils::authenticate_github;
     use crate::utils::refresh_db_github_repos;
 
     #[tokio::test(threaded_scheduler)]
     async fn test_github_repos() {
+        let db = Database::new().unwrap();
         let github = authenticate_github();
-        refresh_db_github_repos(&github).await;
+        refresh_db_github_repos(&db, &github).await.unwrap();
     }
 }
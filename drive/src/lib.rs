@@ -8,6 +8,7 @@
  * ```
  * use std::env;
  *
+ * use gauth::TokenProvider;
  * use google_drive::GoogleDrive;
  * use yup_oauth2::{read_service_account_key, ServiceAccountAuthenticator};
  *
@@ -25,15 +26,12 @@
  *         .await
  *         .expect("failed to create authenticator");
  *
- *     // Add the scopes to the secret and get the token.
- *     let token = auth
- *         .token(&["https://www.googleapis.com/auth/drive"])
- *         .await
- *         .expect("failed to get token");
- *
- *     if token.as_str().is_empty() {
- *         panic!("empty token is not valid");
- *     }
+ *     // Get tokens for the scopes, a new one is fetched before the current
+ *     // one expires.
+ *     let token = TokenProvider::new(
+ *         auth,
+ *         &["https://www.googleapis.com/auth/drive"],
+ *     );
  *
  *     // Initialize the Google Drive client.
  *     let drive_client = GoogleDrive::new(token);
@@ -57,42 +55,57 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use bytes::Bytes;
+use chrono::{DateTime, SecondsFormat, Utc};
+use gauth::{TokenError, TokenProvider};
 use reqwest::{header, Client, Method, Request, StatusCode, Url};
 use serde::{Deserialize, Serialize};
-use yup_oauth2::AccessToken;
 
 /// The endpoint for the Google Drive API.
 const ENDPOINT: &str = "https://www.googleapis.com/drive/v3/";
 
+/// The mime type of a folder.
+pub const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
 /// Entrypoint for interacting with the Google Drive API.
 pub struct GoogleDrive {
-    token: AccessToken,
+    token: TokenProvider,
+    endpoint: String,
 
     client: Arc<Client>,
 }
 
 impl GoogleDrive {
-    /// Create a new Drive client struct. It takes a type that can convert into
-    /// an &str (`String` or `Vec<u8>` for example). As long as the function is
-    /// given a valid API Key and Secret your requests will work.
-    pub fn new(token: AccessToken) -> Self {
+    /// Create a new Drive client struct. It takes a token provider, which gets
+    /// a new token before the current one expires so the client keeps working.
+    pub fn new(token: TokenProvider) -> Self {
         let client =
             Client::builder().timeout(Duration::from_secs(360)).build();
         match client {
             Ok(c) => Self {
                 token,
+                endpoint: ENDPOINT.to_string(),
                 client: Arc::new(c),
             },
             Err(e) => panic!("creating client failed: {:?}", e),
         }
     }
 
-    /// Get the currently set authorization token.
-    pub fn get_token(&self) -> &AccessToken {
+    /// Send requests to a different endpoint than the Drive API, for example
+    /// a proxy. The endpoint must end with a `/`.
+    pub fn with_endpoint<E>(mut self, endpoint: E) -> Self
+    where
+        E: ToString,
+    {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
+    /// Get the provider of the authorization token.
+    pub fn get_token(&self) -> &TokenProvider {
         &self.token
     }
 
-    fn request<B>(
+    async fn request<B>(
         &self,
         method: Method,
         path: String,
@@ -101,7 +114,7 @@ impl GoogleDrive {
         content_length: u64,
         content: String,
         mime_type: &str,
-    ) -> Request
+    ) -> Result<Request, APIError>
     where
         B: Serialize,
     {
@@ -109,19 +122,17 @@ impl GoogleDrive {
         let url = if !path.starts_with("http") {
             // Build the URL from our endpoint instead since a full URL was not
             // passed.
-            let base = Url::parse(ENDPOINT).unwrap();
+            let base = Url::parse(&self.endpoint).unwrap();
             base.join(&path).unwrap()
         } else {
             // Parse the full URL.
             Url::parse(&path).unwrap()
         };
 
-        // Check if the token is expired and panic.
-        if self.token.is_expired() {
-            panic!("token is expired");
-        }
+        // Get the token, this refreshes it if it is about to expire.
+        let token = self.token.token().await?;
 
-        let bt = format!("Bearer {}", self.token.as_str());
+        let bt = format!("Bearer {}", token.access_token);
         let bearer = header::HeaderValue::from_str(&bt).unwrap();
 
         // Set the default headers.
@@ -181,7 +192,7 @@ impl GoogleDrive {
         }
 
         // Build the request.
-        rb.build().unwrap()
+        Ok(rb.build().unwrap())
     }
 
     /// Download a file stored on Google Drive by it's ID.
@@ -190,18 +201,20 @@ impl GoogleDrive {
         id: &str,
     ) -> Result<Bytes, APIError> {
         // Build the request.
-        let request = self.request(
-            Method::GET,
-            format!("files/{}", id),
-            (),
-            Some(vec![
-                ("supportsAllDrives", "true".to_string()),
-                ("alt", "media".to_string()),
-            ]),
-            0,
-            "".to_string(),
-            "",
-        );
+        let request = self
+            .request(
+                Method::GET,
+                format!("files/{}", id),
+                (),
+                Some(vec![
+                    ("supportsAllDrives", "true".to_string()),
+                    ("alt", "media".to_string()),
+                ]),
+                0,
+                "".to_string(),
+                "",
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -223,15 +236,17 @@ impl GoogleDrive {
         id: &str,
     ) -> Result<String, APIError> {
         // Build the request.
-        let request = self.request(
-            Method::GET,
-            format!("files/{}/export", id),
-            (),
-            Some(vec![("mimeType", "text/plain".to_string())]),
-            0,
-            "".to_string(),
-            "",
-        );
+        let request = self
+            .request(
+                Method::GET,
+                format!("files/{}/export", id),
+                (),
+                Some(vec![("mimeType", "text/plain".to_string())]),
+                0,
+                "".to_string(),
+                "",
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -251,15 +266,17 @@ impl GoogleDrive {
     /// Get a file by it's ID.
     pub async fn get_file_by_id(&self, id: &str) -> Result<File, APIError> {
         // Build the request.
-        let request = self.request(
-            Method::GET,
-            format!("files/{}", id),
-            (),
-            Some(vec![("supportsAllDrives", "true".to_string())]),
-            0,
-            "".to_string(),
-            "",
-        );
+        let request = self
+            .request(
+                Method::GET,
+                format!("files/{}", id),
+                (),
+                Some(vec![("supportsAllDrives", "true".to_string())]),
+                0,
+                "".to_string(),
+                "",
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -282,22 +299,57 @@ impl GoogleDrive {
         drive_id: &str,
         name: &str,
     ) -> Result<Vec<File>, APIError> {
+        self.list_files(&FileQuery::new().in_drive(drive_id).name(name))
+            .await
+    }
+
+    /// List the files that match a query, going through all the pages of
+    /// results.
+    pub async fn list_files(
+        &self,
+        query: &FileQuery,
+    ) -> Result<Vec<File>, APIError> {
+        let mut files: Vec<File> = Default::default();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let page =
+                self.list_files_page(query, page_token.as_deref()).await?;
+            files.extend(page.files);
+
+            match page.next_page_token {
+                Some(t) if !t.is_empty() => page_token = Some(t),
+                _ => break,
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// List one page of the files that match a query. Pass the
+    /// `next_page_token` of a page to get the page after it.
+    pub async fn list_files_page(
+        &self,
+        query: &FileQuery,
+        page_token: Option<&str>,
+    ) -> Result<FilesResponse, APIError> {
+        let mut params = query.params();
+        if let Some(t) = page_token {
+            params.push(("pageToken", t.to_string()));
+        }
+
         // Build the request.
-        let request = self.request(
-            Method::GET,
-            "files".to_string(),
-            (),
-            Some(vec![
-                ("corpora", "drive".to_string()),
-                ("supportsAllDrives", "true".to_string()),
-                ("includeItemsFromAllDrives", "true".to_string()),
-                ("driveId", drive_id.to_string()),
-                ("q", format!("name = '{}'", name)),
-            ]),
-            0,
-            "".to_string(),
-            "",
-        );
+        let request = self
+            .request(
+                Method::GET,
+                "files".to_string(),
+                (),
+                Some(params),
+                0,
+                "".to_string(),
+                "",
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -311,23 +363,23 @@ impl GoogleDrive {
         };
 
         // Try to deserialize the response.
-        let files_response: FilesResponse = resp.json().await.unwrap();
-
-        Ok(files_response.files)
+        Ok(resp.json().await.unwrap())
     }
 
     /// List drives.
     pub async fn list_drives(&self) -> Result<Vec<Drive>, APIError> {
         // Build the request.
-        let request = self.request(
-            Method::GET,
-            "drives".to_string(),
-            (),
-            Some(vec![("useDomainAdminAccess", "true".to_string())]),
-            0,
-            "".to_string(),
-            "",
-        );
+        let request = self
+            .request(
+                Method::GET,
+                "drives".to_string(),
+                (),
+                Some(vec![("useDomainAdminAccess", "true".to_string())]),
+                0,
+                "".to_string(),
+                "",
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -365,6 +417,202 @@ impl GoogleDrive {
         })
     }
 
+    /// List the permissions on a file or shared drive.
+    pub async fn list_permissions(
+        &self,
+        file_id: &str,
+    ) -> Result<Vec<Permission>, APIError> {
+        let mut permissions: Vec<Permission> = Default::default();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut params = vec![
+                ("supportsAllDrives", "true".to_string()),
+                ("fields", "nextPageToken,permissions".to_string()),
+            ];
+            if let Some(t) = &page_token {
+                params.push(("pageToken", t.to_string()));
+            }
+
+            // Build the request.
+            let request = self
+                .request(
+                    Method::GET,
+                    format!("files/{}/permissions", file_id),
+                    (),
+                    Some(params),
+                    0,
+                    "".to_string(),
+                    "",
+                )
+                .await?;
+
+            let resp = self.client.execute(request).await.unwrap();
+            match resp.status() {
+                StatusCode::OK => (),
+                s => {
+                    return Err(APIError {
+                        status_code: s,
+                        body: resp.text().await.unwrap(),
+                    });
+                }
+            };
+
+            // Try to deserialize the response.
+            let page: PermissionsResponse = resp.json().await.unwrap();
+            permissions.extend(page.permissions);
+
+            match page.next_page_token {
+                Some(t) if !t.is_empty() => page_token = Some(t),
+                _ => break,
+            }
+        }
+
+        Ok(permissions)
+    }
+
+    /// Share a file or shared drive with a user, group, domain or anyone
+    /// with the link. If the grantee already has access, their role is
+    /// changed instead.
+    pub async fn share_file(
+        &self,
+        file_id: &str,
+        grantee: &Grantee,
+        role: Role,
+        options: &ShareOptions,
+    ) -> Result<Permission, APIError> {
+        let (body, params) = share_request(grantee, role, options);
+        self.create_permission(file_id, body, params).await
+    }
+
+    /// Change the role of a permission on a file or shared drive.
+    pub async fn update_permission(
+        &self,
+        file_id: &str,
+        permission_id: &str,
+        role: Role,
+    ) -> Result<Permission, APIError> {
+        // Build the request.
+        let request = self
+            .request(
+                Method::PATCH,
+                format!("files/{}/permissions/{}", file_id, permission_id),
+                NewPermission {
+                    role: role.to_string(),
+                    ..Default::default()
+                },
+                Some(vec![("supportsAllDrives", "true".to_string())]),
+                0,
+                "".to_string(),
+                "",
+            )
+            .await?;
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                });
+            }
+        };
+
+        // Try to deserialize the response.
+        Ok(resp.json().await.unwrap())
+    }
+
+    /// Remove a permission from a file or shared drive, revoking the access
+    /// it gave.
+    pub async fn remove_permission(
+        &self,
+        file_id: &str,
+        permission_id: &str,
+    ) -> Result<(), APIError> {
+        // Build the request.
+        let request = self
+            .request(
+                Method::DELETE,
+                format!("files/{}/permissions/{}", file_id, permission_id),
+                (),
+                Some(vec![("supportsAllDrives", "true".to_string())]),
+                0,
+                "".to_string(),
+                "",
+            )
+            .await?;
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            StatusCode::NO_CONTENT => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                });
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Make a user the owner of a file, the current owner becomes a writer.
+    /// This only works for files in a user's drive, files in shared drives
+    /// are owned by the drive.
+    pub async fn transfer_ownership(
+        &self,
+        file_id: &str,
+        email: &str,
+    ) -> Result<Permission, APIError> {
+        let body = NewPermission {
+            role: Role::Owner.to_string(),
+            type_: "user".to_string(),
+            email_address: Some(email.to_string()),
+            ..Default::default()
+        };
+        let params = vec![
+            ("supportsAllDrives", "true".to_string()),
+            ("transferOwnership", "true".to_string()),
+        ];
+        self.create_permission(file_id, body, params).await
+    }
+
+    async fn create_permission(
+        &self,
+        file_id: &str,
+        body: NewPermission,
+        params: Vec<(&str, String)>,
+    ) -> Result<Permission, APIError> {
+        // Build the request.
+        let request = self
+            .request(
+                Method::POST,
+                format!("files/{}/permissions", file_id),
+                body,
+                Some(params),
+                0,
+                "".to_string(),
+                "",
+            )
+            .await?;
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                });
+            }
+        };
+
+        // Try to deserialize the response.
+        Ok(resp.json().await.unwrap())
+    }
+
     /// Create a folder.
     pub async fn create_folder(
         &self,
@@ -372,11 +620,10 @@ impl GoogleDrive {
         parent_id: &str,
         name: &str,
     ) -> Result<String, APIError> {
-        let folder_mime_type = "application/vnd.google-apps.folder";
         let mut file: File = Default::default();
         // Set the name,
         file.name = Some(name.to_string());
-        file.mime_type = Some(folder_mime_type.to_string());
+        file.mime_type = Some(FOLDER_MIME_TYPE.to_string());
         if !parent_id.is_empty() {
             file.parents = Some(vec![parent_id.to_string()]);
         } else {
@@ -384,18 +631,20 @@ impl GoogleDrive {
         }
 
         // Make the request and return the ID.
-        let request = self.request(
-            Method::POST,
-            "files".to_string(),
-            file,
-            Some(vec![
-                ("supportsAllDrives", "true".to_string()),
-                ("includeItemsFromAllDrives", "true".to_string()),
-            ]),
-            0,
-            "".to_string(),
-            folder_mime_type,
-        );
+        let request = self
+            .request(
+                Method::POST,
+                "files".to_string(),
+                file,
+                Some(vec![
+                    ("supportsAllDrives", "true".to_string()),
+                    ("includeItemsFromAllDrives", "true".to_string()),
+                ]),
+                0,
+                "".to_string(),
+                FOLDER_MIME_TYPE,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -437,19 +686,21 @@ impl GoogleDrive {
         }
 
         // Build the request to get the URL upload location.
-        let request = self.request(
-            Method::POST,
-            "https://www.googleapis.com/upload/drive/v3/files".to_string(),
-            f,
-            Some(vec![
-                ("uploadType", "resumable".to_string()),
-                ("supportsAllDrives", "true".to_string()),
-                ("includeItemsFromAllDrives", "true".to_string()),
-            ]),
-            metadata.len(),
-            "".to_string(),
-            "",
-        );
+        let request = self
+            .request(
+                Method::POST,
+                "https://www.googleapis.com/upload/drive/v3/files".to_string(),
+                f,
+                Some(vec![
+                    ("uploadType", "resumable".to_string()),
+                    ("supportsAllDrives", "true".to_string()),
+                    ("includeItemsFromAllDrives", "true".to_string()),
+                ]),
+                metadata.len(),
+                "".to_string(),
+                "",
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -470,15 +721,17 @@ impl GoogleDrive {
         let contents = fs::read_to_string(file).unwrap();
 
         // Now upload the file to that location.
-        let request = self.request(
-            Method::PUT,
-            location.to_string(),
-            (),
-            None,
-            metadata.len(),
-            contents,
-            mime_type,
-        );
+        let request = self
+            .request(
+                Method::PUT,
+                location.to_string(),
+                (),
+                None,
+                metadata.len(),
+                contents,
+                mime_type,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -532,21 +785,333 @@ impl error::Error for APIError {
     }
 }
 
+impl From<TokenError> for APIError {
+    fn from(e: TokenError) -> Self {
+        // We could not get a token so the request is not authorized.
+        APIError {
+            status_code: StatusCode::UNAUTHORIZED,
+            body: e.to_string(),
+        }
+    }
+}
+
+/// A search for files, sent with `list_files`.
+///
+/// The terms of the search are joined with `and`. By default it searches the
+/// files of the user, use `in_drive` or `all_drives` to search shared
+/// drives.
+///
+/// ```
+/// use google_drive::FileQuery;
+///
+/// let query = FileQuery::new()
+///     .in_drive("drive_id")
+///     .name_contains("Recording")
+///     .trashed(false);
+///
+/// assert_eq!(
+///     query.to_string(),
+///     "name contains 'Recording' and trashed = false"
+/// );
+/// ```
+///
+/// From: https://developers.google.com/drive/api/v3/search-files
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct FileQuery {
+    terms: Vec<String>,
+    corpora: Option<String>,
+    drive_id: Option<String>,
+    fields: Vec<String>,
+    order_by: Option<String>,
+    page_size: Option<u32>,
+}
+
+impl FileQuery {
+    /// Create a query that matches every file.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Match files with exactly this name.
+    pub fn name(self, name: &str) -> Self {
+        self.term(&format!("name = {}", quote(name)))
+    }
+
+    /// Match files with a name that contains this text. Google matches the
+    /// start of words in the name, so `Rec` matches `Meeting Recording` but
+    /// `cording` does not.
+    pub fn name_contains(self, text: &str) -> Self {
+        self.term(&format!("name contains {}", quote(text)))
+    }
+
+    /// Match files with this mime type.
+    pub fn mime_type(self, mime_type: &str) -> Self {
+        self.term(&format!("mimeType = {}", quote(mime_type)))
+    }
+
+    /// Match folders.
+    pub fn folders(self) -> Self {
+        self.mime_type(FOLDER_MIME_TYPE)
+    }
+
+    /// Match files directly in this folder.
+    pub fn parent(self, folder_id: &str) -> Self {
+        self.term(&format!("{} in parents", quote(folder_id)))
+    }
+
+    /// Match files modified after this time.
+    pub fn modified_after(self, time: DateTime<Utc>) -> Self {
+        self.term(&format!(
+            "modifiedTime > {}",
+            quote(&time.to_rfc3339_opts(SecondsFormat::Secs, true))
+        ))
+    }
+
+    /// Match files that are, or are not, in the trash.
+    pub fn trashed(self, trashed: bool) -> Self {
+        self.term(&format!("trashed = {}", trashed))
+    }
+
+    /// Match files with a search term that has no method here, like
+    /// `fullText contains 'hello'`. The term is sent as is.
+    pub fn term(mut self, term: &str) -> Self {
+        self.terms.push(term.to_string());
+        self
+    }
+
+    /// Search the files in a shared drive.
+    pub fn in_drive(mut self, drive_id: &str) -> Self {
+        self.corpora = Some("drive".to_string());
+        self.drive_id = Some(drive_id.to_string());
+        self
+    }
+
+    /// Search the files of the user and every shared drive they are in.
+    pub fn all_drives(mut self) -> Self {
+        self.corpora = Some("allDrives".to_string());
+        self.drive_id = None;
+        self
+    }
+
+    /// Only return these fields of each file, like `id` or `webViewLink`.
+    /// By default only a few fields are returned.
+    pub fn fields(mut self, fields: &[&str]) -> Self {
+        self.fields = fields.iter().map(|f| f.to_string()).collect();
+        self
+    }
+
+    /// Sort the files, like `modifiedTime desc` or `folder,name`.
+    pub fn order_by(mut self, order_by: &str) -> Self {
+        self.order_by = Some(order_by.to_string());
+        self
+    }
+
+    /// The most files to return in each page, up to 1000.
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Get the query parameters for a request to list files.
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![("supportsAllDrives", "true".to_string())];
+
+        if !self.terms.is_empty() {
+            params.push(("q", self.to_string()));
+        }
+        if let Some(corpora) = &self.corpora {
+            params.push(("corpora", corpora.to_string()));
+            params.push(("includeItemsFromAllDrives", "true".to_string()));
+        }
+        if let Some(drive_id) = &self.drive_id {
+            params.push(("driveId", drive_id.to_string()));
+        }
+        if !self.fields.is_empty() {
+            params.push((
+                "fields",
+                format!(
+                    "kind,nextPageToken,incompleteSearch,files({})",
+                    self.fields.join(",")
+                ),
+            ));
+        }
+        if let Some(order_by) = &self.order_by {
+            params.push(("orderBy", order_by.to_string()));
+        }
+        if let Some(page_size) = self.page_size {
+            params.push(("pageSize", page_size.to_string()));
+        }
+
+        params
+    }
+}
+
+impl fmt::Display for FileQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.terms.join(" and "))
+    }
+}
+
+/// Quote a value in a search term, escaping quotes and backslashes in it.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// A page of files.
+///
 /// From: https://developers.google.com/drive/api/v3/reference/files/list
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
-struct FilesResponse {
+pub struct FilesResponse {
     /// Identifies what kind of resource this is. Value: the fixed string "drive#fileList".
+    #[serde(default)]
     pub kind: String,
     /// The page token for the next page of files. This will be absent if the end of the files list has been reached. If the token is rejected for any reason, it should be discarded, and pagination should be restarted from the first page of results.
     #[serde(rename = "nextPageToken", skip_serializing_if = "Option::is_none")]
     pub next_page_token: Option<String>,
     /// Whether the search process was incomplete. If true, then some search results may be missing, since all documents were not searched. This may occur when searching multiple drives with the "allDrives" corpora, but all corpora could not be searched. When this happens, it is suggested that clients narrow their query by choosing a different corpus such as "user" or "drive".
-    #[serde(rename = "incompleteSearch")]
+    #[serde(default, rename = "incompleteSearch")]
     pub incomplete_search: bool,
     /// The list of files. If nextPageToken is populated, then this list may be incomplete and an additional page of results should be fetched.
     pub files: Vec<File>,
 }
 
+/// Who a file is shared with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Grantee {
+    /// A user, by their email address.
+    User(String),
+    /// A group, by its email address.
+    Group(String),
+    /// Everyone in a domain, like `oxide.computer`.
+    Domain(String),
+    /// Anyone with the link.
+    Anyone,
+}
+
+/// The access a permission gives.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Owner,
+    /// Manages a shared drive, only for shared drives.
+    Organizer,
+    /// Manages the files in a shared drive, only for shared drives.
+    FileOrganizer,
+    Writer,
+    Commenter,
+    Reader,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let role = match self {
+            Role::Owner => "owner",
+            Role::Organizer => "organizer",
+            Role::FileOrganizer => "fileOrganizer",
+            Role::Writer => "writer",
+            Role::Commenter => "commenter",
+            Role::Reader => "reader",
+        };
+        write!(f, "{}", role)
+    }
+}
+
+/// Options for sharing a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShareOptions {
+    /// Whether to email users and groups that the file was shared with
+    /// them. Google always emails them when sharing with a non Google
+    /// account.
+    pub send_notification_email: bool,
+    /// A message to add to the email.
+    pub email_message: Option<String>,
+    /// Whether a domain or anyone can find the file by searching, instead of
+    /// only with the link.
+    pub allow_file_discovery: Option<bool>,
+    /// When the access expires, only for users and groups.
+    pub expiration_time: Option<DateTime<Utc>>,
+}
+
+impl Default for ShareOptions {
+    fn default() -> Self {
+        ShareOptions {
+            send_notification_email: true,
+            email_message: None,
+            allow_file_discovery: None,
+            expiration_time: None,
+        }
+    }
+}
+
+/// The body of a request to create or update a permission.
+#[derive(Default, Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NewPermission {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    role: String,
+    #[serde(rename = "type", skip_serializing_if = "String::is_empty")]
+    type_: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    domain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_file_discovery: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expiration_time: Option<String>,
+}
+
+/// Get the body and query parameters of a request to share a file.
+fn share_request(
+    grantee: &Grantee,
+    role: Role,
+    options: &ShareOptions,
+) -> (NewPermission, Vec<(&'static str, String)>) {
+    let (type_, email_address, domain) = match grantee {
+        Grantee::User(email) => ("user", Some(email), None),
+        Grantee::Group(email) => ("group", Some(email), None),
+        Grantee::Domain(domain) => ("domain", None, Some(domain)),
+        Grantee::Anyone => ("anyone", None, None),
+    };
+
+    let body = NewPermission {
+        role: role.to_string(),
+        type_: type_.to_string(),
+        email_address: email_address.cloned(),
+        domain: domain.cloned(),
+        allow_file_discovery: options.allow_file_discovery,
+        expiration_time: options
+            .expiration_time
+            .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true)),
+    };
+
+    let mut params = vec![("supportsAllDrives", "true".to_string())];
+    // Google only emails users and groups.
+    if email_address.is_some() {
+        params.push((
+            "sendNotificationEmail",
+            options.send_notification_email.to_string(),
+        ));
+        if options.send_notification_email {
+            if let Some(message) = &options.email_message {
+                params.push(("emailMessage", message.to_string()));
+            }
+        }
+    }
+
+    (body, params)
+}
+
+/// From: https://developers.google.com/drive/api/v3/reference/permissions/list
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+struct PermissionsResponse {
+    /// The page token for the next page of permissions. This field will be absent if the end of the permissions list has been reached. If the token is rejected for any reason, it should be discarded, and pagination should be restarted from the first page of results.
+    #[serde(rename = "nextPageToken", skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
+    /// The list of permissions. If nextPageToken is populated, then this list may be incomplete and an additional page of results should be fetched.
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
 /// From: https://developers.google.com/drive/api/v3/reference/drives/list
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 struct DrivesResponse {
@@ -1259,3 +1824,199 @@ pub struct User {
     #[serde(rename = "permissionId")]
     pub permission_id: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+
+    use chrono::{DateTime, Utc};
+    use gauth::{Token, TokenProvider};
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use serde_json::json;
+
+    use crate::{
+        share_request, FileQuery, GoogleDrive, Grantee, Role, ShareOptions,
+    };
+
+    /// Serve the canned bodies in order from a local server in place of
+    /// Drive, returning the endpoint and the URIs of the requests made.
+    fn serve(bodies: Vec<String>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let bodies: VecDeque<String> = bodies.into_iter().collect();
+        let bodies = Arc::new(Mutex::new(bodies));
+        let uris = Arc::new(Mutex::new(Vec::new()));
+
+        let (b, u) = (bodies.clone(), uris.clone());
+        let make_svc = make_service_fn(move |_| {
+            let (b, u) = (b.clone(), u.clone());
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    u.lock().unwrap().push(req.uri().to_string());
+                    let body = b.lock().unwrap().pop_front().unwrap();
+                    async move {
+                        Ok::<_, Infallible>(Response::new(Body::from(body)))
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let endpoint = format!("http://{}/drive/v3/", server.local_addr());
+        tokio::spawn(server);
+
+        (endpoint, uris)
+    }
+
+    fn drive(endpoint: &str) -> GoogleDrive {
+        let token = Token {
+            access_token: "token".to_string(),
+            expires_at: None,
+        };
+        GoogleDrive::new(TokenProvider::new(token, &[])).with_endpoint(endpoint)
+    }
+
+    #[test]
+    fn test_file_query() {
+        let query = FileQuery::new()
+            .in_drive("drive")
+            .folders()
+            .name_contains("Bob's \\ Recording")
+            .parent("folder")
+            .modified_after("2020-09-01T12:30:00Z".parse().unwrap())
+            .trashed(false)
+            .fields(&["id", "name"])
+            .page_size(100);
+
+        assert_eq!(
+            query.params(),
+            vec![
+                ("supportsAllDrives", "true".to_string()),
+                (
+                    "q",
+                    "mimeType = 'application/vnd.google-apps.folder' and \
+                     name contains 'Bob\\'s \\\\ Recording' and \
+                     'folder' in parents and \
+                     modifiedTime > '2020-09-01T12:30:00Z' and \
+                     trashed = false"
+                        .to_string()
+                ),
+                ("corpora", "drive".to_string()),
+                ("includeItemsFromAllDrives", "true".to_string()),
+                ("driveId", "drive".to_string()),
+                (
+                    "fields",
+                    "kind,nextPageToken,incompleteSearch,files(id,name)"
+                        .to_string()
+                ),
+                ("pageSize", "100".to_string()),
+            ]
+        );
+
+        // A query with no terms matches everything.
+        assert_eq!(
+            FileQuery::new().params(),
+            vec![("supportsAllDrives", "true".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_share_request() {
+        let options = ShareOptions {
+            email_message: Some("Welcome!".to_string()),
+            expiration_time: Some(
+                "2021-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap(),
+            ),
+            ..Default::default()
+        };
+        let (body, params) = share_request(
+            &Grantee::User("jess@oxide.computer".to_string()),
+            Role::Writer,
+            &options,
+        );
+        assert_eq!(
+            serde_json::to_value(&body).unwrap(),
+            serde_json::json!({
+                "role": "writer",
+                "type": "user",
+                "emailAddress": "jess@oxide.computer",
+                "expirationTime": "2021-01-01T00:00:00Z"
+            })
+        );
+        assert_eq!(
+            params,
+            vec![
+                ("supportsAllDrives", "true".to_string()),
+                ("sendNotificationEmail", "true".to_string()),
+                ("emailMessage", "Welcome!".to_string()),
+            ]
+        );
+
+        // Domains are never emailed.
+        let (body, params) = share_request(
+            &Grantee::Domain("oxide.computer".to_string()),
+            Role::Reader,
+            &ShareOptions {
+                allow_file_discovery: Some(true),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            serde_json::to_value(&body).unwrap(),
+            serde_json::json!({
+                "role": "reader",
+                "type": "domain",
+                "domain": "oxide.computer",
+                "allowFileDiscovery": true
+            })
+        );
+        assert_eq!(params, vec![("supportsAllDrives", "true".to_string())]);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_list_files_pages() {
+        let (endpoint, uris) = serve(vec![
+            json!({"nextPageToken": "page2", "files": [{"id": "file1"}]})
+                .to_string(),
+            json!({"files": [{"id": "file2"}]}).to_string(),
+        ]);
+
+        let files = drive(&endpoint)
+            .list_files(&FileQuery::new().name("notes"))
+            .await
+            .unwrap();
+        let ids: Vec<String> =
+            files.into_iter().map(|f| f.id.unwrap()).collect();
+        assert_eq!(ids, vec!["file1", "file2"]);
+
+        // The second request asks for the page after the first.
+        let uris = uris.lock().unwrap();
+        assert_eq!(uris.len(), 2);
+        assert!(uris[0].starts_with("/drive/v3/files?"));
+        assert!(!uris[0].contains("pageToken"));
+        assert!(uris[1].contains("pageToken=page2"));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_list_permissions_pages() {
+        let (endpoint, uris) = serve(vec![
+            json!({"nextPageToken": "page2", "permissions": [{"id": "perm1"}]})
+                .to_string(),
+            json!({"permissions": [{"id": "perm2"}]}).to_string(),
+        ]);
+
+        let permissions =
+            drive(&endpoint).list_permissions("file1").await.unwrap();
+        let ids: Vec<String> =
+            permissions.into_iter().map(|p| p.id.unwrap()).collect();
+        assert_eq!(ids, vec!["perm1", "perm2"]);
+
+        // The second request asks for the page after the first.
+        let uris = uris.lock().unwrap();
+        assert_eq!(uris.len(), 2);
+        assert!(uris[0].starts_with("/drive/v3/files/file1/permissions?"));
+        assert!(!uris[0].contains("pageToken"));
+        assert!(uris[1].contains("pageToken=page2"));
+    }
+}
@@ -0,0 +1,309 @@
+/*!
+ * Access tokens for the Google API clients that refresh themselves.
+ *
+ * A `TokenProvider` holds a source of tokens, usually a `yup_oauth2`
+ * authenticator, and the scopes to ask for. It keeps the last token and gets
+ * a new one from the source when it is about to expire, so a client can be
+ * used for longer than the hour a token lasts.
+ *
+ * Example:
+ *
+ * ```
+ * use std::env;
+ *
+ * use gauth::TokenProvider;
+ * use yup_oauth2::{read_service_account_key, ServiceAccountAuthenticator};
+ *
+ * async fn get_token_provider() -> TokenProvider {
+ *     // Get the GSuite credentials file.
+ *     let gsuite_credential_file =
+ *         env::var("GADMIN_CREDENTIAL_FILE").unwrap();
+ *     let gsuite_subject = env::var("GADMIN_SUBJECT").unwrap();
+ *     let gsuite_secret = read_service_account_key(gsuite_credential_file)
+ *         .await
+ *         .expect("failed to read gsuite credential file");
+ *     let auth = ServiceAccountAuthenticator::builder(gsuite_secret)
+ *         .subject(gsuite_subject.to_string())
+ *         .build()
+ *         .await
+ *         .expect("failed to create authenticator");
+ *
+ *     // The provider asks the authenticator for a token with these scopes
+ *     // whenever the one it has is about to expire.
+ *     let provider = TokenProvider::new(
+ *         auth,
+ *         &["https://www.googleapis.com/auth/spreadsheets"],
+ *     );
+ *
+ *     // Get a token now to check the credentials work.
+ *     provider.token().await.expect("failed to get token");
+ *
+ *     provider
+ * }
+ * ```
+ */
+use std::error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use futures_util::lock::Mutex;
+use hyper::client::connect::Connect;
+use yup_oauth2::authenticator::Authenticator;
+use yup_oauth2::AccessToken;
+
+/// How long before a token expires that we get a new one.
+const REFRESH_BEFORE_EXPIRY: i64 = 60;
+
+/// An access token and when it expires.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    /// The value to send as the bearer token.
+    pub access_token: String,
+    /// When the token expires, `None` if it does not.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Token {
+    /// Return if the token expires within the duration.
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at - duration <= Utc::now(),
+            None => false,
+        }
+    }
+
+    /// Return if the token has expired.
+    pub fn is_expired(&self) -> bool {
+        self.expires_within(Duration::zero())
+    }
+}
+
+impl From<AccessToken> for Token {
+    fn from(token: AccessToken) -> Self {
+        Token {
+            access_token: token.as_str().to_string(),
+            expires_at: token.expiration_time(),
+        }
+    }
+}
+
+/// The future returned by `TokenSource::token`.
+pub type TokenFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Token, TokenError>> + Send + 'a>>;
+
+/// Something that can get a new access token for some scopes.
+pub trait TokenSource: Send + Sync {
+    /// Get a token for the scopes.
+    fn token<'a>(&'a self, scopes: &'a [String]) -> TokenFuture<'a>;
+}
+
+impl<C> TokenSource for Authenticator<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    fn token<'a>(&'a self, scopes: &'a [String]) -> TokenFuture<'a> {
+        Box::pin(async move {
+            let token = Authenticator::token(self, scopes).await?;
+            Ok(token.into())
+        })
+    }
+}
+
+/// A fixed token is its own source. Once it expires, getting it returns an
+/// error.
+impl TokenSource for Token {
+    fn token<'a>(&'a self, _scopes: &'a [String]) -> TokenFuture<'a> {
+        Box::pin(async move {
+            if self.is_expired() {
+                return Err(TokenError::Expired);
+            }
+
+            Ok(self.clone())
+        })
+    }
+}
+
+/// Hands out access tokens for some scopes, getting a new one from its
+/// source before the current one expires. Clones share the same token.
+#[derive(Clone)]
+pub struct TokenProvider {
+    source: Arc<dyn TokenSource>,
+    scopes: Arc<Vec<String>>,
+    token: Arc<Mutex<Option<Token>>>,
+}
+
+impl TokenProvider {
+    /// Create a new token provider that gets tokens for the scopes from the
+    /// source.
+    pub fn new<S>(source: S, scopes: &[&str]) -> Self
+    where
+        S: TokenSource + 'static,
+    {
+        TokenProvider {
+            source: Arc::new(source),
+            scopes: Arc::new(scopes.iter().map(|s| s.to_string()).collect()),
+            token: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Get the scopes tokens are requested for.
+    pub fn scopes(&self) -> &[String] {
+        &self.scopes
+    }
+
+    /// Get a token that is valid for at least another minute, getting a new
+    /// one from the source if needed.
+    pub async fn token(&self) -> Result<Token, TokenError> {
+        // Hold the lock while refreshing so concurrent requests wait for
+        // the one new token instead of each getting their own.
+        let mut current = self.token.lock().await;
+
+        let refresh = Duration::seconds(REFRESH_BEFORE_EXPIRY);
+        if let Some(token) = current.as_ref() {
+            if !token.expires_within(refresh) {
+                return Ok(token.clone());
+            }
+        }
+
+        let token = self.source.token(&self.scopes).await?;
+        if token.access_token.is_empty() {
+            return Err(TokenError::Empty);
+        }
+
+        *current = Some(token.clone());
+        Ok(token)
+    }
+}
+
+impl fmt::Debug for TokenProvider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TokenProvider")
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+/// Error type returned when getting a token.
+pub enum TokenError {
+    /// The authenticator failed to get a token.
+    Auth(yup_oauth2::Error),
+    /// The source returned an empty token.
+    Empty,
+    /// The token has expired and the source cannot refresh it.
+    Expired,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenError::Auth(e) => write!(f, "TokenError: auth -> {}", e),
+            TokenError::Empty => write!(f, "TokenError: empty token"),
+            TokenError::Expired => write!(f, "TokenError: token expired"),
+        }
+    }
+}
+
+impl fmt::Debug for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// This is important for other errors to wrap this one.
+impl error::Error for TokenError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TokenError::Auth(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<yup_oauth2::Error> for TokenError {
+    fn from(e: yup_oauth2::Error) -> Self {
+        TokenError::Auth(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use crate::{Token, TokenError, TokenFuture, TokenProvider, TokenSource};
+
+    /// Hands out numbered tokens that expire after a fixed time and counts
+    /// how many it has given out.
+    struct FakeTokenSource {
+        lifetime: Duration,
+        issued: Arc<AtomicUsize>,
+    }
+
+    impl TokenSource for FakeTokenSource {
+        fn token<'a>(&'a self, scopes: &'a [String]) -> TokenFuture<'a> {
+            Box::pin(async move {
+                assert_eq!(scopes, ["scope".to_string()]);
+                let n = self.issued.fetch_add(1, Ordering::SeqCst);
+                Ok(Token {
+                    access_token: format!("token-{}", n),
+                    expires_at: Some(Utc::now() + self.lifetime),
+                })
+            })
+        }
+    }
+
+    fn provider(lifetime: Duration) -> (TokenProvider, Arc<AtomicUsize>) {
+        let issued = Arc::new(AtomicUsize::new(0));
+        let source = FakeTokenSource {
+            lifetime,
+            issued: issued.clone(),
+        };
+        (TokenProvider::new(source, &["scope"]), issued)
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_token_provider_reuses_token() {
+        let (provider, issued) = provider(Duration::hours(1));
+
+        let first = provider.token().await.unwrap();
+        let second = provider.clone().token().await.unwrap();
+        assert_eq!(first.access_token, "token-0");
+        assert_eq!(first, second);
+        assert_eq!(issued.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_token_provider_refreshes_before_expiry() {
+        // Every token is within the refresh window as soon as it is issued.
+        let (provider, issued) = provider(Duration::seconds(30));
+
+        assert_eq!(provider.token().await.unwrap().access_token, "token-0");
+        assert_eq!(provider.token().await.unwrap().access_token, "token-1");
+        assert_eq!(issued.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn test_fixed_token() {
+        let token = Token {
+            access_token: "fixed".to_string(),
+            expires_at: None,
+        };
+        let provider = TokenProvider::new(token.clone(), &["scope"]);
+        assert_eq!(provider.token().await.unwrap(), token);
+
+        let expired = Token {
+            access_token: "fixed".to_string(),
+            expires_at: Some(Utc::now() - Duration::minutes(1)),
+        };
+        let provider = TokenProvider::new(expired, &["scope"]);
+        match provider.token().await {
+            Err(TokenError::Expired) => (),
+            r => panic!("expected an expired token, got {:?}", r),
+        }
+    }
+}
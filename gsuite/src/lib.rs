@@ -11,6 +11,7 @@
  * ```
  * use std::env;
  *
+ * use gauth::TokenProvider;
  * use gsuite_api::GSuite;
  * use yup_oauth2::{read_service_account_key, ServiceAccountAuthenticator};
  *
@@ -28,23 +29,23 @@
  *         .await
  *         .expect("failed to create authenticator");
  *
- *     // Add the scopes to the secret and get the token.
- *     let token = auth.token(&[
- *      "https://www.googleapis.com/auth/admin.directory.group",
- *      "https://www.googleapis.com/auth/admin.directory.resource.calendar",
- *      "https://www.googleapis.com/auth/admin.directory.user",
- *      "https://www.googleapis.com/auth/apps.groups.settings",
- *  ]).await.expect("failed to get token");
- *
- *     if token.as_str().is_empty() {
- *         panic!("empty token is not valid");
- *     }
+ *     // Get tokens for the scopes, a new one is fetched before the current
+ *     // one expires.
+ *     let token = TokenProvider::new(
+ *         auth,
+ *         &[
+ *             "https://www.googleapis.com/auth/admin.directory.group",
+ *             "https://www.googleapis.com/auth/admin.directory.resource.calendar",
+ *             "https://www.googleapis.com/auth/admin.directory.user",
+ *             "https://www.googleapis.com/auth/apps.groups.settings",
+ *         ],
+ *     );
  *
  *     // Initialize the GSuite client.
  *     let gsuite_client = GSuite::new("customer_id", "domain", token);
  *
  *     // List users.
- *     let users = gsuite_client.list_users().await;
+ *     let users = gsuite_client.list_users().await.unwrap();
  *
  *     // Iterate over the users.
  *     for user in users {
@@ -54,13 +55,15 @@
  * ```
  */
 use std::collections::HashMap;
+use std::error;
+use std::fmt;
 use std::sync::Arc;
 
+use gauth::{TokenError, TokenProvider};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use reqwest::{header, Client, Method, Request, StatusCode, Url};
 use serde::{Deserialize, Serialize};
-use yup_oauth2::AccessToken;
 
 use cio_api::configs::{BuildingConfig, ResourceConfig, UserConfig};
 use cio_api::utils::get_github_user_public_ssh_keys;
@@ -78,16 +81,15 @@ pub struct GSuite {
     customer: String,
     domain: String,
 
-    token: AccessToken,
+    token: TokenProvider,
 
     client: Arc<Client>,
 }
 
 impl GSuite {
-    /// Create a new GSuite client struct. It takes a type that can convert into
-    /// an &str (`String` or `Vec<u8>` for example). As long as the function is
-    /// given a valid API Key and Secret your requests will work.
-    pub fn new(customer: &str, domain: &str, token: AccessToken) -> Self {
+    /// Create a new GSuite client struct. It takes a token provider, which gets
+    /// a new token before the current one expires so the client keeps working.
+    pub fn new(customer: &str, domain: &str, token: TokenProvider) -> Self {
         let client = Client::builder().build().expect("creating client failed");
         Self {
             customer: customer.to_string(),
@@ -97,31 +99,29 @@ impl GSuite {
         }
     }
 
-    /// Get the currently set authorization token.
-    pub fn get_token(&self) -> &AccessToken {
+    /// Get the provider of the authorization token.
+    pub fn get_token(&self) -> &TokenProvider {
         &self.token
     }
 
-    fn request<B>(
+    async fn request<B>(
         &self,
         endpoint: &str,
         method: Method,
         path: &str,
         body: B,
         query: Option<&[(&str, &str)]>,
-    ) -> Request
+    ) -> Result<Request, APIError>
     where
         B: Serialize,
     {
         let base = Url::parse(endpoint).unwrap();
         let url = base.join(path).unwrap();
 
-        // Check if the token is expired and panic.
-        if self.token.is_expired() {
-            panic!("token is expired");
-        }
+        // Get the token, this refreshes it if it is about to expire.
+        let token = self.token.token().await?;
 
-        let bt = format!("Bearer {}", self.token.as_str());
+        let bt = format!("Bearer {}", token.access_token);
         let bearer = header::HeaderValue::from_str(&bt).unwrap();
 
         // Set the default headers.
@@ -148,153 +148,184 @@ impl GSuite {
         }
 
         // Build the request.
-        rb.build().unwrap()
+        Ok(rb.build().unwrap())
     }
 
     /// List Google groups.
-    pub async fn list_groups(&self) -> Vec<Group> {
+    pub async fn list_groups(&self) -> Result<Vec<Group>, APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::GET,
-            "groups",
-            (),
-            Some(&[("customer", &self.customer), ("domain", &self.domain)]),
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::GET,
+                "groups",
+                (),
+                Some(&[("customer", &self.customer), ("domain", &self.domain)]),
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
 
         // Try to deserialize the response.
         let value: Groups = resp.json().await.unwrap();
 
-        value.groups.unwrap()
+        Ok(value.groups.unwrap())
     }
 
     /// Get the settings for a Google group.
-    pub async fn get_group_settings(&self, group_email: &str) -> GroupSettings {
+    pub async fn get_group_settings(
+        &self,
+        group_email: &str,
+    ) -> Result<GroupSettings, APIError> {
         // Build the request.
-        let request = self.request(
-            GROUPS_SETTINGS_ENDPOINT,
-            Method::GET,
-            group_email,
-            (),
-            Some(&[("alt", "json")]),
-        );
+        let request = self
+            .request(
+                GROUPS_SETTINGS_ENDPOINT,
+                Method::GET,
+                group_email,
+                (),
+                Some(&[("alt", "json")]),
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
 
         // Try to deserialize the response.
-        resp.json().await.unwrap()
+        Ok(resp.json().await.unwrap())
     }
 
     /// Update a Google group.
-    pub async fn update_group(&self, group: &Group) {
+    pub async fn update_group(&self, group: &Group) -> Result<(), APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::PUT,
-            &format!("groups/{}", group.id.as_ref().unwrap()),
-            group,
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::PUT,
+                &format!("groups/{}", group.id.as_ref().unwrap()),
+                group,
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
+
+        Ok(())
     }
 
     /// Update a Google group's settings.
-    pub async fn update_group_settings(&self, settings: &GroupSettings) {
+    pub async fn update_group_settings(
+        &self,
+        settings: &GroupSettings,
+    ) -> Result<(), APIError> {
         // Build the request.
-        let request = self.request(
-            GROUPS_SETTINGS_ENDPOINT,
-            Method::PUT,
-            settings.email.as_ref().unwrap(),
-            settings,
-            Some(&[("alt", "json")]),
-        );
+        let request = self
+            .request(
+                GROUPS_SETTINGS_ENDPOINT,
+                Method::PUT,
+                settings.email.as_ref().unwrap(),
+                settings,
+                Some(&[("alt", "json")]),
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
+
+        Ok(())
     }
 
     /// Create a google group.
-    pub async fn create_group(&self, group: &Group) -> Group {
+    pub async fn create_group(&self, group: &Group) -> Result<Group, APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::POST,
-            "groups",
-            group,
-            None,
-        );
+        let request = self
+            .request(DIRECTORY_ENDPOINT, Method::POST, "groups", group, None)
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
 
         // Try to deserialize the response.
-        resp.json().await.unwrap()
+        Ok(resp.json().await.unwrap())
     }
 
     /// Update a Google group's aliases.
-    pub async fn update_group_aliases<A>(&self, group_key: &str, aliases: A)
+    pub async fn update_group_aliases<A>(
+        &self,
+        group_key: &str,
+        aliases: A,
+    ) -> Result<(), APIError>
     where
         A: IntoIterator,
         A::Item: AsRef<str>,
     {
         for alias in aliases {
-            self.update_group_alias(group_key, alias.as_ref()).await;
+            self.update_group_alias(group_key, alias.as_ref()).await?;
         }
+
+        Ok(())
     }
 
     /// Update an alias for a Google group.
-    pub async fn update_group_alias(&self, group_key: &str, alias: &str) {
+    pub async fn update_group_alias(
+        &self,
+        group_key: &str,
+        alias: &str,
+    ) -> Result<(), APIError> {
         let mut a: HashMap<&str, &str> = HashMap::new();
         a.insert("alias", alias);
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::POST,
-            &format!("groups/{}/aliases", group_key),
-            a,
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::POST,
+                &format!("groups/{}/aliases", group_key),
+                a,
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -304,42 +335,51 @@ impl GSuite {
 
                 if body.contains("duplicate") {
                     // Ignore the error because we don't care about if it is a duplicate.
-                    return;
+                    return Ok(());
                 }
 
-                panic!(
-                    "received response status: {:?}\nresponse body: {:?}",
-                    s, body,
-                );
+                return Err(APIError {
+                    status_code: s,
+                    body,
+                });
             }
         };
+
+        Ok(())
     }
 
     /// Check if a user is a member of a Google group.
-    pub async fn group_has_member(&self, group_id: &str, email: &str) -> bool {
+    pub async fn group_has_member(
+        &self,
+        group_id: &str,
+        email: &str,
+    ) -> Result<bool, APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::GET,
-            &format!("groups/{}/hasMember/{}", group_id, email),
-            (),
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::GET,
+                &format!("groups/{}/hasMember/{}", group_id, email),
+                (),
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
 
         // Try to deserialize the response.
         let value: MembersHasMember = resp.json().await.unwrap();
 
-        value.is_member.unwrap()
+        Ok(value.is_member.unwrap())
     }
 
     /// Update a member of a Google group.
@@ -348,30 +388,35 @@ impl GSuite {
         group_id: &str,
         email: &str,
         role: &str,
-    ) {
+    ) -> Result<(), APIError> {
         let mut member: Member = Default::default();
         member.role = Some(role.to_string());
         member.email = Some(email.to_string());
         member.delivery_settings = Some("ALL_MAIL".to_string());
 
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::PUT,
-            &format!("groups/{}/members/{}", group_id, email),
-            member,
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::PUT,
+                &format!("groups/{}/members/{}", group_id, email),
+                member,
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
+
+        Ok(())
     }
 
     /// Add a user as a member of a Google group.
@@ -380,150 +425,186 @@ impl GSuite {
         group_id: &str,
         email: &str,
         role: &str,
-    ) {
+    ) -> Result<(), APIError> {
         let mut member: Member = Default::default();
         member.role = Some(role.to_string());
         member.email = Some(email.to_string());
         member.delivery_settings = Some("ALL_MAIL".to_string());
 
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::POST,
-            &format!("groups/{}/members", group_id),
-            member,
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::POST,
+                &format!("groups/{}/members", group_id),
+                member,
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
+
+        Ok(())
     }
 
     /// Remove a user as a member of a Google group.
-    pub async fn group_remove_member(&self, group_id: &str, email: &str) {
+    pub async fn group_remove_member(
+        &self,
+        group_id: &str,
+        email: &str,
+    ) -> Result<(), APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::DELETE,
-            &format!("groups/{}/members/{}", group_id, email),
-            (),
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::DELETE,
+                &format!("groups/{}/members/{}", group_id, email),
+                (),
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
+
+        Ok(())
     }
 
     /// List users.
-    pub async fn list_users(&self) -> Vec<User> {
+    pub async fn list_users(&self) -> Result<Vec<User>, APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::GET,
-            "users",
-            (),
-            Some(&[
-                ("customer", &self.customer),
-                ("domain", &self.domain),
-                ("projection", "full"),
-            ]),
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::GET,
+                "users",
+                (),
+                Some(&[
+                    ("customer", &self.customer),
+                    ("domain", &self.domain),
+                    ("projection", "full"),
+                ]),
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
 
         // Try to deserialize the response.
         let value: Users = resp.json().await.unwrap();
 
-        value.users.unwrap()
+        Ok(value.users.unwrap())
     }
 
     /// Update a user.
-    pub async fn update_user(&self, user: &User) {
+    pub async fn update_user(&self, user: &User) -> Result<(), APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::PUT,
-            &format!("users/{}", user.id.as_ref().unwrap()),
-            user,
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::PUT,
+                &format!("users/{}", user.id.as_ref().unwrap()),
+                user,
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
+
+        Ok(())
     }
 
     /// Create a user.
-    pub async fn create_user(&self, user: &User) -> User {
+    pub async fn create_user(&self, user: &User) -> Result<User, APIError> {
         // Build the request.
-        let request =
-            self.request(DIRECTORY_ENDPOINT, Method::POST, "users", user, None);
+        let request = self
+            .request(DIRECTORY_ENDPOINT, Method::POST, "users", user, None)
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
 
         // Try to deserialize the response.
-        resp.json().await.unwrap()
+        Ok(resp.json().await.unwrap())
     }
 
     /// Update a user's aliases.
-    pub async fn update_user_aliases<A>(&self, user_id: &str, aliases: A)
+    pub async fn update_user_aliases<A>(
+        &self,
+        user_id: &str,
+        aliases: A,
+    ) -> Result<(), APIError>
     where
         A: IntoIterator,
         A::Item: AsRef<str>,
     {
         for alias in aliases {
-            self.update_user_alias(user_id, alias.as_ref()).await;
+            self.update_user_alias(user_id, alias.as_ref()).await?;
         }
+
+        Ok(())
     }
 
     /// Update an alias for a user.
-    pub async fn update_user_alias(&self, user_id: &str, alias: &str) {
+    pub async fn update_user_alias(
+        &self,
+        user_id: &str,
+        alias: &str,
+    ) -> Result<(), APIError> {
         let mut a: HashMap<&str, &str> = HashMap::new();
         a.insert("alias", alias);
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::POST,
-            &format!("users/{}/aliases", user_id),
-            a,
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::POST,
+                &format!("users/{}/aliases", user_id),
+                a,
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -533,163 +614,205 @@ impl GSuite {
 
                 if body.contains("duplicate") {
                     // Ignore the error because we don't care about if it is a duplicate.
-                    return;
+                    return Ok(());
                 }
 
-                panic!(
-                    "received response status: {:?}\nresponse body: {:?}",
-                    s, body,
-                );
+                return Err(APIError {
+                    status_code: s,
+                    body,
+                });
             }
         };
+
+        Ok(())
     }
 
     /// List calendar resources.
-    pub async fn list_calendar_resources(&self) -> Vec<CalendarResource> {
+    pub async fn list_calendar_resources(
+        &self,
+    ) -> Result<Vec<CalendarResource>, APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::GET,
-            &format!("customer/{}/resources/calendars", self.customer),
-            (),
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::GET,
+                &format!("customer/{}/resources/calendars", self.customer),
+                (),
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
 
         // Try to deserialize the response.
         let value: CalendarResources = resp.json().await.unwrap();
 
-        value.items.unwrap()
+        Ok(value.items.unwrap())
     }
 
     /// Update a calendar resource.
-    pub async fn update_calendar_resource(&self, resource: &CalendarResource) {
+    pub async fn update_calendar_resource(
+        &self,
+        resource: &CalendarResource,
+    ) -> Result<(), APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::PUT,
-            &format!(
-                "customer/{}/resources/calendars/{}",
-                self.customer, resource.id
-            ),
-            resource,
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::PUT,
+                &format!(
+                    "customer/{}/resources/calendars/{}",
+                    self.customer, resource.id
+                ),
+                resource,
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
+
+        Ok(())
     }
 
     /// Create a calendar resource.
-    pub async fn create_calendar_resource(&self, resource: &CalendarResource) {
+    pub async fn create_calendar_resource(
+        &self,
+        resource: &CalendarResource,
+    ) -> Result<(), APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::POST,
-            &format!("customer/{}/resources/calendars", self.customer),
-            resource,
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::POST,
+                &format!("customer/{}/resources/calendars", self.customer),
+                resource,
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
+
+        Ok(())
     }
 
     /// List buildings.
-    pub async fn list_buildings(&self) -> Vec<Building> {
+    pub async fn list_buildings(&self) -> Result<Vec<Building>, APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::GET,
-            &format!("customer/{}/resources/buildings", self.customer),
-            (),
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::GET,
+                &format!("customer/{}/resources/buildings", self.customer),
+                (),
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
 
         // Try to deserialize the response.
         let value: Buildings = resp.json().await.unwrap();
 
-        value.buildings.unwrap()
+        Ok(value.buildings.unwrap())
     }
 
     /// Update a building.
-    pub async fn update_building(&self, building: &Building) {
+    pub async fn update_building(
+        &self,
+        building: &Building,
+    ) -> Result<(), APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::PUT,
-            &format!(
-                "customer/{}/resources/buildings/{}",
-                self.customer, building.id
-            ),
-            building,
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::PUT,
+                &format!(
+                    "customer/{}/resources/buildings/{}",
+                    self.customer, building.id
+                ),
+                building,
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
+
+        Ok(())
     }
 
     /// Create a building.
-    pub async fn create_building(&self, building: &Building) {
+    pub async fn create_building(
+        &self,
+        building: &Building,
+    ) -> Result<(), APIError> {
         // Build the request.
-        let request = self.request(
-            DIRECTORY_ENDPOINT,
-            Method::POST,
-            &format!("customer/{}/resources/buildings", self.customer),
-            building,
-            None,
-        );
+        let request = self
+            .request(
+                DIRECTORY_ENDPOINT,
+                Method::POST,
+                &format!("customer/{}/resources/buildings", self.customer),
+                building,
+                None,
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
             StatusCode::OK => (),
-            s => panic!(
-                "received response status: {:?}\nbody: {}",
-                s,
-                resp.text().await.unwrap()
-            ),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
         };
+
+        Ok(())
     }
 }
 
@@ -699,6 +822,52 @@ pub fn generate_password() -> String {
     thread_rng().sample_iter(&Alphanumeric).take(30).collect()
 }
 
+/// Error type returned by our library.
+pub struct APIError {
+    pub status_code: StatusCode,
+    pub body: String,
+}
+
+impl fmt::Display for APIError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "APIError: status code -> {}, body -> {}",
+            self.status_code.to_string(),
+            self.body
+        )
+    }
+}
+
+impl fmt::Debug for APIError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "APIError: status code -> {}, body -> {}",
+            self.status_code.to_string(),
+            self.body
+        )
+    }
+}
+
+// This is important for other errors to wrap this one.
+impl error::Error for APIError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        // Generic error, underlying cause isn't tracked.
+        None
+    }
+}
+
+impl From<TokenError> for APIError {
+    fn from(e: TokenError) -> Self {
+        // We could not get a token so the request is not authorized.
+        APIError {
+            status_code: StatusCode::UNAUTHORIZED,
+            body: e.to_string(),
+        }
+    }
+}
+
 /// A Google group.
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct Group {
@@ -5,13 +5,27 @@ use quote::{format_ident, quote};
 use serde::Deserialize;
 use serde_tokenstream::from_tokenstream;
 use serde_tokenstream::Error;
-use syn::{Field, ItemStruct};
+use syn::{Attribute, Field, ItemStruct, Lit, Meta, NestedMeta, Type};
 
 #[derive(Deserialize, Debug)]
 struct Metadata {
     new_name: String,
-    table: Option<String>,
-    base_id: Option<String>,
+    /// The logical name of the table in Airtable, looked up in our Airtable
+    /// config at runtime.
+    airtable_table: Option<String>,
+    /// The fields that uniquely identify a row, these are used to find an
+    /// existing row when upserting and to match rows to records in Airtable.
+    match_on: Vec<String>,
+    /// The fields owned by Airtable, like links to other tables or fields
+    /// people edit there. These are sent when a record is created and kept
+    /// as they are in Airtable after that.
+    #[serde(default)]
+    airtable_fields: Vec<String>,
+    /// The fields our database owns and pushes to Airtable, besides `id`.
+    /// If this is set, every other field is owned by Airtable. Defaults to
+    /// every field that is not in `airtable_fields`.
+    #[serde(default)]
+    db_fields: Vec<String>,
 }
 
 #[proc_macro_attribute]
@@ -33,39 +47,130 @@ fn do_db_struct(
     let new_name = format_ident!("{}", metadata.new_name);
 
     let old_struct: ItemStruct = syn::parse2(item.clone()).unwrap();
+    let old_name = &old_struct.ident;
     let mut fields: Vec<&Field> = Default::default();
+    let mut idents: Vec<&syn::Ident> = Default::default();
     for field in old_struct.fields.iter() {
         fields.push(field);
+        idents.push(field.ident.as_ref().unwrap());
     }
 
+    let db = do_db_functions(&metadata, &old_struct, &new_name)?;
+
     let mut airtable = Default::default();
-    if metadata.base_id.is_some() && metadata.table.is_some() {
-        let base_id = format_ident!("{}", metadata.base_id.unwrap());
-        let table = format_ident!("{}", metadata.table.unwrap());
+    if let Some(airtable_table) = &metadata.airtable_table {
+        for (list, names) in &[
+            ("airtable_fields", &metadata.airtable_fields),
+            ("db_fields", &metadata.db_fields),
+        ] {
+            for name in names.iter() {
+                if !idents.iter().any(|i| *i == name) {
+                    return Err(Error::new(
+                        old_name.span(),
+                        format!("`{}` field `{}` does not exist", list, name),
+                    ));
+                }
+            }
+        }
+        if let Some(name) = metadata
+            .db_fields
+            .iter()
+            .find(|name| metadata.airtable_fields.contains(name))
+        {
+            return Err(Error::new(
+                old_name.span(),
+                format!(
+                    "`{}` is in both `airtable_fields` and `db_fields`",
+                    name
+                ),
+            ));
+        }
+
+        // Split the fields by who owns them, with the names they have in
+        // Airtable.
+        let mut airtable_fields: Vec<&syn::Ident> = Default::default();
+        let mut airtable_names: Vec<String> = Default::default();
+        let mut db_names: Vec<String> = Default::default();
+        for field in old_struct.fields.iter() {
+            let ident = field.ident.as_ref().unwrap();
+            let owned_by_airtable = if metadata.db_fields.is_empty() {
+                metadata.airtable_fields.iter().any(|n| ident == n)
+            } else {
+                !metadata.db_fields.iter().any(|n| ident == n)
+            };
+
+            let name = serde_name(&old_struct, field)?;
+            if owned_by_airtable {
+                airtable_fields.push(ident);
+                airtable_names.push(name);
+            } else {
+                db_names.push(name);
+            }
+        }
+
+        let key_fields: Vec<syn::Ident> = metadata
+            .match_on
+            .iter()
+            .map(|name| format_ident!("{}", name))
+            .collect();
 
         airtable = quote!(
         impl #new_name {
             /// Push the row to our Airtable workspace.
-            pub async fn push_to_airtable(&self) {
+            pub async fn push_to_airtable(
+                &self,
+            ) -> Result<(), crate::airtable::SyncError> {
+                let table = crate::airtable::airtable_table(#airtable_table)?;
+
                 // Initialize the Airtable client.
-                let airtable =
-                    Airtable::new(airtable_api_key(), #base_id);
+                let airtable = table.client();
 
                 // Create the record.
                 let record = Record {
                     id: None,
                     created_time: None,
-                    fields: serde_json::to_value(self).unwrap(),
+                    fields: self.clone(),
                 };
 
                 // Send the new record to the Airtable client.
-                // Batch can only handle 10 at a time.
                 airtable
-                    .create_records(#table, vec![record])
+                    .create_records(&table.table, vec![record])
                     .await
-                    .unwrap();
+                    .map_err(|e| e.error)?;
 
                 println!("created new row in airtable: {:?}", self);
+                Ok(())
+            }
+        }
+
+        impl crate::airtable::AirtableSync for #new_name {
+            fn airtable_table() -> &'static str {
+                #airtable_table
+            }
+
+            fn key(&self) -> Vec<String> {
+                vec![#(self.#key_fields.to_string()),*]
+            }
+
+            fn airtable_fields() -> &'static [&'static str] {
+                &[#(#airtable_names),*]
+            }
+
+            fn db_fields() -> &'static [&'static str] {
+                &["id", #(#db_names),*]
+            }
+
+            fn keep_airtable_fields(&mut self, record: &Self) {
+                #(self.#airtable_fields = record.#airtable_fields.clone();)*
+            }
+        }
+
+        impl #old_name {
+            /// Copy the fields Airtable owns from the row we already have, so
+            /// writing this to the database does not undo edits made in
+            /// Airtable.
+            pub fn keep_airtable_fields(&mut self, row: &#new_name) {
+                #(self.#airtable_fields = row.#airtable_fields.clone();)*
             }
         }
             );
@@ -90,11 +195,278 @@ fn do_db_struct(
             #(#fields),*
         }
 
+        impl From<#new_name> for #old_name {
+            fn from(item: #new_name) -> Self {
+                #old_name {
+                    #(#idents: item.#idents),*
+                }
+            }
+        }
+
+        #db
+
         #airtable
     );
     Ok(new_struct)
 }
 
+/// Generate the functions on our `Database` to get, upsert and delete rows
+/// for the new struct.
+fn do_db_functions(
+    metadata: &Metadata,
+    old_struct: &ItemStruct,
+    new_name: &syn::Ident,
+) -> Result<TokenStream, Error> {
+    let old_name = &old_struct.ident;
+
+    // Get the table from the `table_name` attribute, diesel needs this anyways.
+    let mut table_name = String::new();
+    for attr in old_struct.attrs.iter() {
+        if let Ok(Meta::NameValue(nv)) = attr.parse_meta() {
+            if nv.path.is_ident("table_name") {
+                if let Lit::Str(s) = nv.lit {
+                    table_name = s.value();
+                }
+            }
+        }
+    }
+    if table_name.is_empty() {
+        return Err(Error::new(
+            old_name.span(),
+            "db_struct requires a `table_name` attribute".to_string(),
+        ));
+    }
+    if metadata.match_on.is_empty() {
+        return Err(Error::new(
+            old_name.span(),
+            "db_struct requires at least one field in `match_on`".to_string(),
+        ));
+    }
+
+    // The table is the plural, strip the trailing "s" for the singular.
+    let plural = table_name.to_string();
+    let singular = table_name
+        .strip_suffix('s')
+        .unwrap_or(&table_name)
+        .to_string();
+
+    let table = format_ident!("{}", plural);
+    let record = format_ident!("{}", singular);
+    let get_all = format_ident!("get_{}", plural);
+    let get_page = format_ident!("get_{}_page", plural);
+    let page_query = format_ident!("{}_page_query", plural);
+    let get_by_id = format_ident!("get_{}_by_id", singular);
+    let get_by_key = format_ident!(
+        "get_{}_by_{}",
+        singular,
+        metadata.match_on.join("_and_")
+    );
+    let upsert = format_ident!("upsert_{}", singular);
+    let delete = format_ident!("delete_{}", singular);
+
+    // Build the arguments and filters for the unique key.
+    let mut key_args: Vec<TokenStream> = Default::default();
+    let mut key_filters: Vec<TokenStream> = Default::default();
+    let mut key_values: Vec<TokenStream> = Default::default();
+    for key in metadata.match_on.iter() {
+        let field = old_struct
+            .fields
+            .iter()
+            .find(|f| matches!(&f.ident, Some(i) if i == key));
+        let ty = match field {
+            Some(f) => &f.ty,
+            None => {
+                return Err(Error::new(
+                    old_name.span(),
+                    format!("`match_on` field `{}` does not exist", key),
+                ))
+            }
+        };
+
+        let ident = format_ident!("{}", key);
+        // Take strings by reference, everything else is expected to be
+        // `Copy`.
+        if is_string(ty) {
+            key_args.push(quote!(#ident: &str));
+            key_values.push(quote!(&#record.#ident));
+        } else {
+            key_args.push(quote!(#ident: #ty));
+            key_values.push(quote!(#record.#ident));
+        }
+        key_filters.push(
+            quote!(.filter(crate::schema::#table::dsl::#ident.eq(#ident))),
+        );
+    }
+
+    Ok(quote!(
+    impl crate::db::Database {
+        /// Get all the rows from the database.
+        pub fn #get_all(&self) -> Result<Vec<#new_name>, crate::db::DbError> {
+            use diesel::prelude::*;
+
+            Ok(crate::schema::#table::dsl::#table
+                .order_by(crate::schema::#table::dsl::id.desc())
+                .load::<#new_name>(&self.conn()?)?)
+        }
+
+        /// Build the query for a page of rows, newest first. If `before` is
+        /// set, only the rows with a lower id are returned. More filters can
+        /// be added to the query before it is loaded.
+        pub(crate) fn #page_query(
+            before: Option<i32>,
+            limit: i64,
+        ) -> crate::schema::#table::BoxedQuery<'static, diesel::pg::Pg> {
+            use diesel::prelude::*;
+
+            let mut query = crate::schema::#table::dsl::#table.into_boxed();
+            if let Some(before) = before {
+                query = query.filter(crate::schema::#table::dsl::id.lt(before));
+            }
+
+            query
+                .order_by(crate::schema::#table::dsl::id.desc())
+                .limit(limit)
+        }
+
+        /// Get a page of rows from the database, newest first. If `before` is
+        /// set, only the rows with a lower id are returned.
+        pub fn #get_page(
+            &self,
+            before: Option<i32>,
+            limit: i64,
+        ) -> Result<Vec<#new_name>, crate::db::DbError> {
+            use diesel::prelude::*;
+
+            Ok(Self::#page_query(before, limit)
+                .load::<#new_name>(&self.conn()?)?)
+        }
+
+        /// Get a row from the database by its id.
+        pub fn #get_by_id(
+            &self,
+            id: i32,
+        ) -> Result<Option<#new_name>, crate::db::DbError> {
+            use diesel::prelude::*;
+
+            Ok(crate::schema::#table::dsl::#table
+                .find(id)
+                .first::<#new_name>(&self.conn()?)
+                .optional()?)
+        }
+
+        /// Get a row from the database by its unique key.
+        pub fn #get_by_key(
+            &self,
+            #(#key_args),*
+        ) -> Result<Option<#new_name>, crate::db::DbError> {
+            use diesel::prelude::*;
+
+            Ok(crate::schema::#table::dsl::#table
+                #(#key_filters)*
+                .first::<#new_name>(&self.conn()?)
+                .optional()?)
+        }
+
+        /// Create or update a row in the database, matching on its unique key.
+        pub fn #upsert(
+            &self,
+            #record: &#old_name,
+        ) -> Result<#new_name, crate::db::DbError> {
+            use diesel::prelude::*;
+
+            // See if we already have the row in the database.
+            if let Some(existing) = self.#get_by_key(#(#key_values),*)? {
+                // Update the row.
+                return Ok(diesel::update(&existing)
+                    .set(#record)
+                    .get_result::<#new_name>(&self.conn()?)?);
+            }
+
+            // We don't have the row in the database so we need to add it.
+            Ok(diesel::insert_into(crate::schema::#table::table)
+                .values(#record)
+                .get_result::<#new_name>(&self.conn()?)?)
+        }
+
+        /// Delete a row from the database by its id. Returns true if the row
+        /// existed.
+        pub fn #delete(&self, id: i32) -> Result<bool, crate::db::DbError> {
+            use diesel::prelude::*;
+
+            let deleted =
+                diesel::delete(crate::schema::#table::dsl::#table.find(id))
+                    .execute(&self.conn()?)?;
+
+            Ok(deleted > 0)
+        }
+    }
+    ))
+}
+
+/// Get the name serde gives a field, which is also the name of its column in
+/// Airtable. We support a `rename` on the field and a `camelCase`
+/// `rename_all` on the struct.
+fn serde_name(old_struct: &ItemStruct, field: &Field) -> Result<String, Error> {
+    let ident = field.ident.as_ref().unwrap();
+    if let Some(name) = serde_attr(&field.attrs, "rename") {
+        return Ok(name);
+    }
+
+    match serde_attr(&old_struct.attrs, "rename_all").as_deref() {
+        None => Ok(ident.to_string()),
+        Some("camelCase") => {
+            let mut name = String::new();
+            let mut upper = false;
+            for c in ident.to_string().chars() {
+                if c == '_' {
+                    upper = true;
+                } else if upper {
+                    name.extend(c.to_uppercase());
+                    upper = false;
+                } else {
+                    name.push(c);
+                }
+            }
+            Ok(name)
+        }
+        Some(other) => Err(Error::new(
+            old_struct.ident.span(),
+            format!("db_struct does not support `rename_all = \"{}\"`", other),
+        )),
+    }
+}
+
+/// Get the value of a `#[serde(name = "value")]` attribute.
+fn serde_attr(attrs: &[Attribute], name: &str) -> Option<String> {
+    for attr in attrs.iter().filter(|a| a.path.is_ident("serde")) {
+        if let Ok(Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested.iter() {
+                if let NestedMeta::Meta(Meta::NameValue(nv)) = nested {
+                    if !nv.path.is_ident(name) {
+                        continue;
+                    }
+                    if let Lit::Str(s) = &nv.lit {
+                        return Some(s.value());
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Returns true if the type is a `String`.
+fn is_string(ty: &Type) -> bool {
+    if let Type::Path(p) = ty {
+        if let Some(segment) = p.path.segments.last() {
+            return segment.ident == "String";
+        }
+    }
+
+    false
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,11 +476,13 @@ mod tests {
         let ret = do_db_struct(
             quote! {
                 new_name = DuplicatedItem,
-                base_id = AIRTABLE_BASE_ID_CUSTOMER_LEADS,
-                table = AIRTABLE_RFD_TABLE,
+                airtable_table = "items",
+                match_on = ["foo"],
+                airtable_fields = ["bar"],
             }
             .into(),
             quote! {
+                #[table_name = "items"]
                 pub struct Item {
                     pub foo: String,
                     pub bar: String
@@ -117,6 +491,7 @@ mod tests {
             .into(),
         );
         let expected = quote! {
+            #[table_name = "items"]
             pub struct Item {
                 pub foo: String,
                 pub bar: String
@@ -139,32 +514,197 @@ mod tests {
                 pub bar: String
             }
 
+            impl From<DuplicatedItem> for Item {
+                fn from(item: DuplicatedItem) -> Self {
+                    Item {
+                        foo: item.foo,
+                        bar: item.bar
+                    }
+                }
+            }
+
+            impl crate::db::Database {
+                /// Get all the rows from the database.
+                pub fn get_items(&self) -> Result<Vec<DuplicatedItem>, crate::db::DbError> {
+                    use diesel::prelude::*;
+
+                    Ok(crate::schema::items::dsl::items
+                        .order_by(crate::schema::items::dsl::id.desc())
+                        .load::<DuplicatedItem>(&self.conn()?)?)
+                }
+
+                /// Build the query for a page of rows, newest first. If `before` is
+                /// set, only the rows with a lower id are returned. More filters can
+                /// be added to the query before it is loaded.
+                pub(crate) fn items_page_query(
+                    before: Option<i32>,
+                    limit: i64,
+                ) -> crate::schema::items::BoxedQuery<'static, diesel::pg::Pg> {
+                    use diesel::prelude::*;
+
+                    let mut query = crate::schema::items::dsl::items.into_boxed();
+                    if let Some(before) = before {
+                        query = query.filter(crate::schema::items::dsl::id.lt(before));
+                    }
+
+                    query
+                        .order_by(crate::schema::items::dsl::id.desc())
+                        .limit(limit)
+                }
+
+                /// Get a page of rows from the database, newest first. If `before` is
+                /// set, only the rows with a lower id are returned.
+                pub fn get_items_page(
+                    &self,
+                    before: Option<i32>,
+                    limit: i64,
+                ) -> Result<Vec<DuplicatedItem>, crate::db::DbError> {
+                    use diesel::prelude::*;
+
+                    Ok(Self::items_page_query(before, limit)
+                        .load::<DuplicatedItem>(&self.conn()?)?)
+                }
+
+                /// Get a row from the database by its id.
+                pub fn get_item_by_id(
+                    &self,
+                    id: i32,
+                ) -> Result<Option<DuplicatedItem>, crate::db::DbError> {
+                    use diesel::prelude::*;
+
+                    Ok(crate::schema::items::dsl::items
+                        .find(id)
+                        .first::<DuplicatedItem>(&self.conn()?)
+                        .optional()?)
+                }
+
+                /// Get a row from the database by its unique key.
+                pub fn get_item_by_foo(
+                    &self,
+                    foo: &str
+                ) -> Result<Option<DuplicatedItem>, crate::db::DbError> {
+                    use diesel::prelude::*;
+
+                    Ok(crate::schema::items::dsl::items
+                        .filter(crate::schema::items::dsl::foo.eq(foo))
+                        .first::<DuplicatedItem>(&self.conn()?)
+                        .optional()?)
+                }
+
+                /// Create or update a row in the database, matching on its unique key.
+                pub fn upsert_item(
+                    &self,
+                    item: &Item,
+                ) -> Result<DuplicatedItem, crate::db::DbError> {
+                    use diesel::prelude::*;
+
+                    // See if we already have the row in the database.
+                    if let Some(existing) = self.get_item_by_foo(&item.foo)? {
+                        // Update the row.
+                        return Ok(diesel::update(&existing)
+                            .set(item)
+                            .get_result::<DuplicatedItem>(&self.conn()?)?);
+                    }
+
+                    // We don't have the row in the database so we need to add it.
+                    Ok(diesel::insert_into(crate::schema::items::table)
+                        .values(item)
+                        .get_result::<DuplicatedItem>(&self.conn()?)?)
+                }
+
+                /// Delete a row from the database by its id. Returns true if the row
+                /// existed.
+                pub fn delete_item(&self, id: i32) -> Result<bool, crate::db::DbError> {
+                    use diesel::prelude::*;
+
+                    let deleted =
+                        diesel::delete(crate::schema::items::dsl::items.find(id))
+                            .execute(&self.conn()?)?;
+
+                    Ok(deleted > 0)
+                }
+            }
+
             impl DuplicatedItem {
                 /// Push the row to our Airtable workspace.
-                pub async fn push_to_airtable(&self) {
+                pub async fn push_to_airtable(
+                    &self,
+                ) -> Result<(), crate::airtable::SyncError> {
+                    let table = crate::airtable::airtable_table("items")?;
+
                     // Initialize the Airtable client.
-                    let airtable =
-                        Airtable::new(airtable_api_key(), AIRTABLE_BASE_ID_CUSTOMER_LEADS);
+                    let airtable = table.client();
 
                     // Create the record.
                     let record = Record {
                         id: None,
                         created_time: None,
-                        fields: serde_json::to_value(self).unwrap(),
+                        fields: self.clone(),
                     };
 
                     // Send the new record to the Airtable client.
-                    // Batch can only handle 10 at a time.
                     airtable
-                        .create_records(AIRTABLE_RFD_TABLE, vec![record])
+                        .create_records(&table.table, vec![record])
                         .await
-                        .unwrap();
+                        .map_err(|e| e.error)?;
 
                     println!("created new row in airtable: {:?}", self);
+                    Ok(())
+                }
+            }
+
+            impl crate::airtable::AirtableSync for DuplicatedItem {
+                fn airtable_table() -> &'static str {
+                    "items"
+                }
+
+                fn key(&self) -> Vec<String> {
+                    vec![self.foo.to_string()]
+                }
+
+                fn airtable_fields() -> &'static [&'static str] {
+                    &["bar"]
+                }
+
+                fn db_fields() -> &'static [&'static str] {
+                    &["id", "foo"]
+                }
+
+                fn keep_airtable_fields(&mut self, record: &Self) {
+                    self.bar = record.bar.clone();
+                }
+            }
+
+            impl Item {
+                /// Copy the fields Airtable owns from the row we already have, so
+                /// writing this to the database does not undo edits made in
+                /// Airtable.
+                pub fn keep_airtable_fields(&mut self, row: &DuplicatedItem) {
+                    self.bar = row.bar.clone();
                 }
             }
         };
 
         assert_eq!(expected.to_string(), ret.unwrap().to_string());
     }
+
+    #[test]
+    fn test_serde_name() {
+        let item: ItemStruct = syn::parse2(quote! {
+            #[serde(rename_all = "camelCase")]
+            pub struct Item {
+                pub link_to_people: Vec<String>,
+                #[serde(rename = "type")]
+                pub item_type: String,
+            }
+        })
+        .unwrap();
+
+        let names: Vec<String> = item
+            .fields
+            .iter()
+            .map(|f| serde_name(&item, f).unwrap())
+            .collect();
+        assert_eq!(names, vec!["linkToPeople", "type"]);
+    }
 }
@@ -8,6 +8,7 @@
  * ```
  * use std::env;
  *
+ * use gauth::TokenProvider;
  * use sheets::Sheets;
  * use yup_oauth2::{read_service_account_key, ServiceAccountAuthenticator};
  *
@@ -25,15 +26,12 @@
  *         .await
  *         .expect("failed to create authenticator");
  *
- *     // Add the scopes to the secret and get the token.
- *     let token = auth
- *         .token(&["https://www.googleapis.com/auth/spreadsheets"])
- *         .await
- *         .expect("failed to get token");
- *
- *     if token.as_str().is_empty() {
- *         panic!("empty token is not valid");
- *     }
+ *     // Get tokens for the scopes, a new one is fetched before the current
+ *     // one expires.
+ *     let token = TokenProvider::new(
+ *         auth,
+ *         &["https://www.googleapis.com/auth/spreadsheets"],
+ *     );
  *
  *     // Initialize the Google Sheets client.
  *     let sheets_client = Sheets::new(token);
@@ -60,25 +58,25 @@ use std::error;
 use std::fmt;
 use std::sync::Arc;
 
+use gauth::{TokenError, TokenProvider};
 use reqwest::{header, Client, Method, Request, StatusCode, Url};
+use serde::de::{self, DeserializeOwned};
 use serde::{Deserialize, Serialize};
-use yup_oauth2::AccessToken;
 
 /// Endpoint for the Google Sheets API.
 const ENDPOINT: &str = "https://sheets.googleapis.com/v4/";
 
 /// Entrypoint for interacting with the Google Sheets API.
 pub struct Sheets {
-    token: AccessToken,
+    token: TokenProvider,
 
     client: Arc<Client>,
 }
 
 impl Sheets {
-    /// Create a new Sheets client struct. It takes a type that can convert into
-    /// an &str (`String` or `Vec<u8>` for example). As long as the function is
-    /// given a valid API Key and Secret your requests will work.
-    pub fn new(token: AccessToken) -> Self {
+    /// Create a new Sheets client struct. It takes a token provider, which gets
+    /// a new token before the current one expires so the client keeps working.
+    pub fn new(token: TokenProvider) -> Self {
         let client = Client::builder().build();
         match client {
             Ok(c) => Self {
@@ -89,30 +87,28 @@ impl Sheets {
         }
     }
 
-    /// Get the currently set authorization token.
-    pub fn get_token(&self) -> &AccessToken {
+    /// Get the provider of the authorization token.
+    pub fn get_token(&self) -> &TokenProvider {
         &self.token
     }
 
-    fn request<B>(
+    async fn request<B>(
         &self,
         method: Method,
         path: String,
         body: B,
         query: Option<Vec<(&str, String)>>,
-    ) -> Request
+    ) -> Result<Request, APIError>
     where
         B: Serialize,
     {
         let base = Url::parse(ENDPOINT).unwrap();
         let url = base.join(&path).unwrap();
 
-        // Check if the token is expired and panic.
-        if self.token.is_expired() {
-            panic!("token is expired");
-        }
+        // Get the token, this refreshes it if it is about to expire.
+        let token = self.token.token().await?;
 
-        let bt = format!("Bearer {}", self.token.as_str());
+        let bt = format!("Bearer {}", token.access_token);
         let bearer = header::HeaderValue::from_str(&bt).unwrap();
 
         // Set the default headers.
@@ -138,7 +134,7 @@ impl Sheets {
         }
 
         // Build the request.
-        rb.build().unwrap()
+        Ok(rb.build().unwrap())
     }
 
     /// Get values.
@@ -148,16 +144,22 @@ impl Sheets {
         range: String,
     ) -> Result<ValueRange, APIError> {
         // Build the request.
-        let request = self.request(
-            Method::GET,
-            format!("spreadsheets/{}/values/{}", sheet_id.to_string(), range),
-            (),
-            Some(vec![
-                ("valueRenderOption", "FORMATTED_VALUE".to_string()),
-                ("dateTimeRenderOption", "FORMATTED_STRING".to_string()),
-                ("majorDimension", "ROWS".to_string()),
-            ]),
-        );
+        let request = self
+            .request(
+                Method::GET,
+                format!(
+                    "spreadsheets/{}/values/{}",
+                    sheet_id.to_string(),
+                    range
+                ),
+                (),
+                Some(vec![
+                    ("valueRenderOption", "FORMATTED_VALUE".to_string()),
+                    ("dateTimeRenderOption", "FORMATTED_STRING".to_string()),
+                    ("majorDimension", "ROWS".to_string()),
+                ]),
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -182,27 +184,159 @@ impl Sheets {
         value: String,
     ) -> Result<UpdateValuesResponse, APIError> {
         // Build the request.
-        let request = self.request(
-            Method::PUT,
-            format!(
-                "spreadsheets/{}/values/{}",
-                sheet_id.to_string(),
-                range.to_string()
-            ),
-            ValueRange {
-                range: Some(range.to_string()),
-                values: Some(vec![vec![value]]),
-                major_dimension: None,
-            },
-            Some(vec![
-                ("valueInputOption", "USER_ENTERED".to_string()),
-                ("responseValueRenderOption", "FORMATTED_VALUE".to_string()),
-                (
-                    "responseDateTimeRenderOption",
-                    "FORMATTED_STRING".to_string(),
+        let request = self
+            .request(
+                Method::PUT,
+                format!(
+                    "spreadsheets/{}/values/{}",
+                    sheet_id.to_string(),
+                    range.to_string()
                 ),
-            ]),
-        );
+                ValueRange {
+                    range: Some(range.to_string()),
+                    values: Some(vec![vec![value]]),
+                    major_dimension: None,
+                },
+                Some(vec![
+                    ("valueInputOption", "USER_ENTERED".to_string()),
+                    (
+                        "responseValueRenderOption",
+                        "FORMATTED_VALUE".to_string(),
+                    ),
+                    (
+                        "responseDateTimeRenderOption",
+                        "FORMATTED_STRING".to_string(),
+                    ),
+                ]),
+            )
+            .await?;
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        // Try to deserialize the response.
+        Ok(resp.json().await.unwrap())
+    }
+
+    /// Get the values in many ranges at once.
+    pub async fn batch_get(
+        &self,
+        sheet_id: &str,
+        ranges: &[String],
+    ) -> Result<BatchGetValuesResponse, APIError> {
+        let mut query = vec![
+            ("valueRenderOption", "FORMATTED_VALUE".to_string()),
+            ("dateTimeRenderOption", "FORMATTED_STRING".to_string()),
+            ("majorDimension", "ROWS".to_string()),
+        ];
+        for range in ranges {
+            query.push(("ranges", range.to_string()));
+        }
+
+        // Build the request.
+        let request = self
+            .request(
+                Method::GET,
+                format!("spreadsheets/{}/values:batchGet", sheet_id),
+                (),
+                Some(query),
+            )
+            .await?;
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        // Try to deserialize the response.
+        Ok(resp.json().await.unwrap())
+    }
+
+    /// Update the values in many ranges at once. Each range can cover many
+    /// rows and columns.
+    pub async fn batch_update(
+        &self,
+        sheet_id: &str,
+        data: Vec<ValueRange>,
+    ) -> Result<BatchUpdateValuesResponse, APIError> {
+        // Build the request.
+        let request = self
+            .request(
+                Method::POST,
+                format!("spreadsheets/{}/values:batchUpdate", sheet_id),
+                BatchUpdateValuesRequest {
+                    value_input_option: "USER_ENTERED".to_string(),
+                    data,
+                    response_value_render_option: "FORMATTED_VALUE".to_string(),
+                    response_date_time_render_option: "FORMATTED_STRING"
+                        .to_string(),
+                },
+                None,
+            )
+            .await?;
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        // Try to deserialize the response.
+        Ok(resp.json().await.unwrap())
+    }
+
+    /// Append rows after the table in a range. The table is found by looking
+    /// for the last row with data in the range, the new rows are inserted
+    /// after it.
+    pub async fn append_values(
+        &self,
+        sheet_id: &str,
+        range: &str,
+        values: Vec<Vec<String>>,
+    ) -> Result<AppendValuesResponse, APIError> {
+        // Build the request.
+        let request = self
+            .request(
+                Method::POST,
+                format!("spreadsheets/{}/values/{}:append", sheet_id, range),
+                ValueRange {
+                    range: Some(range.to_string()),
+                    values: Some(values),
+                    major_dimension: Some("ROWS".to_string()),
+                },
+                Some(vec![
+                    ("valueInputOption", "USER_ENTERED".to_string()),
+                    ("insertDataOption", "INSERT_ROWS".to_string()),
+                    (
+                        "responseValueRenderOption",
+                        "FORMATTED_VALUE".to_string(),
+                    ),
+                    (
+                        "responseDateTimeRenderOption",
+                        "FORMATTED_STRING".to_string(),
+                    ),
+                ]),
+            )
+            .await?;
 
         let resp = self.client.execute(request).await.unwrap();
         match resp.status() {
@@ -218,6 +352,743 @@ impl Sheets {
         // Try to deserialize the response.
         Ok(resp.json().await.unwrap())
     }
+
+    /// Clear the values in a range, keeping the formatting.
+    pub async fn clear_values(
+        &self,
+        sheet_id: &str,
+        range: &str,
+    ) -> Result<ClearValuesResponse, APIError> {
+        // Build the request.
+        let request = self
+            .request(
+                Method::POST,
+                format!("spreadsheets/{}/values/{}:clear", sheet_id, range),
+                ClearValuesRequest {},
+                None,
+            )
+            .await?;
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        // Try to deserialize the response.
+        Ok(resp.json().await.unwrap())
+    }
+
+    /// Create a spreadsheet with tabs with the given titles. If there are no
+    /// titles, the spreadsheet gets the usual `Sheet1`.
+    pub async fn create_spreadsheet(
+        &self,
+        title: &str,
+        sheets: &[&str],
+    ) -> Result<Spreadsheet, APIError> {
+        // Build the request.
+        let request = self
+            .request(
+                Method::POST,
+                "spreadsheets".to_string(),
+                Spreadsheet {
+                    properties: Some(SpreadsheetProperties {
+                        title: Some(title.to_string()),
+                        ..Default::default()
+                    }),
+                    sheets: sheets
+                        .iter()
+                        .map(|t| Sheet {
+                            properties: Some(SheetProperties {
+                                title: Some(t.to_string()),
+                                ..Default::default()
+                            }),
+                        })
+                        .collect(),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await?;
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        // Try to deserialize the response.
+        Ok(resp.json().await.unwrap())
+    }
+
+    /// Get the metadata of a spreadsheet: its properties and its tabs, but
+    /// not the values in them.
+    pub async fn get_spreadsheet(
+        &self,
+        sheet_id: &str,
+    ) -> Result<Spreadsheet, APIError> {
+        // Build the request.
+        let request = self
+            .request(
+                Method::GET,
+                format!("spreadsheets/{}", sheet_id),
+                (),
+                Some(vec![("includeGridData", "false".to_string())]),
+            )
+            .await?;
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        // Try to deserialize the response.
+        Ok(resp.json().await.unwrap())
+    }
+
+    /// Apply changes to the tabs and formatting of a spreadsheet. The
+    /// requests are applied in order, if one fails none of them are.
+    ///
+    /// This is `batch_update` for the spreadsheet itself, use `batch_update`
+    /// to update values.
+    pub async fn batch_update_spreadsheet(
+        &self,
+        sheet_id: &str,
+        requests: Vec<UpdateRequest>,
+    ) -> Result<BatchUpdateSpreadsheetResponse, APIError> {
+        // Build the request.
+        let request = self
+            .request(
+                Method::POST,
+                format!("spreadsheets/{}:batchUpdate", sheet_id),
+                BatchUpdateSpreadsheetRequest { requests },
+                None,
+            )
+            .await?;
+
+        let resp = self.client.execute(request).await.unwrap();
+        match resp.status() {
+            StatusCode::OK => (),
+            s => {
+                return Err(APIError {
+                    status_code: s,
+                    body: resp.text().await.unwrap(),
+                })
+            }
+        };
+
+        // Try to deserialize the response.
+        Ok(resp.json().await.unwrap())
+    }
+}
+
+/// Get the letters of a column in A1 notation from its index, starting at
+/// zero. `0` is `A`, `25` is `Z` and `26` is `AA`.
+pub fn column_letters(index: usize) -> String {
+    let mut letters: Vec<char> = Default::default();
+    let mut n = index + 1;
+    while n > 0 {
+        let rem = (n - 1) % 26;
+        letters.push((b'A' + rem as u8) as char);
+        n = (n - 1) / 26;
+    }
+
+    letters.iter().rev().collect()
+}
+
+/// A range of cells in A1 notation, like `'Form Responses 1'!A2:AB10`.
+///
+/// Columns and rows are indexes starting at zero, like the indexes into the
+/// values we get back. Build one with the sheet it is on, then narrow it
+/// down:
+///
+/// ```
+/// use sheets::A1Range;
+///
+/// let range = A1Range::sheet("Form Responses 1").cell(27, 4);
+/// assert_eq!(range.to_string(), "'Form Responses 1'!AB5");
+/// ```
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct A1Range {
+    sheet: Option<String>,
+    start: Option<CellRef>,
+    end: Option<CellRef>,
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+struct CellRef {
+    column: Option<usize>,
+    row: Option<usize>,
+}
+
+impl fmt::Display for CellRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(column) = self.column {
+            write!(f, "{}", column_letters(column))?;
+        }
+        if let Some(row) = self.row {
+            write!(f, "{}", row + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl A1Range {
+    /// A range on the first visible sheet. Narrow it down with `cell`,
+    /// `cells`, `columns` or `rows`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// All the cells on a sheet.
+    pub fn sheet<S>(name: S) -> Self
+    where
+        S: ToString,
+    {
+        A1Range {
+            sheet: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// A single cell.
+    pub fn cell(self, column: usize, row: usize) -> Self {
+        self.with(
+            CellRef {
+                column: Some(column),
+                row: Some(row),
+            },
+            None,
+        )
+    }
+
+    /// The cells from `start` to `end`, both `(column, row)`.
+    pub fn cells(self, start: (usize, usize), end: (usize, usize)) -> Self {
+        self.with(
+            CellRef {
+                column: Some(start.0),
+                row: Some(start.1),
+            },
+            Some(CellRef {
+                column: Some(end.0),
+                row: Some(end.1),
+            }),
+        )
+    }
+
+    /// Whole columns, from `start` to `end`.
+    pub fn columns(self, start: usize, end: usize) -> Self {
+        self.with(
+            CellRef {
+                column: Some(start),
+                row: None,
+            },
+            Some(CellRef {
+                column: Some(end),
+                row: None,
+            }),
+        )
+    }
+
+    /// Whole rows, from `start` to `end`.
+    pub fn rows(self, start: usize, end: usize) -> Self {
+        self.with(
+            CellRef {
+                column: None,
+                row: Some(start),
+            },
+            Some(CellRef {
+                column: None,
+                row: Some(end),
+            }),
+        )
+    }
+
+    fn with(mut self, start: CellRef, end: Option<CellRef>) -> Self {
+        self.start = Some(start);
+        self.end = end;
+        self
+    }
+}
+
+impl fmt::Display for A1Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(sheet) = &self.sheet {
+            write!(f, "{}", quote_sheet_name(sheet))?;
+            if self.start.is_some() {
+                write!(f, "!")?;
+            }
+        }
+        if let Some(start) = &self.start {
+            write!(f, "{}", start)?;
+        }
+        if let Some(end) = &self.end {
+            write!(f, ":{}", end)?;
+        }
+        Ok(())
+    }
+}
+
+/// Quote a sheet name for A1 notation if it needs it, for example if it has
+/// spaces or looks like a cell. Quotes in the name are doubled.
+fn quote_sheet_name(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    // Names like `A1`, `AB12` or `R1C1` would be read as a cell.
+    let digits = name.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    let looks_like_cell = (name.len() - digits.len() <= 3
+        && !digits.is_empty()
+        && digits.chars().all(|c| c.is_ascii_digit()))
+        || is_r1c1(name);
+
+    if plain && !looks_like_cell {
+        return name.to_string();
+    }
+
+    format!("'{}'", name.replace('\'', "''"))
+}
+
+/// Returns true if the name is a cell in R1C1 notation, like `R1C1`.
+fn is_r1c1(name: &str) -> bool {
+    let rest = match name.strip_prefix('R') {
+        Some(r) => r,
+        None => return false,
+    };
+    let c = match rest.find('C') {
+        Some(c) => c,
+        None => return false,
+    };
+    let (row, column) = (&rest[..c], &rest[c + 1..]);
+
+    !row.is_empty()
+        && !column.is_empty()
+        && row.chars().all(|c| c.is_ascii_digit())
+        && column.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Which columns hold the fields of a row type, by their header.
+///
+/// A field is found in the column whose header is one of its aliases, or
+/// its name if it has none. Headers are compared ignoring case and the
+/// whitespace around them. For headers that are long or change, like the
+/// questions of a Google Form, a field can instead be found in the first
+/// column whose header contains one of its phrases.
+///
+/// ```
+/// use serde::Deserialize;
+/// use sheets::{read_rows, HeaderMap};
+///
+/// #[derive(Debug, Deserialize)]
+/// struct Signup {
+///     name: String,
+///     email: String,
+///     #[serde(default)]
+///     newsletter: bool,
+/// }
+///
+/// let headers = HeaderMap::new()
+///     .field("name", &["Full name"])
+///     .field("email", &["Email address"])
+///     .field("newsletter", &["Sign me up"]);
+/// let values = vec![
+///     vec!["Full name".to_string(), "Email address".to_string()],
+///     vec!["Ada".to_string(), "ada@example.com".to_string()],
+/// ];
+///
+/// let rows = read_rows::<Signup>(&values, &headers);
+/// assert_eq!(rows[0].as_ref().unwrap().email, "ada@example.com");
+/// ```
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct HeaderMap {
+    aliases: Vec<(String, Vec<String>)>,
+    phrases: Vec<(String, Vec<String>)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the headers of the columns a field can be in.
+    pub fn field(mut self, name: &str, aliases: &[&str]) -> Self {
+        self.aliases.push((
+            name.to_string(),
+            aliases.iter().map(|a| a.to_string()).collect(),
+        ));
+        self
+    }
+
+    /// Set phrases the header of the column a field is in contains. These
+    /// are compared ignoring case.
+    pub fn field_containing(mut self, name: &str, phrases: &[&str]) -> Self {
+        self.phrases.push((
+            name.to_string(),
+            phrases.iter().map(|p| p.to_lowercase()).collect(),
+        ));
+        self
+    }
+
+    /// Get the index of the column for a field in the header row.
+    pub fn column(&self, field: &str, header: &[String]) -> Option<usize> {
+        if let Some((_, phrases)) =
+            self.phrases.iter().find(|(n, _)| n == field)
+        {
+            return header.iter().position(|h| {
+                let h = h.to_lowercase();
+                phrases.iter().any(|p| h.contains(p.as_str()))
+            });
+        }
+
+        let aliases: Vec<&str> =
+            match self.aliases.iter().find(|(n, _)| n == field) {
+                Some((_, a)) if !a.is_empty() => {
+                    a.iter().map(|a| a.as_str()).collect()
+                }
+                _ => vec![field],
+            };
+
+        header.iter().position(|h| {
+            aliases
+                .iter()
+                .any(|a| h.trim().to_lowercase() == a.trim().to_lowercase())
+        })
+    }
+}
+
+/// Read typed rows from the values of a sheet. The first row is the header,
+/// the rest are deserialized into `T`, which must be a struct.
+///
+/// Empty cells and columns that are not in the sheet are left out, so
+/// those fields get their `#[serde(default)]` or are `None` if they are an
+/// `Option`. Cells are parsed into the type of their field, like numbers and
+/// `TRUE` or `FALSE` for bools, and a list is read from values separated by
+/// commas. A row that does not fit `T` does not fail the rest, it comes back
+/// as an error for that row.
+pub fn read_rows<T: DeserializeOwned>(
+    values: &[Vec<String>],
+    headers: &HeaderMap,
+) -> Vec<Result<T, RowError>> {
+    let header = match values.first() {
+        Some(h) => h,
+        None => return vec![],
+    };
+
+    values
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(index, row)| {
+            T::deserialize(RowDeserializer {
+                header,
+                row,
+                headers,
+            })
+            .map_err(|e| RowError {
+                row: index,
+                error: e.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Write typed rows as the values of a sheet, with the cells in the order
+/// of the columns in the header. This is the reverse of `read_rows`.
+///
+/// Fields that are not in the header are left out. Columns in the header
+/// that no field maps to are written as empty cells, so writing these rows
+/// over a whole row of the sheet clears those columns. If the sheet has
+/// columns you fill in by hand, only write the ranges of the mapped columns.
+///
+/// Bools are written as `TRUE` or `FALSE`, `None` as an empty cell and
+/// lists as their values separated by commas.
+pub fn write_rows<T: Serialize>(
+    header: &[String],
+    headers: &HeaderMap,
+    rows: &[T],
+) -> Result<Vec<Vec<String>>, RowError> {
+    let mut values: Vec<Vec<String>> = Default::default();
+
+    for (index, row) in rows.iter().enumerate() {
+        let error = |error: String| RowError {
+            // Count the header, like `read_rows` does.
+            row: index + 1,
+            error,
+        };
+
+        let fields = match serde_json::to_value(row) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            Ok(_) => return Err(error("rows must be structs".to_string())),
+            Err(e) => return Err(error(e.to_string())),
+        };
+
+        let mut cells = vec![String::new(); header.len()];
+        for (field, value) in fields {
+            if let Some(column) = headers.column(&field, header) {
+                cells[column] = cell_value(&value).ok_or_else(|| {
+                    error(format!("`{}` is not a cell", field))
+                })?;
+            }
+        }
+        values.push(cells);
+    }
+
+    Ok(values)
+}
+
+/// Get the text of a cell for a value, if the value fits in a cell.
+fn cell_value(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Null => Some(String::new()),
+        serde_json::Value::Bool(b) => {
+            Some(if *b { "TRUE" } else { "FALSE" }.to_string())
+        }
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::String(s) => Some(s.to_string()),
+        serde_json::Value::Array(a) => Some(
+            a.iter()
+                .map(cell_value)
+                .collect::<Option<Vec<String>>>()?
+                .join(", "),
+        ),
+        serde_json::Value::Object(_) => None,
+    }
+}
+
+/// Error returned for a row that does not fit the type it is read into.
+pub struct RowError {
+    /// The index of the row in the values, the header is row 0.
+    pub row: usize,
+    /// The error, this names the field that did not fit.
+    pub error: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RowError: row -> {}, error -> {}", self.row, self.error)
+    }
+}
+
+impl fmt::Debug for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl error::Error for RowError {}
+
+/// Deserializes a row into a struct, with a field for each column we found.
+struct RowDeserializer<'a> {
+    header: &'a [String],
+    row: &'a [String],
+    headers: &'a HeaderMap,
+}
+
+impl<'de, 'a> de::Deserializer<'de> for RowDeserializer<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let cells = fields.iter().filter_map(|field| {
+            let column = self.headers.column(field, self.header)?;
+            let cell = self.row.get(column)?.trim();
+            if cell.is_empty() {
+                None
+            } else {
+                Some((*field, CellDeserializer(cell.to_string())))
+            }
+        });
+
+        visitor.visit_map(de::value::MapDeserializer::new(cells))
+    }
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        Err(de::Error::custom("rows can only be read into structs"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Deserializes the text of a cell into the type of its field.
+struct CellDeserializer(String);
+
+impl CellDeserializer {
+    fn parse<T: std::str::FromStr>(&self) -> Result<T, de::value::Error> {
+        without_thousands(&self.0)
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| {
+                de::Error::custom(format!("invalid value `{}`", self.0))
+            })
+    }
+}
+
+/// Remove the thousands separators from a number, like `1,000`. Commas
+/// anywhere else, like `1,5`, are not a number we understand.
+fn without_thousands(s: &str) -> Option<String> {
+    if !s.contains(',') {
+        return Some(s.to_string());
+    }
+
+    let (sign, rest) = match s.chars().next() {
+        Some(c @ '-') | Some(c @ '+') => (c.to_string(), &s[1..]),
+        _ => (String::new(), s),
+    };
+    let (integer, fraction) = match rest.find('.') {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+
+    let groups: Vec<&str> = integer.split(',').collect();
+    let digits = |g: &str| g.chars().all(|c| c.is_ascii_digit());
+    let first = groups[0];
+    if first.is_empty()
+        || first.len() > 3
+        || !digits(first)
+        || groups[1..].iter().any(|g| g.len() != 3 || !digits(g))
+        || fraction.contains(',')
+    {
+        return None;
+    }
+
+    Some(format!("{}{}{}", sign, groups.concat(), fraction))
+}
+
+impl<'de> de::IntoDeserializer<'de, de::value::Error> for CellDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_number {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: de::Visitor<'de>,
+            {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for CellDeserializer {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.0.trim().to_lowercase().as_str() {
+            "true" => visitor.visit_bool(true),
+            "false" => visitor.visit_bool(false),
+            _ => Err(de::Error::custom(format!("invalid bool `{}`", self.0))),
+        }
+    }
+
+    deserialize_number! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        if self.0.trim().is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let values = self
+            .0
+            .split(',')
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| CellDeserializer(v.to_string()));
+
+        visitor.visit_seq(de::value::SeqDeserializer::new(values))
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_enum(de::IntoDeserializer::into_deserializer(self.0))
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct tuple
+        tuple_struct map struct identifier ignored_any
+    }
 }
 
 /// Error type returned by our library.
@@ -256,6 +1127,16 @@ impl error::Error for APIError {
     }
 }
 
+impl From<TokenError> for APIError {
+    fn from(e: TokenError) -> Self {
+        // We could not get a token so the request is not authorized.
+        APIError {
+            status_code: StatusCode::UNAUTHORIZED,
+            body: e.to_string(),
+        }
+    }
+}
+
 /// A range of values.
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct ValueRange {
@@ -317,3 +1198,726 @@ pub struct UpdateValuesResponse {
     #[serde(rename = "updatedCells")]
     pub updated_cells: Option<i32>,
 }
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchUpdateValuesRequest {
+    value_input_option: String,
+    data: Vec<ValueRange>,
+    response_value_render_option: String,
+    response_date_time_render_option: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct ClearValuesRequest {}
+
+/// The response returned from getting the values in many ranges.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct BatchGetValuesResponse {
+    /// The spreadsheet the values were read from.
+    #[serde(rename = "spreadsheetId")]
+    pub spreadsheet_id: Option<String>,
+    /// The values, in the same order as the ranges that were requested.
+    #[serde(default, rename = "valueRanges")]
+    pub value_ranges: Vec<ValueRange>,
+}
+
+/// The response returned from updating the values in many ranges.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct BatchUpdateValuesResponse {
+    /// The spreadsheet the updates were applied to.
+    #[serde(rename = "spreadsheetId")]
+    pub spreadsheet_id: Option<String>,
+    /// The total number of rows where at least one cell in the row was
+    /// updated.
+    #[serde(rename = "totalUpdatedRows")]
+    pub total_updated_rows: Option<i32>,
+    /// The total number of columns where at least one cell in the column was
+    /// updated.
+    #[serde(rename = "totalUpdatedColumns")]
+    pub total_updated_columns: Option<i32>,
+    /// The total number of cells updated.
+    #[serde(rename = "totalUpdatedCells")]
+    pub total_updated_cells: Option<i32>,
+    /// The total number of sheets where at least one cell in the sheet was
+    /// updated.
+    #[serde(rename = "totalUpdatedSheets")]
+    pub total_updated_sheets: Option<i32>,
+    /// One response for each range that was updated, in the same order.
+    #[serde(default)]
+    pub responses: Vec<UpdateValuesResponse>,
+}
+
+/// The response returned from appending values.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct AppendValuesResponse {
+    /// The spreadsheet the updates were applied to.
+    #[serde(rename = "spreadsheetId")]
+    pub spreadsheet_id: Option<String>,
+    /// The range (in A1 notation) of the table the values were appended to,
+    /// before the values were appended. Empty if no table was found.
+    #[serde(rename = "tableRange")]
+    pub table_range: Option<String>,
+    /// Information about the updates that were applied.
+    pub updates: Option<UpdateValuesResponse>,
+}
+
+/// The response returned from clearing values.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct ClearValuesResponse {
+    /// The spreadsheet the updates were applied to.
+    #[serde(rename = "spreadsheetId")]
+    pub spreadsheet_id: Option<String>,
+    /// The range (in A1 notation) that was cleared.
+    #[serde(rename = "clearedRange")]
+    pub cleared_range: Option<String>,
+}
+
+/// A spreadsheet and its tabs.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Spreadsheet {
+    /// The id of the spreadsheet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spreadsheet_id: Option<String>,
+    /// The properties of the spreadsheet, like its title.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<SpreadsheetProperties>,
+    /// The tabs of the spreadsheet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sheets: Vec<Sheet>,
+    /// The url of the spreadsheet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spreadsheet_url: Option<String>,
+}
+
+impl Spreadsheet {
+    /// Get a tab by its title.
+    pub fn sheet(&self, title: &str) -> Option<&SheetProperties> {
+        self.sheets
+            .iter()
+            .filter_map(|s| s.properties.as_ref())
+            .find(|p| p.title.as_deref() == Some(title))
+    }
+}
+
+/// The properties of a spreadsheet.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpreadsheetProperties {
+    /// The title of the spreadsheet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The locale of the spreadsheet, like `en_US`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// The time zone of the spreadsheet, like `America/New_York`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
+}
+
+/// A tab in a spreadsheet.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sheet {
+    /// The properties of the tab.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub properties: Option<SheetProperties>,
+}
+
+/// The properties of a tab.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetProperties {
+    /// The id of the tab, this is the `gid` in its url.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sheet_id: Option<i64>,
+    /// The title of the tab.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The position of the tab, starting at zero.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<i32>,
+    /// The type of the tab, `GRID` for a sheet of cells.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sheet_type: Option<String>,
+    /// The size of the grid and the frozen rows and columns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid_properties: Option<GridProperties>,
+    /// Whether the tab is hidden.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hidden: Option<bool>,
+}
+
+/// The size of the grid of a tab and the frozen rows and columns.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GridProperties {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub row_count: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column_count: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frozen_row_count: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frozen_column_count: Option<i32>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct BatchUpdateSpreadsheetRequest {
+    requests: Vec<UpdateRequest>,
+}
+
+/// A change to a spreadsheet, sent with `batch_update_spreadsheet`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UpdateRequest {
+    AddSheet(AddSheetRequest),
+    DeleteSheet(DeleteSheetRequest),
+    UpdateSheetProperties(UpdateSheetPropertiesRequest),
+    AddConditionalFormatRule(AddConditionalFormatRuleRequest),
+    SetDataValidation(SetDataValidationRequest),
+}
+
+impl UpdateRequest {
+    /// Add a tab at the end.
+    pub fn add_sheet(title: &str) -> Self {
+        UpdateRequest::AddSheet(AddSheetRequest {
+            properties: SheetProperties {
+                title: Some(title.to_string()),
+                ..Default::default()
+            },
+        })
+    }
+
+    /// Delete a tab.
+    pub fn delete_sheet(sheet_id: i64) -> Self {
+        UpdateRequest::DeleteSheet(DeleteSheetRequest { sheet_id })
+    }
+
+    /// Rename a tab.
+    pub fn rename_sheet(sheet_id: i64, title: &str) -> Self {
+        UpdateRequest::UpdateSheetProperties(UpdateSheetPropertiesRequest {
+            properties: SheetProperties {
+                sheet_id: Some(sheet_id),
+                title: Some(title.to_string()),
+                ..Default::default()
+            },
+            fields: "title".to_string(),
+        })
+    }
+
+    /// Freeze the first rows of a tab, like its header. Zero unfreezes them.
+    pub fn freeze_rows(sheet_id: i64, rows: i32) -> Self {
+        UpdateRequest::UpdateSheetProperties(UpdateSheetPropertiesRequest {
+            properties: SheetProperties {
+                sheet_id: Some(sheet_id),
+                grid_properties: Some(GridProperties {
+                    frozen_row_count: Some(rows),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            fields: "gridProperties.frozenRowCount".to_string(),
+        })
+    }
+
+    /// Format the cells in a range that match a condition.
+    pub fn add_conditional_format(
+        range: GridRange,
+        condition: BooleanCondition,
+        format: CellFormat,
+    ) -> Self {
+        UpdateRequest::AddConditionalFormatRule(
+            AddConditionalFormatRuleRequest {
+                rule: ConditionalFormatRule {
+                    ranges: vec![range],
+                    boolean_rule: BooleanRule { condition, format },
+                },
+                index: 0,
+            },
+        )
+    }
+
+    /// Only allow values that match a condition in the cells in a range.
+    /// If `strict` is false, other values are allowed but flagged.
+    pub fn set_data_validation(
+        range: GridRange,
+        condition: BooleanCondition,
+        strict: bool,
+    ) -> Self {
+        UpdateRequest::SetDataValidation(SetDataValidationRequest {
+            range,
+            rule: Some(DataValidationRule {
+                condition,
+                input_message: None,
+                strict,
+                show_custom_ui: true,
+            }),
+        })
+    }
+}
+
+/// Add a tab.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AddSheetRequest {
+    pub properties: SheetProperties,
+}
+
+/// Delete a tab.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteSheetRequest {
+    pub sheet_id: i64,
+}
+
+/// Update the properties of a tab.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UpdateSheetPropertiesRequest {
+    /// The properties to set, with the `sheet_id` of the tab.
+    pub properties: SheetProperties,
+    /// The properties to update, like `title`. The rest are left alone.
+    pub fields: String,
+}
+
+/// Add a conditional format rule.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AddConditionalFormatRuleRequest {
+    pub rule: ConditionalFormatRule,
+    /// Where to put the rule in the list of rules, they are applied in
+    /// order.
+    pub index: i32,
+}
+
+/// A rule that formats the cells in some ranges that match a condition.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionalFormatRule {
+    pub ranges: Vec<GridRange>,
+    pub boolean_rule: BooleanRule,
+}
+
+/// A format to apply when a condition is true.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BooleanRule {
+    pub condition: BooleanCondition,
+    pub format: CellFormat,
+}
+
+/// Set or clear the data validation of the cells in a range.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SetDataValidationRequest {
+    pub range: GridRange,
+    /// The rule to set, or `None` to clear it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<DataValidationRule>,
+}
+
+/// A rule for the values allowed in a cell.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataValidationRule {
+    pub condition: BooleanCondition,
+    /// A message shown when the cell is selected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_message: Option<String>,
+    /// Whether values that do not match are rejected.
+    #[serde(default)]
+    pub strict: bool,
+    /// Whether to show a dropdown for `ONE_OF_LIST` conditions.
+    #[serde(default, rename = "showCustomUi")]
+    pub show_custom_ui: bool,
+}
+
+/// A condition on the value of a cell.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BooleanCondition {
+    /// The type of condition, like `TEXT_EQ`, `ONE_OF_LIST` or
+    /// `CUSTOM_FORMULA`.
+    #[serde(rename = "type")]
+    pub condition_type: String,
+    /// The values of the condition, how many depends on its type.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<ConditionValue>,
+}
+
+impl BooleanCondition {
+    /// A condition with a type and its values.
+    pub fn new(condition_type: &str, values: &[&str]) -> Self {
+        BooleanCondition {
+            condition_type: condition_type.to_string(),
+            values: values
+                .iter()
+                .map(|v| ConditionValue {
+                    user_entered_value: v.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// The cell is equal to the text.
+    pub fn text_eq(text: &str) -> Self {
+        BooleanCondition::new("TEXT_EQ", &[text])
+    }
+
+    /// The cell is one of the values, this shows a dropdown in data
+    /// validation.
+    pub fn one_of(values: &[&str]) -> Self {
+        BooleanCondition::new("ONE_OF_LIST", values)
+    }
+
+    /// The formula is true for the cell, like `=$B2="Hired"`.
+    pub fn custom_formula(formula: &str) -> Self {
+        BooleanCondition::new("CUSTOM_FORMULA", &[formula])
+    }
+}
+
+/// A value in a condition, as if it was typed into a cell.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionValue {
+    pub user_entered_value: String,
+}
+
+/// A range of cells on a tab. Indexes start at zero, the start is included
+/// and the end is not. A missing start or end means the range is unbounded
+/// on that side.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GridRange {
+    pub sheet_id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_row_index: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_row_index: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_column_index: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_column_index: Option<i32>,
+}
+
+impl GridRange {
+    /// Whole columns, from `start` up to but not including `end`, below the
+    /// header rows.
+    pub fn columns(
+        sheet_id: i64,
+        start: i32,
+        end: i32,
+        header_rows: i32,
+    ) -> Self {
+        GridRange {
+            sheet_id,
+            start_row_index: Some(header_rows),
+            start_column_index: Some(start),
+            end_column_index: Some(end),
+            ..Default::default()
+        }
+    }
+}
+
+/// The format of a cell.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellFormat {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background_color: Option<Color>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_format: Option<TextFormat>,
+}
+
+/// The format of the text in a cell.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextFormat {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foreground_color: Option<Color>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<bool>,
+}
+
+/// A color, each part is between 0 and 1.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Color {
+    #[serde(default)]
+    pub red: f32,
+    #[serde(default)]
+    pub green: f32,
+    #[serde(default)]
+    pub blue: f32,
+}
+
+impl Color {
+    /// A color from its red, green and blue parts between 0 and 255.
+    pub fn rgb(red: u8, green: u8, blue: u8) -> Self {
+        Color {
+            red: f32::from(red) / 255.0,
+            green: f32::from(green) / 255.0,
+            blue: f32::from(blue) / 255.0,
+        }
+    }
+}
+
+/// The response returned from changing a spreadsheet.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchUpdateSpreadsheetResponse {
+    /// The spreadsheet the changes were applied to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spreadsheet_id: Option<String>,
+    /// One reply for each request, in the same order. Most are empty.
+    #[serde(default)]
+    pub replies: Vec<UpdateReply>,
+}
+
+/// The reply to a request to change a spreadsheet.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateReply {
+    /// The tab that was added, with its id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub add_sheet: Option<AddSheetRequest>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        column_letters, read_rows, without_thousands, write_rows, A1Range,
+        BooleanCondition, CellFormat, Color, GridRange, HeaderMap,
+        UpdateRequest,
+    };
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Applicant {
+        name: String,
+        email: String,
+        #[serde(default)]
+        sent_email_received: bool,
+        #[serde(default)]
+        years: Option<u32>,
+        #[serde(default)]
+        values: Vec<String>,
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_column_letters() {
+        assert_eq!(column_letters(0), "A");
+        assert_eq!(column_letters(25), "Z");
+        assert_eq!(column_letters(26), "AA");
+        assert_eq!(column_letters(27), "AB");
+        assert_eq!(column_letters(51), "AZ");
+        assert_eq!(column_letters(52), "BA");
+        assert_eq!(column_letters(701), "ZZ");
+        assert_eq!(column_letters(702), "AAA");
+    }
+
+    #[test]
+    fn test_a1_range() {
+        assert_eq!(A1Range::new().cell(17, 4).to_string(), "R5");
+        assert_eq!(A1Range::sheet("Sheet1").to_string(), "Sheet1");
+        assert_eq!(
+            A1Range::sheet("Form Responses 1")
+                .cells((0, 0), (28, 999))
+                .to_string(),
+            "'Form Responses 1'!A1:AC1000"
+        );
+        assert_eq!(
+            A1Range::sheet("Sheet1").columns(0, 26).to_string(),
+            "Sheet1!A:AA"
+        );
+        assert_eq!(
+            A1Range::sheet("Sheet1").rows(1, 9).to_string(),
+            "Sheet1!2:10"
+        );
+
+        // Names that look like cells or have quotes need quoting.
+        assert_eq!(A1Range::sheet("AB12").cell(0, 0).to_string(), "'AB12'!A1");
+        assert_eq!(
+            A1Range::sheet("Hiring's sheet").cell(0, 0).to_string(),
+            "'Hiring''s sheet'!A1"
+        );
+        assert_eq!(A1Range::sheet("R1C1").to_string(), "'R1C1'");
+        assert_eq!(A1Range::sheet("1st").to_string(), "'1st'");
+    }
+
+    #[test]
+    fn test_read_write_rows() {
+        let headers = HeaderMap::new()
+            .field("name", &["Name"])
+            .field("email", &["Email address", "Email"])
+            .field("sent_email_received", &["Sent email"])
+            .field("years", &["Years of experience"]);
+        let values = vec![
+            strings(&[
+                "Timestamp",
+                " email ",
+                "Name",
+                "Years of experience",
+                "values",
+            ]),
+            strings(&["1/1/2020", "ada@example.com", "Ada", "1,200", "a, b"]),
+            // Trailing empty cells are left out by the API.
+            strings(&["1/2/2020", "grace@example.com", "Grace"]),
+            strings(&["1/3/2020", "", "Nobody", "lots"]),
+            strings(&["1/4/2020", "", "Nobody", "1,5"]),
+        ];
+
+        let rows = read_rows::<Applicant>(&values, &headers);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(
+            rows[0].as_ref().unwrap(),
+            &Applicant {
+                name: "Ada".to_string(),
+                email: "ada@example.com".to_string(),
+                // The column is not in the sheet.
+                sent_email_received: false,
+                years: Some(1200),
+                values: strings(&["a", "b"]),
+            }
+        );
+        assert_eq!(rows[1].as_ref().unwrap().years, None);
+
+        let error = rows[2].as_ref().unwrap_err();
+        assert_eq!(error.row, 3);
+        assert!(error.error.contains("invalid value `lots`"));
+        // Only thousands separators are taken out of numbers.
+        let error = rows[3].as_ref().unwrap_err();
+        assert_eq!(error.row, 4);
+        assert!(error.error.contains("invalid value `1,5`"));
+
+        let rows: Vec<Applicant> =
+            rows.into_iter().filter_map(|r| r.ok()).collect();
+        let written = write_rows(&values[0], &headers, &rows).unwrap();
+        assert_eq!(
+            written,
+            vec![
+                strings(&["", "ada@example.com", "Ada", "1200", "a, b"]),
+                strings(&["", "grace@example.com", "Grace", "", ""]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_without_thousands() {
+        assert_eq!(without_thousands("1200").as_deref(), Some("1200"));
+        assert_eq!(without_thousands("1,200").as_deref(), Some("1200"));
+        assert_eq!(
+            without_thousands("-12,345,678.5").as_deref(),
+            Some("-12345678.5")
+        );
+        assert_eq!(without_thousands("1,5"), None);
+        assert_eq!(without_thousands("1,2000"), None);
+        assert_eq!(without_thousands(",200"), None);
+        assert_eq!(without_thousands("1,200.5,0"), None);
+    }
+
+    #[test]
+    fn test_header_map_phrases() {
+        let headers = HeaderMap::new()
+            .field("email", &["Email address"])
+            .field_containing("status", &["status"])
+            .field_containing("value_in_tension_1", &["Value in tension [1"])
+            .field_containing("linkedin", &["linkedin"]);
+        let header = strings(&[
+            "Timestamp",
+            "Email Address",
+            "What is your value in tension [2]?",
+            "What is your value in tension [1]?",
+            "Status of the application",
+        ]);
+
+        assert_eq!(headers.column("email", &header), Some(1));
+        assert_eq!(headers.column("status", &header), Some(4));
+        assert_eq!(headers.column("value_in_tension_1", &header), Some(3));
+        assert_eq!(headers.column("linkedin", &header), None);
+    }
+
+    #[test]
+    fn test_update_requests() {
+        let requests = vec![
+            UpdateRequest::add_sheet("Form Responses 1"),
+            UpdateRequest::rename_sheet(1, "Archive"),
+            UpdateRequest::freeze_rows(0, 1),
+            UpdateRequest::set_data_validation(
+                GridRange::columns(0, 2, 3, 1),
+                BooleanCondition::one_of(&["Onsite", "Hired"]),
+                true,
+            ),
+            UpdateRequest::add_conditional_format(
+                GridRange::columns(0, 0, 5, 1),
+                BooleanCondition::custom_formula("=$C2=\"Hired\""),
+                CellFormat {
+                    background_color: Some(Color::rgb(255, 255, 255)),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        assert_eq!(
+            serde_json::to_value(&requests).unwrap(),
+            serde_json::json!([
+                {"addSheet": {"properties": {"title": "Form Responses 1"}}},
+                {"updateSheetProperties": {
+                    "properties": {"sheetId": 1, "title": "Archive"},
+                    "fields": "title"
+                }},
+                {"updateSheetProperties": {
+                    "properties": {
+                        "sheetId": 0,
+                        "gridProperties": {"frozenRowCount": 1}
+                    },
+                    "fields": "gridProperties.frozenRowCount"
+                }},
+                {"setDataValidation": {
+                    "range": {
+                        "sheetId": 0,
+                        "startRowIndex": 1,
+                        "startColumnIndex": 2,
+                        "endColumnIndex": 3
+                    },
+                    "rule": {
+                        "condition": {
+                            "type": "ONE_OF_LIST",
+                            "values": [
+                                {"userEnteredValue": "Onsite"},
+                                {"userEnteredValue": "Hired"}
+                            ]
+                        },
+                        "strict": true,
+                        "showCustomUi": true
+                    }
+                }},
+                {"addConditionalFormatRule": {
+                    "rule": {
+                        "ranges": [{
+                            "sheetId": 0,
+                            "startRowIndex": 1,
+                            "startColumnIndex": 0,
+                            "endColumnIndex": 5
+                        }],
+                        "booleanRule": {
+                            "condition": {
+                                "type": "CUSTOM_FORMULA",
+                                "values": [{"userEnteredValue": "=$C2=\"Hired\""}]
+                            },
+                            "format": {
+                                "backgroundColor": {
+                                    "red": 1.0, "green": 1.0, "blue": 1.0
+                                }
+                            }
+                        }
+                    },
+                    "index": 0
+                }}
+            ])
+        );
+    }
+}